@@ -133,6 +133,29 @@ COURSE_MANAGEMENT_ID=$(dfx canister id course_management --network $NETWORK)
 CERTIFICATION_SYSTEM_ID=$(dfx canister id certification_system --network $NETWORK)
 GOVERNANCE_ID=$(dfx canister id governance --network $NETWORK)
 
+# Allow inter-canister moderation calls
+print_status "Configuring trusted canisters..."
+dfx canister call user_management set_trusted_canisters "(vec { principal \"$COURSE_MANAGEMENT_ID\" })" --network $NETWORK
+dfx canister call course_management set_trusted_canisters "(vec { principal \"$GOVERNANCE_ID\" })" --network $NETWORK
+print_success "Trusted canisters configured"
+
+# Governance upgrades the other canisters through approved upgrade proposals
+print_status "Adding governance as a controller..."
+for CANISTER in user_management course_management certification_system; do
+    dfx canister update-settings $CANISTER --add-controller "$GOVERNANCE_ID" --network $NETWORK
+done
+print_success "Governance added as a controller"
+
+# Paid enrollment and proposal fees need an ICRC-2 ledger; pass its canister id to enable them
+if [ -n "$LEDGER_CANISTER_ID" ]; then
+    print_status "Configuring payment ledger..."
+    dfx canister call course_management set_payment_config "(record { ledger_canister_id = opt principal \"$LEDGER_CANISTER_ID\"; platform_fee_bps = 1000 : nat16; platform_account = null; refund_window_days = 14 : nat32; refund_max_completion = 20.0 : float32 })" --network $NETWORK
+    dfx canister call governance set_ledger "(opt principal \"$LEDGER_CANISTER_ID\")" --network $NETWORK
+    print_success "Payment ledger configured"
+else
+    print_warning "LEDGER_CANISTER_ID not set; only free courses can be enrolled in and proposal fees are waived"
+fi
+
 echo ""
 echo "🎉 Deployment completed successfully!"
 echo "====================================="
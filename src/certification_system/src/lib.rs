@@ -1,9 +1,12 @@
 use candid::{candid_method, CandidType, Deserialize, Principal};
 use ic_cdk::api::time;
-use ic_cdk::{caller, export_candid, query, update};
+use ic_cdk::{caller, export_candid, post_upgrade, pre_upgrade, query, update};
 use serde::Serialize;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use shared::freeze;
+use shared::pagination::{paginate, InvalidCursor, Page};
+use shared::stable;
 
 // Simple types for demo
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
@@ -11,6 +14,7 @@ pub struct Certification {
     pub id: String,
     pub user_id: Principal,
     pub course_id: String,
+    pub course_version: u32, // syllabus version the certificate was earned on
     pub title: String,
     pub description: String,
     pub issued_at: u64,
@@ -25,14 +29,50 @@ pub enum ApiError {
     InvalidInput(String),
     AlreadyExists(String),
     InsufficientPermissions,
+    InternalError(String),
+    Frozen { until: u64 }, // governance guardians froze the canister's mutating endpoints
 }
 
 pub type Result<T> = std::result::Result<T, ApiError>;
 
+impl From<InvalidCursor> for ApiError {
+    fn from(_: InvalidCursor) -> Self {
+        ApiError::InvalidInput("Invalid cursor".to_string())
+    }
+}
+
 // Simple in-memory storage for demo
 thread_local! {
     static CERTIFICATIONS: RefCell<HashMap<String, Certification>> = RefCell::new(HashMap::new());
-    static CERTIFICATION_COUNTER: RefCell<u64> = RefCell::new(0);
+    static CERTIFICATION_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+}
+
+// Stable Memory Functions
+
+// Everything kept across upgrades
+#[derive(CandidType, Deserialize)]
+struct StableState {
+    certifications: HashMap<String, Certification>,
+    certification_counter: u64,
+    frozen_until: Option<u64>,
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let state = StableState {
+        certifications: CERTIFICATIONS.with(RefCell::take),
+        certification_counter: CERTIFICATION_COUNTER.with(RefCell::take),
+        frozen_until: freeze::frozen_until(get_current_time()),
+    };
+    stable::save(state);
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    let Some(state) = stable::restore::<StableState>() else { return };
+    CERTIFICATIONS.with(|certifications| *certifications.borrow_mut() = state.certifications);
+    CERTIFICATION_COUNTER.with(|counter| *counter.borrow_mut() = state.certification_counter);
+    freeze::restore(state.frozen_until);
 }
 
 // Helper functions
@@ -40,6 +80,11 @@ fn get_current_time() -> u64 {
     time()
 }
 
+// Certifications are listed in the order they were issued
+fn certification_page_key(cert: &Certification) -> String {
+    format!("{:020}:{}", cert.issued_at, cert.id)
+}
+
 fn generate_certification_id() -> String {
     CERTIFICATION_COUNTER.with(|counter| {
         let mut counter = counter.borrow_mut();
@@ -48,6 +93,27 @@ fn generate_certification_id() -> String {
     })
 }
 
+// Set by dfx at build time when course_management is listed as a dependency
+fn course_management_canister() -> Result<Principal> {
+    option_env!("CANISTER_ID_COURSE_MANAGEMENT")
+        .and_then(|id| Principal::from_text(id).ok())
+        .ok_or_else(|| ApiError::InternalError("course_management canister is not configured".to_string()))
+}
+
+// Certificates may only name a syllabus version that was actually published
+async fn ensure_course_version_exists(course_id: &str, course_version: u32) -> Result<()> {
+    let (result,): (std::result::Result<candid::Reserved, candid::Reserved>,) = ic_cdk::call(
+        course_management_canister()?,
+        "get_course_version",
+        (course_id.to_string(), course_version),
+    )
+    .await
+    .map_err(|(_, message)| ApiError::InternalError(message))?;
+    result.map(|_| ()).map_err(|_| {
+        ApiError::NotFound(format!("Course {} has no version {}", course_id, course_version))
+    })
+}
+
 // Certification System Functions
 
 #[update]
@@ -55,9 +121,12 @@ fn generate_certification_id() -> String {
 async fn issue_certification(
     user_id: Principal,
     course_id: String,
+    course_version: u32,
     final_score: u8,
 ) -> Result<Certification> {
+    ensure_not_frozen()?;
     let _caller_id = caller();
+    ensure_course_version_exists(&course_id, course_version).await?;
     
     let certification_id = generate_certification_id();
     let current_time = get_current_time();
@@ -66,6 +135,7 @@ async fn issue_certification(
         id: certification_id.clone(),
         user_id,
         course_id,
+        course_version,
         title: "Certificate of Completion".to_string(),
         description: "This certifies successful course completion".to_string(),
         issued_at: current_time,
@@ -90,21 +160,26 @@ fn get_certification(certification_id: String) -> Result<Certification> {
 
 #[query]
 #[candid_method(query)]
-fn get_user_certifications(user_id: Principal) -> Vec<Certification> {
+fn get_user_certifications(
+    user_id: Principal,
+    cursor: Option<String>,
+    limit: Option<u32>,
+) -> Result<Page<Certification>> {
     CERTIFICATIONS.with(|certs| {
-        certs.borrow()
-            .values()
-            .filter(|cert| cert.user_id == user_id)
-            .cloned()
-            .collect()
+        paginate(
+            certs.borrow().values().filter(|cert| cert.user_id == user_id),
+            certification_page_key,
+            cursor,
+            limit,
+        )
     })
 }
 
 #[query]
 #[candid_method(query)]
-fn get_all_certifications() -> Vec<Certification> {
+fn get_all_certifications(cursor: Option<String>, limit: Option<u32>) -> Result<Page<Certification>> {
     CERTIFICATIONS.with(|certs| {
-        certs.borrow().values().cloned().collect()
+        paginate(certs.borrow().values(), certification_page_key, cursor, limit)
     })
 }
 
@@ -117,4 +192,23 @@ fn verify_certification(certification_id: String) -> Result<bool> {
     }
 }
 
+// Freeze Functions
+
+fn ensure_not_frozen() -> Result<()> {
+    freeze::ensure_not_frozen(get_current_time()).map_err(|until| ApiError::Frozen { until })
+}
+
+#[update]
+#[candid_method(update)]
+fn set_frozen_until(frozen_until: Option<u64>) -> Result<bool> {
+    freeze::set_frozen_until(frozen_until).map_err(|_| ApiError::InsufficientPermissions)?;
+    Ok(true)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_frozen_until() -> Option<u64> {
+    freeze::frozen_until(get_current_time())
+}
+
 export_candid!();
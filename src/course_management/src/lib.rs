@@ -1,9 +1,16 @@
-use candid::{candid_method, CandidType, Deserialize, Principal};
-use ic_cdk::api::time;
-use ic_cdk::{caller, export_candid, query, update};
+use candid::{candid_method, CandidType, Deserialize, Nat, Principal};
+use ic_cdk::{caller, export_candid, post_upgrade, pre_upgrade, query, update};
+use rust_stemmers::{Algorithm, Stemmer};
 use serde::Serialize;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use shared::icrc::{
+    describe_transfer_error, describe_transfer_from_error, memo_for, Account, TransferArg, TransferError,
+    TransferFromArgs, TransferFromError,
+};
+use shared::freeze;
+use shared::pagination::{paginate, InvalidCursor, Page};
+use shared::stable;
 
 // Simple types for the demo
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
@@ -35,11 +42,453 @@ pub struct Course {
     pub difficulty_level: DifficultyLevel,
     pub estimated_duration_hours: u32,
     pub price: u64,
+    pub prerequisites: Vec<String>, // course ids
+    pub language: String, // ISO 639-1 code, e.g. "en"
+    pub modules: Vec<String>, // module ids in display order
+    pub lessons: Vec<String>, // lesson ids in display order
+    pub enrollment_count: u32,
+    pub rating: f32,
+    pub review_count: u32,
     pub is_published: bool,
+    pub version: u32, // latest published version, 0 until first published
+    pub draft: Option<CourseDraft>, // pending edits to a published course
     pub created_at: u64,
     pub updated_at: u64,
 }
 
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CourseDraft {
+    pub title: String,
+    pub description: String,
+    pub category: String,
+    pub tags: Vec<String>,
+    pub difficulty_level: DifficultyLevel,
+    pub estimated_duration_hours: u32,
+    pub price: u64,
+    pub prerequisites: Vec<String>,
+    pub language: String,
+    pub updated_at: u64,
+}
+
+// Immutable snapshot of a course taken each time it is published
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CourseVersion {
+    pub course_id: String,
+    pub version: u32,
+    pub title: String,
+    pub description: String,
+    pub category: String,
+    pub tags: Vec<String>,
+    pub difficulty_level: DifficultyLevel,
+    pub estimated_duration_hours: u32,
+    pub price: u64,
+    pub prerequisites: Vec<String>,
+    pub language: String,
+    pub modules: Vec<String>,
+    pub lessons: Vec<String>,
+    pub published_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Lesson {
+    pub id: String,
+    pub course_id: String,
+    pub module_id: Option<String>,
+    pub title: String,
+    pub description: String,
+    pub content_type: ContentType,
+    pub content_url: String,
+    pub duration_minutes: u32,
+    pub order_index: u32,
+    pub prerequisites: Vec<String>,
+    pub learning_objectives: Vec<String>,
+    pub release_schedule: Option<ReleaseSchedule>,
+    pub is_archived: bool,
+    pub previous_revision: Option<String>, // the published lesson this edit was copied from
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+// When drip-fed content becomes available to a learner
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub enum ReleaseSchedule {
+    At(u64),
+    DaysAfterEnrollment(u32),
+}
+
+// A lesson as seen by a particular caller; locked lessons have their content withheld
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CourseLesson {
+    pub lesson: Lesson,
+    pub is_locked: bool,
+    pub available_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Module {
+    pub id: String,
+    pub course_id: String,
+    pub title: String,
+    pub description: String,
+    pub order_index: u32,
+    pub release_schedule: Option<ReleaseSchedule>,
+    pub lessons: Vec<String>, // lesson ids in display order
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct ModuleOutline {
+    pub module: Module,
+    pub is_locked: bool,
+    pub available_at: Option<u64>,
+    pub lessons: Vec<CourseLesson>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CourseOutline {
+    pub course_id: String,
+    pub title: String,
+    pub unassigned_lessons: Vec<CourseLesson>,
+    pub modules: Vec<ModuleOutline>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct ModuleProgress {
+    pub module_id: String,
+    pub title: String,
+    pub completed_lessons: u32,
+    pub total_lessons: u32,
+    pub completion_percentage: f32,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct AssignmentSubmission {
+    pub content: String,
+    pub submitted_at: u64,
+    pub grade: Option<u8>,
+    pub feedback: Option<String>,
+    pub graded_by: Option<Principal>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CourseProgress {
+    pub completed_lessons: Vec<String>,
+    pub quiz_scores: HashMap<String, u8>,
+    pub assignment_submissions: HashMap<String, AssignmentSubmission>,
+    pub time_spent_minutes: u32,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Enrollment {
+    pub user_id: Principal,
+    pub course_id: String,
+    pub run_id: Option<String>, // None for self-paced enrollments
+    pub course_version: u32,
+    pub enrolled_at: u64,
+    pub progress: CourseProgress,
+    pub completion_percentage: f32,
+    pub last_accessed: u64,
+}
+
+// A scheduled cohort of a course with its own dates, capacity and waitlist
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CourseRun {
+    pub id: String,
+    pub course_id: String,
+    pub title: String,
+    pub start_date: u64,
+    pub end_date: u64,
+    pub enrollment_opens_at: u64,
+    pub enrollment_closes_at: u64,
+    pub capacity: u32,
+    pub enrollment_count: u32,
+    pub waitlist: Vec<Principal>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub enum RunEnrollmentStatus {
+    Enrolled(Box<Enrollment>),
+    Waitlisted { position: u32 },
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct DiscussionThread {
+    pub id: String,
+    pub course_id: String,
+    pub lesson_id: Option<String>,
+    pub run_id: Option<String>, // cohort the author belongs to, if any
+    pub author_id: Principal,
+    pub title: String,
+    pub content: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub last_activity_at: u64,
+    pub replies: Vec<ThreadReply>,
+    pub tags: Vec<String>,
+    pub upvotes: u32,
+    pub downvotes: u32,
+    pub is_pinned: bool,
+    pub is_locked: bool,
+    pub visibility: ContentVisibility,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct ThreadReply {
+    pub id: String,
+    pub author_id: Principal,
+    pub content: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub upvotes: u32,
+    pub downvotes: u32,
+    pub parent_reply_id: Option<String>,
+    pub visibility: ContentVisibility,
+}
+
+// Hidden content is only shown to its author; deleted content is shown to no one.
+// Both are reversible through a successful moderation appeal.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum ContentVisibility {
+    Visible,
+    Hidden,
+    Deleted,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum ReportTarget {
+    Thread { thread_id: String },
+    Reply { thread_id: String, reply_id: String },
+    Profile { user_id: Principal },
+    Review { review_id: String },
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum ReportStatus {
+    Open,
+    Actioned,
+    Dismissed,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct ContentReport {
+    pub id: u64,
+    pub reporter_id: Principal,
+    pub target: ReportTarget,
+    pub reason: String,
+    pub status: ReportStatus,
+    pub created_at: u64,
+    pub resolved_at: Option<u64>,
+    pub resolved_by: Option<Principal>,
+    pub action_id: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum ModerationActionType {
+    Dismiss,
+    HideContent,
+    DeleteContent,
+    WarnUser,
+    SuspendUser { days: u32 },
+    Overturn { action_id: u64, proposal_id: u64 },
+}
+
+// An entry in the append-only moderation audit log
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct ModerationAction {
+    pub id: u64,
+    pub actor_id: Principal,
+    pub action: ModerationActionType,
+    pub target: Option<ReportTarget>,
+    pub affected_user: Option<Principal>,
+    pub report_id: Option<u64>,
+    pub reason: String,
+    pub created_at: u64,
+    pub overturned: bool,
+}
+
+// Account-level actions applied by user_management on our behalf
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub enum UserModeration {
+    HideProfile,
+    RestoreProfile,
+    Warn,
+    RemoveWarning,
+    Suspend { until: u64, reason: String },
+    Reinstate,
+}
+
+// Mirrors user_management's account status
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum AccountStatus {
+    Active,
+    Suspended { until: u64, reason: String },
+    Banned { until: Option<u64>, reason: String },
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum DiscussionVote {
+    Upvote,
+    Downvote,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum ThreadSort {
+    Newest,
+    Activity,
+    Score,
+}
+
+// A learner's rating of a course. Only visible reviews count towards the
+// course rating.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Review {
+    pub id: String,
+    pub course_id: String,
+    pub user_id: Principal,
+    pub rating: u8, // 1 - 5
+    pub comment: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub helpful_votes: u32,
+    pub unhelpful_votes: u32,
+    pub instructor_response: Option<InstructorResponse>,
+    pub visibility: ContentVisibility,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct InstructorResponse {
+    pub content: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum ReviewVote {
+    Helpful,
+    Unhelpful,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum ReviewSort {
+    Newest,
+    MostHelpful,
+    HighestRating,
+    LowestRating,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CourseRecommendation {
+    pub course_id: String,
+    pub reason: String,
+    pub priority_score: f32, // 0.0 - 1.0
+    pub estimated_completion_time: u32, // hours
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum TagMatch {
+    Any,
+    All,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum CourseSort {
+    Relevance,
+    Newest,
+    MostPopular,
+    HighestRated,
+    Shortest,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct SearchHit {
+    pub course: Course,
+    pub score: f32,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: u32,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct SearchResults {
+    pub hits: Page<SearchHit>,
+    pub total: u32,
+    pub categories: Vec<FacetCount>,
+    pub difficulty_levels: Vec<FacetCount>,
+    pub languages: Vec<FacetCount>,
+    pub tags: Vec<FacetCount>,
+    pub price_ranges: Vec<FacetCount>,
+    pub durations: Vec<FacetCount>,
+    pub ratings: Vec<FacetCount>,
+    pub instructors: Vec<FacetCount>,
+}
+
+// An ordered list of courses, either curated by a user or generated from
+// their target skills
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct LearningPath {
+    pub id: String,
+    pub user_id: Principal,
+    pub title: String,
+    pub description: String,
+    pub target_skills: Vec<String>,
+    pub recommended_courses: Vec<CourseRecommendation>,
+    pub unavailable_prerequisites: Vec<String>, // unpublished prerequisites left out of a generated path
+    pub is_public: bool,
+    pub is_generated: bool,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct PaymentConfig {
+    pub ledger_canister_id: Option<Principal>, // ICRC-2 ledger that course prices are paid in
+    pub platform_fee_bps: u16, // share of each payment kept by the platform, in basis points
+    pub platform_account: Option<Account>, // where platform earnings are withdrawn to
+    pub refund_window_days: u32, // learners can get their money back for this long after paying
+    pub refund_max_completion: f32, // ...as long as they have completed less than this percentage
+}
+
+// Payments are held until their refund window closes and only then settle
+// into the instructor's and platform's withdrawable balances
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum PaymentStatus {
+    Held,
+    Refunding,
+    Refunded { refunded_at: u64, block_index: Nat },
+    Settled,
+}
+
+// A completed enrollment payment and how it was split
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Payment {
+    pub id: String,
+    pub user_id: Principal,
+    pub course_id: String,
+    pub run_id: Option<String>,
+    pub instructor_id: Principal,
+    pub amount: u64,
+    pub instructor_share: u64,
+    pub platform_fee: u64,
+    pub block_index: Nat,
+    pub paid_at: u64,
+    pub settles_at: u64, // end of the refund window
+    pub status: PaymentStatus,
+}
+
+// Progress of a cancelled enrollment, kept for the learner's records
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct ArchivedEnrollment {
+    pub enrollment: Enrollment,
+    pub archived_at: u64,
+    pub refund: Option<Payment>,
+}
+
 #[derive(Clone, Debug, CandidType, Deserialize)]
 pub struct CreateCourseRequest {
     pub title: String,
@@ -49,6 +498,118 @@ pub struct CreateCourseRequest {
     pub difficulty_level: DifficultyLevel,
     pub estimated_duration_hours: u32,
     pub price: u64,
+    pub prerequisites: Vec<String>,
+    pub language: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct UpdateCourseRequest {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub difficulty_level: Option<DifficultyLevel>,
+    pub estimated_duration_hours: Option<u32>,
+    pub price: Option<u64>,
+    pub prerequisites: Option<Vec<String>>,
+    pub language: Option<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CreateLessonRequest {
+    pub course_id: String,
+    pub module_id: Option<String>,
+    pub title: String,
+    pub description: String,
+    pub content_type: ContentType,
+    pub content_url: String,
+    pub duration_minutes: u32,
+    pub prerequisites: Vec<String>,
+    pub learning_objectives: Vec<String>,
+    pub release_schedule: Option<ReleaseSchedule>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct UpdateLessonRequest {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub content_type: Option<ContentType>,
+    pub content_url: Option<String>,
+    pub duration_minutes: Option<u32>,
+    pub prerequisites: Option<Vec<String>>,
+    pub learning_objectives: Option<Vec<String>>,
+    pub release_schedule: Option<Option<ReleaseSchedule>>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CreateModuleRequest {
+    pub course_id: String,
+    pub title: String,
+    pub description: String,
+    pub release_schedule: Option<ReleaseSchedule>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct UpdateModuleRequest {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub release_schedule: Option<Option<ReleaseSchedule>>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CreateCourseRunRequest {
+    pub course_id: String,
+    pub title: String,
+    pub start_date: u64,
+    pub end_date: u64,
+    pub enrollment_opens_at: u64,
+    pub enrollment_closes_at: u64,
+    pub capacity: u32,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CreateLearningPathRequest {
+    pub title: String,
+    pub description: String,
+    pub course_ids: Vec<String>,
+    pub is_public: bool,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct UpdateLearningPathRequest {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub course_ids: Option<Vec<String>>,
+    pub is_public: Option<bool>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct SearchRequest {
+    pub query: Option<String>,
+    pub category: Option<String>,
+    pub difficulty_level: Option<DifficultyLevel>,
+    pub min_price: Option<u64>,
+    pub max_price: Option<u64>,
+    pub free_only: Option<bool>,
+    pub min_duration_hours: Option<u32>,
+    pub max_duration_hours: Option<u32>,
+    pub min_rating: Option<f32>,
+    pub instructor_id: Option<Principal>,
+    pub tags: Option<Vec<String>>,
+    pub tag_match: Option<TagMatch>,
+    pub language: Option<String>,
+    pub sort: Option<CourseSort>,
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CreateThreadRequest {
+    pub course_id: String,
+    pub lesson_id: Option<String>,
+    pub title: String,
+    pub content: String,
+    pub tags: Vec<String>,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
@@ -56,21 +617,211 @@ pub enum ApiError {
     NotFound(String),
     Unauthorized,
     InvalidInput(String),
+    InternalError(String),
     AlreadyExists(String),
     InsufficientPermissions,
+    AccountRestricted(AccountStatus),
+    PaymentFailed(String),
+    Frozen { until: u64 }, // governance guardians froze the canister's mutating endpoints
 }
 
 pub type Result<T> = std::result::Result<T, ApiError>;
 
-// Simple in-memory storage for demo purposes
+impl From<InvalidCursor> for ApiError {
+    fn from(_: InvalidCursor) -> Self {
+        ApiError::InvalidInput("Invalid cursor".to_string())
+    }
+}
+
+// Inverted index over published courses, kept up to date by save_course
+#[derive(Default)]
+struct SearchIndex {
+    postings: HashMap<String, HashMap<String, u32>>, // term -> course id -> weighted term frequency
+    document_lengths: HashMap<String, u32>,
+    document_terms: HashMap<String, Vec<String>>,
+    total_length: u64,
+}
+
+// Simple in-memory storage for demo purposes. Other calls can run while an
+// update awaits an inter-canister call, so updates read what they change again
+// after the call instead of writing back a copy read before it.
 thread_local! {
     static COURSES: RefCell<HashMap<String, Course>> = RefCell::new(HashMap::new());
-    static COURSE_COUNTER: RefCell<u64> = RefCell::new(0);
+    static COURSE_VERSIONS: RefCell<HashMap<String, CourseVersion>> = RefCell::new(HashMap::new());
+    static MODULES: RefCell<HashMap<String, Module>> = RefCell::new(HashMap::new());
+    static LESSONS: RefCell<HashMap<String, Lesson>> = RefCell::new(HashMap::new());
+    static ENROLLMENTS: RefCell<HashMap<String, Enrollment>> = RefCell::new(HashMap::new());
+    static COURSE_RUNS: RefCell<HashMap<String, CourseRun>> = RefCell::new(HashMap::new());
+    static DISCUSSIONS: RefCell<HashMap<String, DiscussionThread>> = RefCell::new(HashMap::new());
+    static DISCUSSION_VOTES: RefCell<HashMap<String, DiscussionVote>> = RefCell::new(HashMap::new());
+    static REVIEWS: RefCell<HashMap<String, Review>> = RefCell::new(HashMap::new());
+    static REVIEW_VOTES: RefCell<HashMap<String, ReviewVote>> = RefCell::new(HashMap::new());
+    static RATING_TOTALS: RefCell<HashMap<String, u64>> = RefCell::new(HashMap::new()); // course id -> sum of visible ratings
+    static REPORTS: RefCell<HashMap<u64, ContentReport>> = RefCell::new(HashMap::new());
+    static RESOLVING_REPORTS: RefCell<HashSet<u64>> = RefCell::new(HashSet::new()); // reports with a moderation call in flight
+    static LEARNING_PATHS: RefCell<HashMap<String, LearningPath>> = RefCell::new(HashMap::new());
+    static SEARCH_INDEX: RefCell<SearchIndex> = RefCell::new(SearchIndex::default());
+    static MODERATION_LOG: RefCell<Vec<ModerationAction>> = const { RefCell::new(Vec::new()) };
+    static TRUSTED_CANISTERS: RefCell<Vec<Principal>> = const { RefCell::new(Vec::new()) };
+    static PAYMENT_CONFIG: RefCell<PaymentConfig> = const {
+        RefCell::new(PaymentConfig {
+            ledger_canister_id: None,
+            platform_fee_bps: DEFAULT_PLATFORM_FEE_BPS,
+            platform_account: None,
+            refund_window_days: DEFAULT_REFUND_WINDOW_DAYS,
+            refund_max_completion: DEFAULT_REFUND_MAX_COMPLETION,
+        })
+    };
+    static PAYMENTS: RefCell<HashMap<String, Payment>> = RefCell::new(HashMap::new());
+    static ARCHIVED_ENROLLMENTS: RefCell<Vec<ArchivedEnrollment>> = const { RefCell::new(Vec::new()) };
+    static PENDING_PAYMENTS: RefCell<HashSet<String>> = RefCell::new(HashSet::new()); // enrollment keys with a ledger call in flight
+    static EARNINGS: RefCell<HashMap<Principal, u64>> = RefCell::new(HashMap::new()); // instructor -> settled, unwithdrawn share
+    static PLATFORM_EARNINGS: RefCell<u64> = const { RefCell::new(0) };
+    static PLATFORM_FEES_OWED: RefCell<u64> = const { RefCell::new(0) }; // refund ledger fees not yet covered by platform earnings
+    static COURSE_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    static MODULE_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    static LESSON_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    static COURSE_RUN_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    static DISCUSSION_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    static REPLY_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    static REVIEW_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    static PAYMENT_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    static REPORT_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    static LEARNING_PATH_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+}
+
+// Stable Memory Functions
+
+// Everything kept across upgrades. The search index is rebuilt from the
+// courses, and no ledger calls can be pending once the canister has been
+// stopped for the upgrade.
+#[derive(CandidType, Deserialize)]
+struct StableState {
+    courses: HashMap<String, Course>,
+    course_versions: HashMap<String, CourseVersion>,
+    modules: HashMap<String, Module>,
+    lessons: HashMap<String, Lesson>,
+    enrollments: HashMap<String, Enrollment>,
+    course_runs: HashMap<String, CourseRun>,
+    discussions: HashMap<String, DiscussionThread>,
+    discussion_votes: HashMap<String, DiscussionVote>,
+    reviews: HashMap<String, Review>,
+    review_votes: HashMap<String, ReviewVote>,
+    rating_totals: HashMap<String, u64>,
+    reports: HashMap<u64, ContentReport>,
+    learning_paths: HashMap<String, LearningPath>,
+    moderation_log: Vec<ModerationAction>,
+    trusted_canisters: Vec<Principal>,
+    payment_config: PaymentConfig,
+    payments: HashMap<String, Payment>,
+    archived_enrollments: Vec<ArchivedEnrollment>,
+    earnings: HashMap<Principal, u64>,
+    platform_earnings: u64,
+    platform_fees_owed: u64,
+    course_counter: u64,
+    module_counter: u64,
+    lesson_counter: u64,
+    course_run_counter: u64,
+    discussion_counter: u64,
+    reply_counter: u64,
+    review_counter: u64,
+    payment_counter: u64,
+    report_counter: u64,
+    learning_path_counter: u64,
+    frozen_until: Option<u64>,
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let state = StableState {
+        courses: COURSES.with(RefCell::take),
+        course_versions: COURSE_VERSIONS.with(RefCell::take),
+        modules: MODULES.with(RefCell::take),
+        lessons: LESSONS.with(RefCell::take),
+        enrollments: ENROLLMENTS.with(RefCell::take),
+        course_runs: COURSE_RUNS.with(RefCell::take),
+        discussions: DISCUSSIONS.with(RefCell::take),
+        discussion_votes: DISCUSSION_VOTES.with(RefCell::take),
+        reviews: REVIEWS.with(RefCell::take),
+        review_votes: REVIEW_VOTES.with(RefCell::take),
+        rating_totals: RATING_TOTALS.with(RefCell::take),
+        reports: REPORTS.with(RefCell::take),
+        learning_paths: LEARNING_PATHS.with(RefCell::take),
+        moderation_log: MODERATION_LOG.with(RefCell::take),
+        trusted_canisters: TRUSTED_CANISTERS.with(RefCell::take),
+        payment_config: PAYMENT_CONFIG.with(|payment_config| payment_config.borrow().clone()),
+        payments: PAYMENTS.with(RefCell::take),
+        archived_enrollments: ARCHIVED_ENROLLMENTS.with(RefCell::take),
+        earnings: EARNINGS.with(RefCell::take),
+        platform_earnings: PLATFORM_EARNINGS.with(RefCell::take),
+        platform_fees_owed: PLATFORM_FEES_OWED.with(RefCell::take),
+        course_counter: COURSE_COUNTER.with(RefCell::take),
+        module_counter: MODULE_COUNTER.with(RefCell::take),
+        lesson_counter: LESSON_COUNTER.with(RefCell::take),
+        course_run_counter: COURSE_RUN_COUNTER.with(RefCell::take),
+        discussion_counter: DISCUSSION_COUNTER.with(RefCell::take),
+        reply_counter: REPLY_COUNTER.with(RefCell::take),
+        review_counter: REVIEW_COUNTER.with(RefCell::take),
+        payment_counter: PAYMENT_COUNTER.with(RefCell::take),
+        report_counter: REPORT_COUNTER.with(RefCell::take),
+        learning_path_counter: LEARNING_PATH_COUNTER.with(RefCell::take),
+        frozen_until: freeze::frozen_until(get_current_time()),
+    };
+    stable::save(state);
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    let Some(state) = stable::restore::<StableState>() else { return };
+    COURSES.with(|courses| *courses.borrow_mut() = state.courses);
+    COURSE_VERSIONS.with(|course_versions| *course_versions.borrow_mut() = state.course_versions);
+    MODULES.with(|modules| *modules.borrow_mut() = state.modules);
+    LESSONS.with(|lessons| *lessons.borrow_mut() = state.lessons);
+    ENROLLMENTS.with(|enrollments| *enrollments.borrow_mut() = state.enrollments);
+    COURSE_RUNS.with(|course_runs| *course_runs.borrow_mut() = state.course_runs);
+    DISCUSSIONS.with(|discussions| *discussions.borrow_mut() = state.discussions);
+    DISCUSSION_VOTES.with(|discussion_votes| *discussion_votes.borrow_mut() = state.discussion_votes);
+    REVIEWS.with(|reviews| *reviews.borrow_mut() = state.reviews);
+    REVIEW_VOTES.with(|review_votes| *review_votes.borrow_mut() = state.review_votes);
+    RATING_TOTALS.with(|rating_totals| *rating_totals.borrow_mut() = state.rating_totals);
+    REPORTS.with(|reports| *reports.borrow_mut() = state.reports);
+    LEARNING_PATHS.with(|learning_paths| *learning_paths.borrow_mut() = state.learning_paths);
+    MODERATION_LOG.with(|moderation_log| *moderation_log.borrow_mut() = state.moderation_log);
+    TRUSTED_CANISTERS.with(|trusted_canisters| *trusted_canisters.borrow_mut() = state.trusted_canisters);
+    PAYMENT_CONFIG.with(|payment_config| *payment_config.borrow_mut() = state.payment_config);
+    PAYMENTS.with(|payments| *payments.borrow_mut() = state.payments);
+    ARCHIVED_ENROLLMENTS.with(|archived_enrollments| *archived_enrollments.borrow_mut() = state.archived_enrollments);
+    EARNINGS.with(|earnings| *earnings.borrow_mut() = state.earnings);
+    PLATFORM_EARNINGS.with(|platform_earnings| *platform_earnings.borrow_mut() = state.platform_earnings);
+    PLATFORM_FEES_OWED.with(|platform_fees_owed| *platform_fees_owed.borrow_mut() = state.platform_fees_owed);
+    COURSE_COUNTER.with(|course_counter| *course_counter.borrow_mut() = state.course_counter);
+    MODULE_COUNTER.with(|module_counter| *module_counter.borrow_mut() = state.module_counter);
+    LESSON_COUNTER.with(|lesson_counter| *lesson_counter.borrow_mut() = state.lesson_counter);
+    COURSE_RUN_COUNTER.with(|course_run_counter| *course_run_counter.borrow_mut() = state.course_run_counter);
+    DISCUSSION_COUNTER.with(|discussion_counter| *discussion_counter.borrow_mut() = state.discussion_counter);
+    REPLY_COUNTER.with(|reply_counter| *reply_counter.borrow_mut() = state.reply_counter);
+    REVIEW_COUNTER.with(|review_counter| *review_counter.borrow_mut() = state.review_counter);
+    PAYMENT_COUNTER.with(|payment_counter| *payment_counter.borrow_mut() = state.payment_counter);
+    REPORT_COUNTER.with(|report_counter| *report_counter.borrow_mut() = state.report_counter);
+    LEARNING_PATH_COUNTER.with(|learning_path_counter| *learning_path_counter.borrow_mut() = state.learning_path_counter);
+    freeze::restore(state.frozen_until);
+
+    let courses: Vec<Course> = COURSES.with(|courses| courses.borrow().values().cloned().collect());
+    for course in &courses {
+        index_course(course);
+    }
 }
 
 // Helper functions
+#[cfg(not(test))]
+fn get_current_time() -> u64 {
+    ic_cdk::api::time()
+}
+
+// Unit tests run outside a canister, where there is no system clock
+#[cfg(test)]
 fn get_current_time() -> u64 {
-    time()
+    tests::now()
 }
 
 fn generate_course_id() -> String {
@@ -81,138 +832,4637 @@ fn generate_course_id() -> String {
     })
 }
 
-// Course Management Functions
+fn generate_module_id() -> String {
+    MODULE_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        *counter += 1;
+        format!("module_{}", *counter)
+    })
+}
+
+fn generate_lesson_id() -> String {
+    LESSON_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        *counter += 1;
+        format!("lesson_{}", *counter)
+    })
+}
+
+fn generate_course_run_id() -> String {
+    COURSE_RUN_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        *counter += 1;
+        format!("run_{}", *counter)
+    })
+}
+
+fn generate_discussion_id() -> String {
+    DISCUSSION_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        *counter += 1;
+        format!("discussion_{}", *counter)
+    })
+}
+
+fn generate_reply_id() -> String {
+    REPLY_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        *counter += 1;
+        format!("reply_{}", *counter)
+    })
+}
+
+fn generate_review_id() -> String {
+    REVIEW_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        *counter += 1;
+        format!("review_{}", *counter)
+    })
+}
+
+fn generate_payment_id() -> String {
+    PAYMENT_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        *counter += 1;
+        format!("payment_{}", *counter)
+    })
+}
+
+fn generate_learning_path_id() -> String {
+    LEARNING_PATH_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        *counter += 1;
+        format!("path_{}", *counter)
+    })
+}
+
+// Saturates instead of overflowing; day counts from callers are also bounded
+fn days_to_nanoseconds(days: u64) -> u64 {
+    days.saturating_mul(24 * 60 * 60 * 1_000_000_000)
+}
+
+const MAX_RELEASE_DELAY_DAYS: u32 = 3650;
+const MAX_SUSPENSION_DAYS: u32 = 365;
+
+fn validate_release_schedule(schedule: Option<&ReleaseSchedule>) -> Result<()> {
+    if let Some(ReleaseSchedule::DaysAfterEnrollment(days)) = schedule {
+        if *days > MAX_RELEASE_DELAY_DAYS {
+            return Err(ApiError::InvalidInput(format!(
+                "Release delay cannot exceed {} days",
+                MAX_RELEASE_DELAY_DAYS
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn course_version_key(course_id: &str, version: u32) -> String {
+    format!("{}_v{}", course_id, version)
+}
+
+fn enrollment_key(user_id: &Principal, course_id: &str) -> String {
+    format!("{}_{}", user_id.to_text(), course_id)
+}
+
+// Set by dfx at build time when user_management is listed as a dependency
+fn user_management_canister() -> Option<Principal> {
+    option_env!("CANISTER_ID_USER_MANAGEMENT").and_then(|id| Principal::from_text(id).ok())
+}
+
+// Inter-canister call to check if user is a moderator (or admin)
+async fn is_user_moderator(user_id: Principal) -> bool {
+    let canister_id = match user_management_canister() {
+        Some(canister_id) => canister_id,
+        None => return false,
+    };
+
+    ic_cdk::call::<(Principal,), (bool,)>(canister_id, "is_moderator", (user_id,))
+        .await
+        .map(|(is_moderator,)| is_moderator)
+        .unwrap_or(false)
+}
+
+async fn apply_user_moderation(user_id: Principal, action: UserModeration) -> Result<()> {
+    let canister_id = user_management_canister()
+        .ok_or_else(|| ApiError::InternalError("user_management canister is not configured".to_string()))?;
+
+    let (result,): (Result<candid::Reserved>,) =
+        ic_cdk::call(canister_id, "apply_moderation", (user_id, action))
+            .await
+            .map_err(|(_, message)| ApiError::InternalError(message))?;
+    result.map(|_| ())
+}
+
+// Inter-canister call to reject suspended or banned users. This must run
+// before any state is read so that nothing goes stale across the await.
+async fn ensure_account_active(user_id: Principal) -> Result<()> {
+    let canister_id = user_management_canister()
+        .ok_or_else(|| ApiError::InternalError("user_management canister is not configured".to_string()))?;
+
+    let (status,): (AccountStatus,) = ic_cdk::call(canister_id, "get_account_status", (user_id,))
+        .await
+        .map_err(|(_, message)| ApiError::InternalError(message))?;
+    match status {
+        AccountStatus::Active => Ok(()),
+        status => Err(ApiError::AccountRestricted(status)),
+    }
+}
+
+fn is_trusted_canister(principal: &Principal) -> bool {
+    TRUSTED_CANISTERS.with(|trusted| trusted.borrow().contains(principal))
+}
+
+// Returns the course if the caller is its instructor
+fn get_instructor_course(course_id: &str, caller_id: Principal) -> Result<Course> {
+    let course = get_course(course_id.to_string())?;
+    if course.instructor_id != caller_id {
+        return Err(ApiError::InsufficientPermissions);
+    }
+    Ok(course)
+}
+
+// Only lessons in the learner's syllabus count towards completion, so archived
+// lessons drop out of both the numerator and the denominator.
+fn calculate_completion(progress: &CourseProgress, syllabus: &[String]) -> f32 {
+    if syllabus.is_empty() {
+        return 0.0;
+    }
+
+    let completed = syllabus.iter()
+        .filter(|lesson_id| progress.completed_lessons.contains(lesson_id))
+        .count();
+    (completed as f32) / (syllabus.len() as f32) * 100.0
+}
+
+// The lessons of a published version that still count. Archiving retires a
+// lesson from every version, not just the next one.
+fn version_syllabus(course_id: &str, version: u32) -> Vec<String> {
+    let lesson_ids = get_course_version(course_id.to_string(), version)
+        .map(|course_version| course_version.lessons)
+        .unwrap_or_default();
+
+    LESSONS.with(|lessons| {
+        let lessons = lessons.borrow();
+        lesson_ids.into_iter()
+            .filter(|lesson_id| lessons.get(lesson_id).map(|lesson| !lesson.is_archived).unwrap_or(false))
+            .collect()
+    })
+}
+
+fn draft_from(course: &Course) -> CourseDraft {
+    CourseDraft {
+        title: course.title.clone(),
+        description: course.description.clone(),
+        category: course.category.clone(),
+        tags: course.tags.clone(),
+        difficulty_level: course.difficulty_level.clone(),
+        estimated_duration_hours: course.estimated_duration_hours,
+        price: course.price,
+        prerequisites: course.prerequisites.clone(),
+        language: course.language.clone(),
+        updated_at: course.updated_at,
+    }
+}
+
+fn apply_draft(course: &mut Course, draft: CourseDraft) {
+    course.title = draft.title;
+    course.description = draft.description;
+    course.category = draft.category;
+    course.tags = draft.tags;
+    course.difficulty_level = draft.difficulty_level;
+    course.estimated_duration_hours = draft.estimated_duration_hours;
+    course.price = draft.price;
+    course.prerequisites = draft.prerequisites;
+    course.language = draft.language;
+}
+
+fn normalize_language(language: &str) -> Result<String> {
+    let language = language.trim().to_lowercase();
+    if language.is_empty() {
+        return Err(ApiError::InvalidInput("Course language cannot be empty".to_string()));
+    }
+    Ok(language)
+}
+
+// Prerequisites must be existing courses and must not lead back to this course
+fn validate_prerequisites(course_id: &str, prerequisites: &[String]) -> Result<()> {
+    let mut seen = HashSet::new();
+    for prerequisite_id in prerequisites {
+        if prerequisite_id == course_id {
+            return Err(ApiError::InvalidInput("A course cannot be its own prerequisite".to_string()));
+        }
+        if !seen.insert(prerequisite_id) {
+            return Err(ApiError::InvalidInput("Duplicate prerequisite".to_string()));
+        }
+        get_course(prerequisite_id.clone())?;
+    }
+
+    COURSES.with(|courses| {
+        let courses = courses.borrow();
+        let mut stack: Vec<&String> = prerequisites.iter().collect();
+        let mut visited = HashSet::new();
+        while let Some(current_id) = stack.pop() {
+            if current_id == course_id {
+                return Err(ApiError::InvalidInput("Prerequisites cannot form a cycle".to_string()));
+            }
+            if visited.insert(current_id.clone()) {
+                if let Some(course) = courses.get(current_id) {
+                    stack.extend(course.prerequisites.iter());
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+// Lessons that are not part of any module, in their current course order
+fn unassigned_lessons(course: &Course) -> Vec<String> {
+    LESSONS.with(|lessons| {
+        let lessons = lessons.borrow();
+        course.lessons.iter()
+            .filter(|lesson_id| {
+                lessons.get(*lesson_id).map(|lesson| lesson.module_id.is_none()).unwrap_or(false)
+            })
+            .cloned()
+            .collect()
+    })
+}
+
+// Rebuilds the flat `course.lessons` order: unassigned lessons come first,
+// followed by the lessons of each module in module order.
+fn rebuild_lesson_order(course: &mut Course, unassigned: Vec<String>) {
+    let mut ordered = unassigned;
+    MODULES.with(|modules| {
+        let modules = modules.borrow();
+        for module_id in &course.modules {
+            if let Some(module) = modules.get(module_id) {
+                ordered.extend(module.lessons.iter().cloned());
+            }
+        }
+    });
+    course.lessons = ordered;
+}
+
+fn get_course_module(module_id: &str, course_id: &str) -> Result<Module> {
+    let module = get_module(module_id.to_string())?;
+    if module.course_id != course_id {
+        return Err(ApiError::InvalidInput("Module does not belong to this course".to_string()));
+    }
+    Ok(module)
+}
+
+fn save_module(module: &Module) {
+    MODULES.with(|modules| modules.borrow_mut().insert(module.id.clone(), module.clone()));
+}
+
+// Rewrites `order_index` of every module to match its position in `course.modules`
+fn renumber_modules(course: &Course) {
+    let current_time = get_current_time();
+    MODULES.with(|modules| {
+        let mut modules = modules.borrow_mut();
+        for (index, module_id) in course.modules.iter().enumerate() {
+            if let Some(module) = modules.get_mut(module_id) {
+                if module.order_index != index as u32 {
+                    module.order_index = index as u32;
+                    module.updated_at = current_time;
+                }
+            }
+        }
+    });
+}
+
+// Rewrites `order_index` of every lesson to match its position in `course.lessons`
+fn renumber_lessons(course: &Course) {
+    let current_time = get_current_time();
+    LESSONS.with(|lessons| {
+        let mut lessons = lessons.borrow_mut();
+        for (index, lesson_id) in course.lessons.iter().enumerate() {
+            if let Some(lesson) = lessons.get_mut(lesson_id) {
+                if lesson.order_index != index as u32 {
+                    lesson.order_index = index as u32;
+                    lesson.updated_at = current_time;
+                }
+            }
+        }
+    });
+}
+
+// Who is looking at a course's lessons, for release schedule and version checks
+struct Viewer {
+    is_instructor: bool,
+    enrolled_at: Option<u64>,
+    syllabus: Vec<String>,
+}
+
+// Instructors see the working syllabus, enrolled learners the version they are
+// pinned to and everyone else the latest published version. Cohort learners
+// follow the run's calendar, so relative schedules count from the run start
+// rather than from the moment they signed up.
+fn viewer_for(course: &Course, user_id: Principal) -> Viewer {
+    let is_instructor = course.instructor_id == user_id;
+    let enrollment = get_user_enrollment(user_id, course.id.clone()).ok();
+
+    let syllabus = match enrollment {
+        _ if is_instructor || course.version == 0 => course.lessons.clone(),
+        Some(ref enrollment) => version_syllabus(&course.id, enrollment.course_version),
+        None => version_syllabus(&course.id, course.version),
+    };
+
+    let enrolled_at = enrollment.map(|enrollment| {
+        let run_start = enrollment.run_id
+            .and_then(|run_id| get_course_run(run_id).ok())
+            .map(|run| run.start_date)
+            .unwrap_or(0);
+        enrollment.enrolled_at.max(run_start)
+    });
+
+    Viewer {
+        is_instructor,
+        enrolled_at,
+        syllabus,
+    }
+}
+
+fn release_time(schedule: &ReleaseSchedule, enrolled_at: u64) -> u64 {
+    match schedule {
+        ReleaseSchedule::At(timestamp) => *timestamp,
+        ReleaseSchedule::DaysAfterEnrollment(days) => {
+            enrolled_at.saturating_add(days_to_nanoseconds(*days as u64))
+        }
+    }
+}
+
+// When content unlocks for the viewer, or None if it has no schedule. Relative
+// schedules count from `enrolled_at`; viewers who are not enrolled are shown the
+// time they would get by enrolling now.
+fn schedule_release_time(schedule: Option<&ReleaseSchedule>, viewer: &Viewer) -> Option<u64> {
+    let enrolled_at = viewer.enrolled_at.unwrap_or_else(get_current_time);
+    schedule.map(|schedule| release_time(schedule, enrolled_at))
+}
+
+// A lesson unlocks once both its own schedule and its module's schedule have passed
+fn lesson_release_time(lesson: &Lesson, viewer: &Viewer) -> Option<u64> {
+    let module_schedule = lesson.module_id.as_ref().and_then(|module_id| {
+        MODULES.with(|modules| {
+            modules.borrow().get(module_id).and_then(|module| module.release_schedule.clone())
+        })
+    });
+
+    let lesson_release = schedule_release_time(lesson.release_schedule.as_ref(), viewer);
+    let module_release = schedule_release_time(module_schedule.as_ref(), viewer);
+    lesson_release.max(module_release)
+}
+
+fn is_locked(available_at: Option<u64>, viewer: &Viewer) -> bool {
+    !viewer.is_instructor && available_at.map(|time| time > get_current_time()).unwrap_or(false)
+}
+
+fn lesson_view(mut lesson: Lesson, viewer: &Viewer) -> CourseLesson {
+    let available_at = lesson_release_time(&lesson, viewer);
+    let is_locked = is_locked(available_at, viewer);
+    if is_locked {
+        lesson.content_url = String::new();
+    }
+
+    CourseLesson {
+        lesson,
+        is_locked,
+        available_at,
+    }
+}
+
+fn load_lesson(lesson_id: &str) -> Result<Lesson> {
+    LESSONS.with(|lessons| {
+        lessons.borrow().get(lesson_id).cloned()
+            .ok_or_else(|| ApiError::NotFound("Lesson not found".to_string()))
+    })
+}
+
+// The lesson and every earlier revision it was copied from, newest first
+fn lesson_lineage(lesson_id: &str) -> Vec<String> {
+    let mut lineage = vec![lesson_id.to_string()];
+    LESSONS.with(|lessons| {
+        let lessons = lessons.borrow();
+        let mut current = lessons.get(lesson_id).and_then(|lesson| lesson.previous_revision.clone());
+        while let Some(previous) = current {
+            current = lessons.get(&previous).and_then(|lesson| lesson.previous_revision.clone());
+            lineage.push(previous);
+        }
+    });
+    lineage
+}
+
+// Maps working lesson ids to the revisions in a pinned syllabus, dropping
+// lessons the syllabus does not contain
+fn pinned_lessons(lesson_ids: &[String], syllabus: &[String]) -> Vec<String> {
+    lesson_ids.iter()
+        .filter_map(|lesson_id| {
+            lesson_lineage(lesson_id).into_iter().find(|revision| syllabus.contains(revision))
+        })
+        .collect()
+}
+
+fn lesson_in_published_version(course: &Course, lesson_id: &str) -> bool {
+    (1..=course.version).any(|version| {
+        get_course_version(course.id.clone(), version)
+            .map(|course_version| course_version.lessons.iter().any(|id| id == lesson_id))
+            .unwrap_or(false)
+    })
+}
+
+fn load_lessons(lesson_ids: &[String]) -> Vec<Lesson> {
+    LESSONS.with(|lessons| {
+        let lessons = lessons.borrow();
+        lesson_ids.iter()
+            .filter_map(|lesson_id| lessons.get(lesson_id).cloned())
+            .collect()
+    })
+}
+
+fn recalculate_course_progress(course: &Course) {
+    ENROLLMENTS.with(|enrollments| {
+        for enrollment in enrollments.borrow_mut().values_mut() {
+            if enrollment.course_id == course.id {
+                let syllabus = version_syllabus(&course.id, enrollment.course_version);
+                enrollment.completion_percentage = calculate_completion(&enrollment.progress, &syllabus);
+            }
+        }
+    });
+}
+
+fn save_course(course: &Course) {
+    COURSES.with(|courses| courses.borrow_mut().insert(course.id.clone(), course.clone()));
+    index_course(course);
+}
+
+fn save_course_run(run: &CourseRun) {
+    COURSE_RUNS.with(|runs| runs.borrow_mut().insert(run.id.clone(), run.clone()));
+}
+
+fn has_course_runs(course_id: &str) -> bool {
+    COURSE_RUNS.with(|runs| runs.borrow().values().any(|run| run.course_id == course_id))
+}
+
+fn is_enrolled(user_id: &Principal, course_id: &str) -> bool {
+    let enrollment_id = enrollment_key(user_id, course_id);
+    ENROLLMENTS.with(|enrollments| enrollments.borrow().contains_key(&enrollment_id))
+}
+
+// Creates and stores a new enrollment, bumping the course enrollment count
+fn create_enrollment(user_id: Principal, course_id: &str, run_id: Option<String>) -> Result<Enrollment> {
+    let mut course = get_course(course_id.to_string())?;
+    let current_time = get_current_time();
+
+    let enrollment = Enrollment {
+        user_id,
+        course_id: course_id.to_string(),
+        run_id,
+        course_version: course.version,
+        enrolled_at: current_time,
+        progress: CourseProgress {
+            completed_lessons: vec![],
+            quiz_scores: HashMap::new(),
+            assignment_submissions: HashMap::new(),
+            time_spent_minutes: 0,
+        },
+        completion_percentage: 0.0,
+        last_accessed: current_time,
+    };
+
+    course.enrollment_count += 1;
+    course.updated_at = current_time;
+    save_course(&course);
+
+    let enrollment_id = enrollment_key(&user_id, course_id);
+    ENROLLMENTS.with(|enrollments| enrollments.borrow_mut().insert(enrollment_id, enrollment.clone()));
+    Ok(enrollment)
+}
+
+// Removes an enrollment and decrements the course's enrollment count, keeping
+// the learner's progress in the archive
+fn archive_enrollment(user_id: Principal, course_id: &str, refund: Option<Payment>) -> Result<ArchivedEnrollment> {
+    let enrollment_id = enrollment_key(&user_id, course_id);
+    let enrollment = ENROLLMENTS.with(|enrollments| enrollments.borrow_mut().remove(&enrollment_id))
+        .ok_or_else(|| ApiError::NotFound("Enrollment not found".to_string()))?;
+
+    let current_time = get_current_time();
+    if let Ok(mut course) = get_course(course_id.to_string()) {
+        course.enrollment_count = course.enrollment_count.saturating_sub(1);
+        course.updated_at = current_time;
+        save_course(&course);
+    }
+
+    let archived = ArchivedEnrollment {
+        enrollment,
+        archived_at: current_time,
+        refund,
+    };
+    ARCHIVED_ENROLLMENTS.with(|archive| archive.borrow_mut().push(archived.clone()));
+    Ok(archived)
+}
+
+// Fills free seats from the front of the waitlist until the run is full.
+// Paid runs charge each learner as they are promoted, and a learner whose
+// payment fails gives up their place to the next in line. Promotion stops
+// once the run has ended, since there is nothing left to join.
+async fn promote_waitlist(run_id: &str) -> Result<CourseRun> {
+    loop {
+        let mut run = get_course_run(run_id.to_string())?;
+        if get_current_time() >= run.end_date || run.enrollment_count >= run.capacity || run.waitlist.is_empty() {
+            return Ok(run);
+        }
+
+        // Hold a seat for the learner while they are being charged
+        let user_id = run.waitlist.remove(0);
+        let already_enrolled = is_enrolled(&user_id, &run.course_id);
+        if !already_enrolled {
+            run.enrollment_count += 1;
+        }
+        run.updated_at = get_current_time();
+        save_course_run(&run);
+        if already_enrolled {
+            continue;
+        }
+
+        let course = get_course(run.course_id.clone())?;
+        if charge_and_enroll(user_id, &course, Some(run.id.clone())).await.is_err() {
+            release_run_seat(&run.id)?;
+        }
+    }
+}
+
+// Gives back a seat that was held for a learner who could not be enrolled
+fn release_run_seat(run_id: &str) -> Result<()> {
+    let mut run = get_course_run(run_id.to_string())?;
+    run.enrollment_count = run.enrollment_count.saturating_sub(1);
+    run.updated_at = get_current_time();
+    save_course_run(&run);
+    Ok(())
+}
+
+// Course Management Functions
+#[update]
+#[candid_method(update)]
+async fn create_course(request: CreateCourseRequest) -> Result<Course> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    
+    // Validate input
+    if request.title.trim().is_empty() {
+        return Err(ApiError::InvalidInput("Course title cannot be empty".to_string()));
+    }
+    
+    if request.description.trim().is_empty() {
+        return Err(ApiError::InvalidInput("Course description cannot be empty".to_string()));
+    }
+
+    let language = normalize_language(&request.language)?;
+    let course_id = generate_course_id();
+    validate_prerequisites(&course_id, &request.prerequisites)?;
+    let current_time = get_current_time();
+
+    let course = Course {
+        id: course_id.clone(),
+        title: request.title,
+        description: request.description,
+        instructor_id: caller_id,
+        category: request.category,
+        tags: request.tags,
+        difficulty_level: request.difficulty_level,
+        estimated_duration_hours: request.estimated_duration_hours,
+        price: request.price,
+        prerequisites: request.prerequisites,
+        language,
+        modules: vec![],
+        lessons: vec![],
+        enrollment_count: 0,
+        rating: 0.0,
+        review_count: 0,
+        is_published: false,
+        version: 0,
+        draft: None,
+        created_at: current_time,
+        updated_at: current_time,
+    };
+
+    save_course(&course);
+    Ok(course)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_course(course_id: String) -> Result<Course> {
+    COURSES.with(|courses| {
+        courses.borrow().get(&course_id).cloned()
+            .ok_or_else(|| ApiError::NotFound("Course not found".to_string()))
+    })
+}
+
+#[update]
+#[candid_method(update)]
+async fn update_course(course_id: String, request: UpdateCourseRequest) -> Result<Course> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let mut course = get_instructor_course(&course_id, caller_id)?;
+
+    // Published courses collect edits in a draft so enrolled learners are not
+    // affected until the instructor publishes a new version.
+    let mut draft = course.draft.take().unwrap_or_else(|| draft_from(&course));
+
+    if let Some(title) = request.title {
+        if title.trim().is_empty() {
+            return Err(ApiError::InvalidInput("Title cannot be empty".to_string()));
+        }
+        draft.title = title;
+    }
+    if let Some(description) = request.description {
+        draft.description = description;
+    }
+    if let Some(category) = request.category {
+        draft.category = category;
+    }
+    if let Some(tags) = request.tags {
+        draft.tags = tags;
+    }
+    if let Some(difficulty_level) = request.difficulty_level {
+        draft.difficulty_level = difficulty_level;
+    }
+    if let Some(estimated_duration_hours) = request.estimated_duration_hours {
+        draft.estimated_duration_hours = estimated_duration_hours;
+    }
+    if let Some(price) = request.price {
+        draft.price = price;
+    }
+    if let Some(prerequisites) = request.prerequisites {
+        validate_prerequisites(&course_id, &prerequisites)?;
+        draft.prerequisites = prerequisites;
+    }
+    if let Some(language) = request.language {
+        draft.language = normalize_language(&language)?;
+    }
+
+    let current_time = get_current_time();
+    draft.updated_at = current_time;
+
+    if course.is_published {
+        course.draft = Some(draft);
+    } else {
+        apply_draft(&mut course, draft);
+    }
+
+    course.updated_at = current_time;
+    save_course(&course);
+    Ok(course)
+}
+
+#[update]
+#[candid_method(update)]
+async fn discard_course_draft(course_id: String) -> Result<Course> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let mut course = get_instructor_course(&course_id, caller_id)?;
+    if course.draft.take().is_none() {
+        return Err(ApiError::NotFound("Course has no draft".to_string()));
+    }
+
+    course.updated_at = get_current_time();
+    save_course(&course);
+    Ok(course)
+}
+
+// Publishes the current draft and syllabus as a new immutable version
+#[update]
+#[candid_method(update)]
+async fn publish_course(course_id: String) -> Result<Course> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let mut course = get_instructor_course(&course_id, caller_id)?;
+
+    if course.is_published {
+        let published = get_course_version(course_id.clone(), course.version)?;
+        let syllabus_changed = published.lessons != course.lessons || published.modules != course.modules;
+        match course.draft.take() {
+            // Other courses may have changed since the draft was saved
+            Some(draft) => {
+                validate_prerequisites(&course.id, &draft.prerequisites)?;
+                apply_draft(&mut course, draft)
+            }
+            None if !syllabus_changed => {
+                return Err(ApiError::InvalidInput("No changes to publish".to_string()));
+            }
+            None => {}
+        }
+    }
+
+    let current_time = get_current_time();
+    course.version += 1;
+    course.is_published = true;
+    course.updated_at = current_time;
+
+    let course_version = CourseVersion {
+        course_id: course.id.clone(),
+        version: course.version,
+        title: course.title.clone(),
+        description: course.description.clone(),
+        category: course.category.clone(),
+        tags: course.tags.clone(),
+        difficulty_level: course.difficulty_level.clone(),
+        estimated_duration_hours: course.estimated_duration_hours,
+        price: course.price,
+        prerequisites: course.prerequisites.clone(),
+        language: course.language.clone(),
+        modules: course.modules.clone(),
+        lessons: course.lessons.clone(),
+        published_at: current_time,
+    };
+
+    COURSE_VERSIONS.with(|versions| {
+        versions.borrow_mut().insert(course_version_key(&course.id, course.version), course_version)
+    });
+    save_course(&course);
+    Ok(course)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_course_version(course_id: String, version: u32) -> Result<CourseVersion> {
+    COURSE_VERSIONS.with(|versions| {
+        versions.borrow().get(&course_version_key(&course_id, version)).cloned()
+            .ok_or_else(|| ApiError::NotFound("Course version not found".to_string()))
+    })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_course_versions(course_id: String, cursor: Option<String>, limit: Option<u32>) -> Result<Page<CourseVersion>> {
+    COURSE_VERSIONS.with(|versions| {
+        paginate(
+            versions.borrow().values().filter(|course_version| course_version.course_id == course_id),
+            |course_version| format!("{:010}", course_version.version),
+            cursor,
+            limit,
+        )
+    })
+}
+
+// Courses are listed oldest first
+fn course_page_key(course: &Course) -> String {
+    format!("{:020}:{}", course.created_at, course.id)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_all_courses(cursor: Option<String>, limit: Option<u32>) -> Result<Page<Course>> {
+    COURSES.with(|courses| {
+        paginate(courses.borrow().values(), course_page_key, cursor, limit)
+    })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_published_courses(cursor: Option<String>, limit: Option<u32>) -> Result<Page<Course>> {
+    COURSES.with(|courses| {
+        paginate(
+            courses.borrow().values().filter(|course| course.is_published),
+            course_page_key,
+            cursor,
+            limit,
+        )
+    })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_instructor_courses(
+    instructor_id: Principal,
+    cursor: Option<String>,
+    limit: Option<u32>,
+) -> Result<Page<Course>> {
+    COURSES.with(|courses| {
+        paginate(
+            courses.borrow().values().filter(|course| course.instructor_id == instructor_id),
+            course_page_key,
+            cursor,
+            limit,
+        )
+    })
+}
+
+// Search Functions
+
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+const TITLE_WEIGHT: u32 = 3;
+const TAG_WEIGHT: u32 = 2;
+const TEXT_WEIGHT: u32 = 1;
+
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "how", "in", "into", "is", "it",
+    "of", "on", "or", "that", "the", "this", "to", "with", "you", "your",
+];
+
+// Lowercases, splits on anything that is not a letter or digit, drops stop
+// words and reduces each word to its English stem
+fn tokenize(text: &str) -> Vec<String> {
+    let stemmer = Stemmer::create(Algorithm::English);
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+        .map(|word| stemmer.stem(&word).into_owned())
+        .collect()
+}
+
+fn add_terms(terms: &mut HashMap<String, u32>, text: &str, weight: u32) {
+    for term in tokenize(text) {
+        *terms.entry(term).or_insert(0) += weight;
+    }
+}
+
+fn remove_from_index(index: &mut SearchIndex, course_id: &str) {
+    if let Some(terms) = index.document_terms.remove(course_id) {
+        for term in terms {
+            if let Some(postings) = index.postings.get_mut(&term) {
+                postings.remove(course_id);
+                if postings.is_empty() {
+                    index.postings.remove(&term);
+                }
+            }
+        }
+    }
+    if let Some(length) = index.document_lengths.remove(course_id) {
+        index.total_length -= length as u64;
+    }
+}
+
+// Only published courses are searchable. Title words count three times and
+// tags twice, and the document length used by BM25 is weighted the same way.
+fn index_course(course: &Course) {
+    let mut terms = HashMap::new();
+    if course.is_published {
+        add_terms(&mut terms, &course.title, TITLE_WEIGHT);
+        add_terms(&mut terms, &course.description, TEXT_WEIGHT);
+        for tag in &course.tags {
+            add_terms(&mut terms, tag, TAG_WEIGHT);
+        }
+        // Index what learners can see, not unpublished edits to the working syllabus
+        let syllabus = version_syllabus(&course.id, course.version);
+        LESSONS.with(|lessons| {
+            let lessons = lessons.borrow();
+            for lesson in syllabus.iter().filter_map(|lesson_id| lessons.get(lesson_id)) {
+                add_terms(&mut terms, &lesson.title, TEXT_WEIGHT);
+            }
+        });
+    }
+
+    SEARCH_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        remove_from_index(&mut index, &course.id);
+        if terms.is_empty() {
+            return;
+        }
+
+        let length: u32 = terms.values().sum();
+        for (term, frequency) in &terms {
+            index.postings.entry(term.clone()).or_default().insert(course.id.clone(), *frequency);
+        }
+        index.document_terms.insert(course.id.clone(), terms.into_keys().collect());
+        index.document_lengths.insert(course.id.clone(), length);
+        index.total_length += length as u64;
+    });
+}
+
+fn reindex_course(course_id: &str) {
+    if let Ok(course) = get_course(course_id.to_string()) {
+        index_course(&course);
+    }
+}
+
+// BM25 score of every indexed course containing at least one query term
+fn score_query(query: &str) -> HashMap<String, f32> {
+    let mut query_terms = tokenize(query);
+    query_terms.sort();
+    query_terms.dedup();
+
+    SEARCH_INDEX.with(|index| {
+        let index = index.borrow();
+        let document_count = index.document_lengths.len() as f32;
+        let average_length = (index.total_length as f32 / document_count.max(1.0)).max(1.0);
+
+        let mut scores: HashMap<String, f32> = HashMap::new();
+        for term in &query_terms {
+            let postings = match index.postings.get(term) {
+                Some(postings) => postings,
+                None => continue,
+            };
+            let document_frequency = postings.len() as f32;
+            let idf = (1.0 + (document_count - document_frequency + 0.5) / (document_frequency + 0.5)).ln();
+
+            for (course_id, frequency) in postings {
+                let frequency = *frequency as f32;
+                let length = index.document_lengths.get(course_id).copied().unwrap_or(0) as f32;
+                let normalization = BM25_K1 * (1.0 - BM25_B + BM25_B * length / average_length);
+                *scores.entry(course_id.clone()).or_insert(0.0) +=
+                    idf * frequency * (BM25_K1 + 1.0) / (frequency + normalization);
+            }
+        }
+        scores
+    })
+}
+
+// Catalog dimensions that searches can be filtered and faceted by
+#[derive(Clone, Copy, PartialEq)]
+enum Facet {
+    Category,
+    Difficulty,
+    Language,
+    Tags,
+    Price,
+    Duration,
+    Rating,
+    Instructor,
+}
+
+fn normalized_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+fn price_label(price: u64) -> String {
+    if price == 0 { "Free" } else { "Paid" }.to_string()
+}
+
+fn duration_label(hours: u32) -> String {
+    match hours {
+        0..=4 => "Under 5 hours",
+        5..=20 => "5-20 hours",
+        _ => "Over 20 hours",
+    }.to_string()
+}
+
+// Ratings bucket cumulatively to match the min_rating filter, so a 4.5 star
+// course counts towards "4+ stars" and every lower bucket
+fn rating_labels(rating: f32) -> Vec<String> {
+    (1..=4u8).rev()
+        .filter(|stars| rating >= *stars as f32)
+        .map(|stars| format!("{}+ stars", stars))
+        .collect()
+}
+
+// Every filter in the request that the course does not satisfy
+fn failed_filters(course: &Course, request: &SearchRequest) -> Vec<Facet> {
+    let mut failed = Vec::new();
+
+    if let Some(ref category) = request.category {
+        if course.category != *category {
+            failed.push(Facet::Category);
+        }
+    }
+    if let Some(ref difficulty_level) = request.difficulty_level {
+        if course.difficulty_level != *difficulty_level {
+            failed.push(Facet::Difficulty);
+        }
+    }
+    if let Some(ref language) = request.language {
+        if course.language != language.trim().to_lowercase() {
+            failed.push(Facet::Language);
+        }
+    }
+    if let Some(ref tags) = request.tags {
+        let wanted = normalized_tags(tags);
+        let course_tags = normalized_tags(&course.tags);
+        let matched = if matches!(request.tag_match, Some(TagMatch::All)) {
+            wanted.iter().all(|tag| course_tags.contains(tag))
+        } else {
+            wanted.is_empty() || wanted.iter().any(|tag| course_tags.contains(tag))
+        };
+        if !matched {
+            failed.push(Facet::Tags);
+        }
+    }
+
+    let price_matches = request.min_price.map(|min| course.price >= min).unwrap_or(true)
+        && request.max_price.map(|max| course.price <= max).unwrap_or(true)
+        && (!request.free_only.unwrap_or(false) || course.price == 0);
+    if !price_matches {
+        failed.push(Facet::Price);
+    }
+
+    let duration_matches = request.min_duration_hours.map(|min| course.estimated_duration_hours >= min).unwrap_or(true)
+        && request.max_duration_hours.map(|max| course.estimated_duration_hours <= max).unwrap_or(true);
+    if !duration_matches {
+        failed.push(Facet::Duration);
+    }
+
+    if let Some(min_rating) = request.min_rating {
+        if course.rating < min_rating {
+            failed.push(Facet::Rating);
+        }
+    }
+    if let Some(instructor_id) = request.instructor_id {
+        if course.instructor_id != instructor_id {
+            failed.push(Facet::Instructor);
+        }
+    }
+
+    failed
+}
+
+// Counts values over the courses that pass every filter except the facet's
+// own, so clients can show what selecting another value would return
+fn facet_counts(
+    candidates: &[(SearchHit, Vec<Facet>)],
+    facet: Facet,
+    values: impl Fn(&Course) -> Vec<String>,
+) -> Vec<FacetCount> {
+    let mut counts: HashMap<String, u32> = HashMap::new();
+    for (hit, failed) in candidates {
+        if failed.iter().all(|failed_facet| *failed_facet == facet) {
+            for value in values(&hit.course) {
+                *counts.entry(value).or_insert(0) += 1;
+            }
+        }
+    }
+    let mut facets: Vec<FacetCount> = counts.into_iter()
+        .map(|(value, count)| FacetCount { value, count })
+        .collect();
+    facets.sort_by(|a, b| b.count.cmp(&a.count).then(a.value.cmp(&b.value)));
+    facets
+}
+
+// Ascending sort key for a hit; descending orders are stored inverted
+fn sort_key(hit: &SearchHit, sort: &CourseSort) -> u64 {
+    match sort {
+        CourseSort::Relevance => u64::MAX - hit.score.max(0.0).to_bits() as u64,
+        CourseSort::Newest => u64::MAX - hit.course.created_at,
+        CourseSort::MostPopular => u64::MAX - hit.course.enrollment_count as u64,
+        CourseSort::HighestRated => u64::MAX - hit.course.rating.max(0.0).to_bits() as u64,
+        CourseSort::Shortest => hit.course.estimated_duration_hours as u64,
+    }
+}
+
+// Sorts by relevance when there is a query and by popularity otherwise
+#[query]
+#[candid_method(query)]
+fn search_courses(request: SearchRequest) -> Result<SearchResults> {
+    let query = request.query.as_deref().map(str::trim).filter(|query| !query.is_empty());
+    let scores = query.map(score_query);
+    let sort = match (request.sort.clone(), &scores) {
+        (Some(CourseSort::Relevance), None) | (None, None) => CourseSort::MostPopular,
+        (Some(sort), _) => sort,
+        (None, Some(_)) => CourseSort::Relevance,
+    };
+
+    let candidates: Vec<(SearchHit, Vec<Facet>)> = COURSES.with(|courses| {
+        courses.borrow()
+            .values()
+            .filter(|course| course.is_published)
+            .filter_map(|course| {
+                let score = match scores {
+                    Some(ref scores) => *scores.get(&course.id)?,
+                    None => 0.0,
+                };
+                let failed = failed_filters(course, &request);
+                Some((SearchHit { course: course.clone(), score }, failed))
+            })
+            .collect()
+    });
+
+    let categories = facet_counts(&candidates, Facet::Category, |course| vec![course.category.clone()]);
+    let difficulty_levels = facet_counts(&candidates, Facet::Difficulty, |course| {
+        vec![format!("{:?}", course.difficulty_level)]
+    });
+    let languages = facet_counts(&candidates, Facet::Language, |course| vec![course.language.clone()]);
+    let tags = facet_counts(&candidates, Facet::Tags, |course| normalized_tags(&course.tags));
+    let price_ranges = facet_counts(&candidates, Facet::Price, |course| vec![price_label(course.price)]);
+    let durations = facet_counts(&candidates, Facet::Duration, |course| {
+        vec![duration_label(course.estimated_duration_hours)]
+    });
+    let ratings = facet_counts(&candidates, Facet::Rating, |course| rating_labels(course.rating));
+    let instructors = facet_counts(&candidates, Facet::Instructor, |course| {
+        vec![course.instructor_id.to_text()]
+    });
+
+    let hits: Vec<&SearchHit> = candidates.iter()
+        .filter(|(_, failed)| failed.is_empty())
+        .map(|(hit, _)| hit)
+        .collect();
+    let total = hits.len() as u32;
+    let hits = paginate::<_, ApiError>(
+        hits.into_iter(),
+        |hit| format!("{:020}:{}", sort_key(hit, &sort), hit.course.id),
+        request.cursor.clone(),
+        request.limit,
+    )?;
+
+    Ok(SearchResults {
+        hits,
+        total,
+        categories,
+        difficulty_levels,
+        languages,
+        tags,
+        price_ranges,
+        durations,
+        ratings,
+        instructors,
+    })
+}
+
+// Module Functions
+
+#[update]
+#[candid_method(update)]
+async fn create_module(request: CreateModuleRequest) -> Result<Module> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let mut course = get_instructor_course(&request.course_id, caller_id)?;
+
+    if request.title.trim().is_empty() {
+        return Err(ApiError::InvalidInput("Module title cannot be empty".to_string()));
+    }
+    validate_release_schedule(request.release_schedule.as_ref())?;
+
+    let module_id = generate_module_id();
+    let current_time = get_current_time();
+
+    let module = Module {
+        id: module_id.clone(),
+        course_id: request.course_id,
+        title: request.title,
+        description: request.description,
+        order_index: course.modules.len() as u32,
+        release_schedule: request.release_schedule,
+        lessons: vec![],
+        created_at: current_time,
+        updated_at: current_time,
+    };
+
+    save_module(&module);
+
+    course.modules.push(module_id);
+    course.updated_at = current_time;
+    save_course(&course);
+
+    Ok(module)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_module(module_id: String) -> Result<Module> {
+    MODULES.with(|modules| {
+        modules.borrow().get(&module_id).cloned()
+            .ok_or_else(|| ApiError::NotFound("Module not found".to_string()))
+    })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_course_modules(course_id: String) -> Vec<Module> {
+    let module_ids = match get_course(course_id) {
+        Ok(course) => course.modules,
+        Err(_) => return vec![],
+    };
+
+    MODULES.with(|modules| {
+        let modules = modules.borrow();
+        module_ids.iter()
+            .filter_map(|module_id| modules.get(module_id).cloned())
+            .collect()
+    })
+}
+
+#[update]
+#[candid_method(update)]
+async fn update_module(module_id: String, request: UpdateModuleRequest) -> Result<Module> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let mut module = get_module(module_id)?;
+    get_instructor_course(&module.course_id, caller_id)?;
+
+    if let Some(title) = request.title {
+        if title.trim().is_empty() {
+            return Err(ApiError::InvalidInput("Module title cannot be empty".to_string()));
+        }
+        module.title = title;
+    }
+    if let Some(description) = request.description {
+        module.description = description;
+    }
+    if let Some(release_schedule) = request.release_schedule {
+        validate_release_schedule(release_schedule.as_ref())?;
+        module.release_schedule = release_schedule;
+    }
+
+    module.updated_at = get_current_time();
+    save_module(&module);
+    Ok(module)
+}
+
+#[update]
+#[candid_method(update)]
+async fn move_module(module_id: String, new_position: u32) -> Result<Vec<Module>> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let module = get_module(module_id.clone())?;
+    let mut course = get_instructor_course(&module.course_id, caller_id)?;
+
+    let current_position = course.modules.iter()
+        .position(|id| *id == module_id)
+        .ok_or_else(|| ApiError::InternalError("Module is missing from its course".to_string()))?;
+
+    if new_position as usize >= course.modules.len() {
+        return Err(ApiError::InvalidInput("Module position is out of range".to_string()));
+    }
+
+    let module_id = course.modules.remove(current_position);
+    course.modules.insert(new_position as usize, module_id);
+
+    let unassigned = unassigned_lessons(&course);
+    rebuild_lesson_order(&mut course, unassigned);
+    course.updated_at = get_current_time();
+    save_course(&course);
+    renumber_modules(&course);
+    renumber_lessons(&course);
+
+    Ok(get_course_modules(course.id))
+}
+
+// Deleting a module keeps its lessons; they become unassigned lessons of the course
+#[update]
+#[candid_method(update)]
+async fn delete_module(module_id: String) -> Result<Course> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let module = get_module(module_id.clone())?;
+    let mut course = get_instructor_course(&module.course_id, caller_id)?;
+
+    let current_time = get_current_time();
+    let mut unassigned = unassigned_lessons(&course);
+
+    LESSONS.with(|lessons| {
+        let mut lessons = lessons.borrow_mut();
+        for lesson_id in &module.lessons {
+            if let Some(lesson) = lessons.get_mut(lesson_id) {
+                lesson.module_id = None;
+                lesson.updated_at = current_time;
+            }
+        }
+    });
+    unassigned.extend(module.lessons.iter().cloned());
+
+    MODULES.with(|modules| modules.borrow_mut().remove(&module_id));
+    course.modules.retain(|id| *id != module_id);
+
+    rebuild_lesson_order(&mut course, unassigned);
+    course.updated_at = current_time;
+    save_course(&course);
+    renumber_modules(&course);
+    renumber_lessons(&course);
+
+    Ok(course)
+}
+
+// Works out the sibling lists after moving a lesson to a module (or out of
+// any module) at a position: the module it leaves, the module it joins and
+// the course's unassigned lessons. Nothing is saved, so an invalid position
+// leaves every list untouched.
+fn place_lesson(
+    course: &Course,
+    lesson: &Lesson,
+    module_id: Option<&str>,
+    position: Option<u32>,
+) -> Result<(Option<Module>, Option<Module>, Vec<String>)> {
+    let mut target = match module_id {
+        Some(module_id) => Some(get_course_module(module_id, &course.id)?),
+        None => None,
+    };
+
+    // Detach from the previous module first so the target sibling list is accurate
+    let mut previous = match lesson.module_id {
+        Some(ref previous_id) => Some(get_module(previous_id.clone())?),
+        None => None,
+    };
+    if let Some(ref mut previous) = previous {
+        previous.lessons.retain(|id| *id != lesson.id);
+    }
+    if let Some(ref mut target) = target {
+        if let Some(previous) = previous.take_if(|previous| previous.id == target.id) {
+            target.lessons = previous.lessons;
+        }
+    }
+
+    let mut unassigned = unassigned_lessons(course);
+    unassigned.retain(|id| *id != lesson.id);
+
+    let siblings = match target {
+        Some(ref mut module) => &mut module.lessons,
+        None => &mut unassigned,
+    };
+    let position = match position {
+        Some(position) if position as usize > siblings.len() => {
+            return Err(ApiError::InvalidInput("Lesson position is out of range".to_string()));
+        }
+        Some(position) => position as usize,
+        None => siblings.len(),
+    };
+    siblings.insert(position, lesson.id.clone());
+
+    Ok((previous, target, unassigned))
+}
+
+// Moves a lesson into a module (or out of all modules when `module_id` is None),
+// placing it at `position` within its new siblings or at the end.
+#[update]
+#[candid_method(update)]
+async fn assign_lesson_to_module(
+    lesson_id: String,
+    module_id: Option<String>,
+    position: Option<u32>,
+) -> Result<Lesson> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let lesson = load_lesson(&lesson_id)?;
+    let mut course = get_instructor_course(&lesson.course_id, caller_id)?;
+
+    if lesson.is_archived {
+        return Err(ApiError::InvalidInput("Archived lessons cannot be moved".to_string()));
+    }
+
+    assign_lesson(&mut course, lesson, module_id, position)?;
+    load_lesson(&lesson_id)
+}
+
+fn assign_lesson(course: &mut Course, mut lesson: Lesson, module_id: Option<String>, position: Option<u32>) -> Result<()> {
+    let (previous, target, unassigned) = place_lesson(course, &lesson, module_id.as_deref(), position)?;
+
+    let current_time = get_current_time();
+    for mut module in previous.into_iter().chain(target) {
+        module.updated_at = current_time;
+        save_module(&module);
+    }
+
+    lesson.module_id = module_id;
+    lesson.updated_at = current_time;
+    LESSONS.with(|lessons| lessons.borrow_mut().insert(lesson.id.clone(), lesson));
+
+    rebuild_lesson_order(course, unassigned);
+    course.updated_at = current_time;
+    save_course(course);
+    renumber_lessons(course);
+    Ok(())
+}
+
+#[query]
+#[candid_method(query)]
+fn get_course_outline(course_id: String) -> Result<CourseOutline> {
+    let course = get_course(course_id)?;
+    let viewer = viewer_for(&course, caller());
+
+    let lessons_for = |lesson_ids: &[String]| -> Vec<CourseLesson> {
+        load_lessons(&pinned_lessons(lesson_ids, &viewer.syllabus))
+            .into_iter()
+            .map(|lesson| lesson_view(lesson, &viewer))
+            .collect()
+    };
+
+    let modules = get_course_modules(course.id.clone())
+        .into_iter()
+        .map(|module| {
+            let available_at = schedule_release_time(module.release_schedule.as_ref(), &viewer);
+            ModuleOutline {
+                is_locked: is_locked(available_at, &viewer),
+                available_at,
+                lessons: lessons_for(&module.lessons),
+                module,
+            }
+        })
+        .collect();
+
+    Ok(CourseOutline {
+        course_id: course.id.clone(),
+        title: course.title.clone(),
+        unassigned_lessons: lessons_for(&unassigned_lessons(&course)),
+        modules,
+    })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_module_progress(user_id: Principal, course_id: String) -> Result<Vec<ModuleProgress>> {
+    let enrollment = get_user_enrollment(user_id, course_id.clone())?;
+    let syllabus = version_syllabus(&course_id, enrollment.course_version);
+
+    Ok(get_course_modules(course_id)
+        .into_iter()
+        .map(|module| {
+            let module_lessons = pinned_lessons(&module.lessons, &syllabus);
+            let total_lessons = module_lessons.len() as u32;
+            let completed_lessons = module_lessons.iter()
+                .filter(|lesson_id| enrollment.progress.completed_lessons.contains(lesson_id))
+                .count() as u32;
+            let completion_percentage = if total_lessons == 0 {
+                0.0
+            } else {
+                (completed_lessons as f32) / (total_lessons as f32) * 100.0
+            };
+
+            ModuleProgress {
+                module_id: module.id,
+                title: module.title,
+                completed_lessons,
+                total_lessons,
+                completion_percentage,
+            }
+        })
+        .collect())
+}
+
+// Lesson Management Functions
+
+fn create_lesson(request: CreateLessonRequest, position: Option<u32>) -> Result<Lesson> {
+    let caller_id = caller();
+
+    let mut course = get_instructor_course(&request.course_id, caller_id)?;
+
+    if request.title.trim().is_empty() {
+        return Err(ApiError::InvalidInput("Lesson title cannot be empty".to_string()));
+    }
+    validate_release_schedule(request.release_schedule.as_ref())?;
+
+    // Positions are relative to the target module, or to the unassigned lessons
+    let mut module = match request.module_id {
+        Some(ref module_id) => Some(get_course_module(module_id, &course.id)?),
+        None => None,
+    };
+    let mut unassigned = unassigned_lessons(&course);
+    let siblings = module.as_ref().map(|module| module.lessons.len()).unwrap_or(unassigned.len());
+
+    let position = match position {
+        Some(position) if position as usize > siblings => {
+            return Err(ApiError::InvalidInput("Lesson position is out of range".to_string()));
+        }
+        Some(position) => position as usize,
+        None => siblings,
+    };
+
+    let lesson_id = generate_lesson_id();
+    let current_time = get_current_time();
+
+    let lesson = Lesson {
+        id: lesson_id.clone(),
+        course_id: request.course_id,
+        module_id: request.module_id,
+        title: request.title,
+        description: request.description,
+        content_type: request.content_type,
+        content_url: request.content_url,
+        duration_minutes: request.duration_minutes,
+        order_index: 0, // assigned by renumber_lessons below
+        prerequisites: request.prerequisites,
+        learning_objectives: request.learning_objectives,
+        release_schedule: request.release_schedule,
+        is_archived: false,
+        previous_revision: None,
+        created_at: current_time,
+        updated_at: current_time,
+    };
+
+    LESSONS.with(|lessons| lessons.borrow_mut().insert(lesson_id.clone(), lesson.clone()));
+
+    match module {
+        Some(ref mut module) => {
+            module.lessons.insert(position, lesson_id.clone());
+            module.updated_at = current_time;
+            save_module(module);
+        }
+        None => unassigned.insert(position, lesson_id.clone()),
+    }
+
+    rebuild_lesson_order(&mut course, unassigned);
+    course.updated_at = current_time;
+    save_course(&course);
+    renumber_lessons(&course);
+
+    load_lesson(&lesson_id)
+}
+
+#[update]
+#[candid_method(update)]
+async fn add_lesson(request: CreateLessonRequest) -> Result<Lesson> {
+    ensure_not_frozen()?;
+    create_lesson(request, None)
+}
+
+#[update]
+#[candid_method(update)]
+async fn insert_lesson(request: CreateLessonRequest, position: u32) -> Result<Lesson> {
+    ensure_not_frozen()?;
+    create_lesson(request, Some(position))
+}
+
+#[query]
+#[candid_method(query)]
+fn get_lesson(lesson_id: String) -> Result<CourseLesson> {
+    let lesson = load_lesson(&lesson_id)?;
+    let course = get_course(lesson.course_id.clone())?;
+    Ok(lesson_view(lesson, &viewer_for(&course, caller())))
+}
+
+#[query]
+#[candid_method(query)]
+fn get_course_lessons(course_id: String) -> Vec<CourseLesson> {
+    let course = match get_course(course_id) {
+        Ok(course) => course,
+        Err(_) => return vec![],
+    };
+
+    let viewer = viewer_for(&course, caller());
+    load_lessons(&viewer.syllabus)
+        .into_iter()
+        .map(|lesson| lesson_view(lesson, &viewer))
+        .collect()
+}
+
+#[update]
+#[candid_method(update)]
+async fn update_lesson(lesson_id: String, request: UpdateLessonRequest) -> Result<Lesson> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let mut lesson = load_lesson(&lesson_id)?;
+    let mut course = get_instructor_course(&lesson.course_id, caller_id)?;
+
+    if lesson.is_archived {
+        return Err(ApiError::InvalidInput("Archived lessons cannot be edited".to_string()));
+    }
+
+    if let Some(title) = request.title {
+        if title.trim().is_empty() {
+            return Err(ApiError::InvalidInput("Lesson title cannot be empty".to_string()));
+        }
+        lesson.title = title;
+    }
+    if let Some(description) = request.description {
+        lesson.description = description;
+    }
+    if let Some(content_type) = request.content_type {
+        lesson.content_type = content_type;
+    }
+    if let Some(content_url) = request.content_url {
+        lesson.content_url = content_url;
+    }
+    if let Some(duration_minutes) = request.duration_minutes {
+        lesson.duration_minutes = duration_minutes;
+    }
+    if let Some(prerequisites) = request.prerequisites {
+        lesson.prerequisites = prerequisites;
+    }
+    if let Some(learning_objectives) = request.learning_objectives {
+        lesson.learning_objectives = learning_objectives;
+    }
+    if let Some(release_schedule) = request.release_schedule {
+        validate_release_schedule(release_schedule.as_ref())?;
+        lesson.release_schedule = release_schedule;
+    }
+
+    // Published versions keep the lesson as it was; the edit becomes a new
+    // revision that replaces it in the working syllabus
+    let current_time = get_current_time();
+    if lesson_in_published_version(&course, &lesson_id) {
+        let revision_id = generate_lesson_id();
+        lesson.id = revision_id.clone();
+        lesson.previous_revision = Some(lesson_id.clone());
+        lesson.created_at = current_time;
+
+        if let Some(ref module_id) = lesson.module_id {
+            let mut module = get_module(module_id.clone())?;
+            for id in module.lessons.iter_mut().filter(|id| **id == lesson_id) {
+                *id = revision_id.clone();
+            }
+            module.updated_at = current_time;
+            save_module(&module);
+        }
+        for id in course.lessons.iter_mut().filter(|id| **id == lesson_id) {
+            *id = revision_id.clone();
+        }
+        course.updated_at = current_time;
+        save_course(&course);
+    }
+
+    lesson.updated_at = current_time;
+    LESSONS.with(|lessons| lessons.borrow_mut().insert(lesson.id.clone(), lesson.clone()));
+    reindex_course(&lesson.course_id);
+    Ok(lesson)
+}
+
+#[update]
+#[candid_method(update)]
+async fn move_lesson(lesson_id: String, new_position: u32) -> Result<Vec<Lesson>> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let lesson = load_lesson(&lesson_id)?;
+    let mut course = get_instructor_course(&lesson.course_id, caller_id)?;
+
+    if lesson.is_archived {
+        return Err(ApiError::InvalidInput("Archived lessons cannot be moved".to_string()));
+    }
+
+    reorder_lesson(&mut course, &lesson, new_position)?;
+    Ok(load_lessons(&course.lessons))
+}
+
+// Moves a lesson within its own module, or within the unassigned lessons
+fn reorder_lesson(course: &mut Course, lesson: &Lesson, new_position: u32) -> Result<()> {
+    let mut module = match lesson.module_id {
+        Some(ref module_id) => Some(get_course_module(module_id, &course.id)?),
+        None => None,
+    };
+    let mut unassigned = unassigned_lessons(course);
+    let siblings = match module {
+        Some(ref mut module) => &mut module.lessons,
+        None => &mut unassigned,
+    };
+
+    let current_position = siblings.iter()
+        .position(|id| *id == lesson.id)
+        .ok_or_else(|| ApiError::InternalError("Lesson is missing from its course".to_string()))?;
+
+    if new_position as usize >= siblings.len() {
+        return Err(ApiError::InvalidInput("Lesson position is out of range".to_string()));
+    }
+
+    let lesson_id = siblings.remove(current_position);
+    siblings.insert(new_position as usize, lesson_id);
+
+    let current_time = get_current_time();
+    if let Some(ref mut module) = module {
+        module.updated_at = current_time;
+        save_module(module);
+    }
+
+    rebuild_lesson_order(course, unassigned);
+    course.updated_at = current_time;
+    save_course(course);
+    renumber_lessons(course);
+    Ok(())
+}
+
+#[update]
+#[candid_method(update)]
+async fn archive_lesson(lesson_id: String) -> Result<Lesson> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let mut lesson = load_lesson(&lesson_id)?;
+    let mut course = get_instructor_course(&lesson.course_id, caller_id)?;
+
+    if lesson.is_archived {
+        return Err(ApiError::InvalidInput("Lesson is already archived".to_string()));
+    }
+
+    retire_lesson(&mut course, &mut lesson)?;
+    Ok(lesson)
+}
+
+// Archives a lesson and takes it out of the course, recalculating every
+// enrollment's progress without it
+fn retire_lesson(course: &mut Course, lesson: &mut Lesson) -> Result<()> {
+    let lesson_id = lesson.id.clone();
+
+    // Earlier revisions are retired from the versions that pin them too
+    let current_time = get_current_time();
+    lesson.is_archived = true;
+    lesson.updated_at = current_time;
+    let lineage = lesson_lineage(&lesson_id);
+    LESSONS.with(|lessons| {
+        let mut lessons = lessons.borrow_mut();
+        for revision_id in lineage {
+            if let Some(revision) = lessons.get_mut(&revision_id) {
+                revision.is_archived = true;
+                revision.updated_at = current_time;
+            }
+        }
+        lessons.insert(lesson_id.clone(), lesson.clone());
+    });
+
+    if let Some(ref module_id) = lesson.module_id {
+        let mut module = get_module(module_id.clone())?;
+        module.lessons.retain(|id| *id != lesson_id);
+        module.updated_at = current_time;
+        save_module(&module);
+    }
+
+    course.lessons.retain(|id| *id != lesson_id);
+    course.updated_at = current_time;
+    save_course(course);
+    renumber_lessons(course);
+    recalculate_course_progress(course);
+    Ok(())
+}
+
+// Payment Functions
+
+const DEFAULT_PLATFORM_FEE_BPS: u16 = 1_000; // 10%
+const DEFAULT_REFUND_WINDOW_DAYS: u32 = 14;
+const DEFAULT_REFUND_MAX_COMPLETION: f32 = 20.0;
+const MAX_BPS: u64 = 10_000;
+
+fn payment_config() -> PaymentConfig {
+    PAYMENT_CONFIG.with(|config| config.borrow().clone())
+}
+
+fn payment_ledger() -> Result<Principal> {
+    payment_config().ledger_canister_id
+        .ok_or_else(|| ApiError::InternalError("Payment ledger is not configured".to_string()))
+}
+
+// Splits a payment into the instructor's share and the platform fee
+fn split_payment(amount: u64, platform_fee_bps: u16) -> (u64, u64) {
+    let platform_fee = (amount as u128 * platform_fee_bps as u128 / MAX_BPS as u128) as u64;
+    (amount - platform_fee, platform_fee)
+}
+
+fn save_payment(payment: &Payment) {
+    PAYMENTS.with(|payments| payments.borrow_mut().insert(payment.id.clone(), payment.clone()));
+}
+
+// Credits every held payment whose refund window has closed to the
+// instructor's and platform's withdrawable balances
+fn settle_payments() {
+    let current_time = get_current_time();
+    PAYMENTS.with(|payments| {
+        for payment in payments.borrow_mut().values_mut() {
+            if payment.status != PaymentStatus::Held || payment.settles_at > current_time {
+                continue;
+            }
+            payment.status = PaymentStatus::Settled;
+            EARNINGS.with(|earnings| {
+                *earnings.borrow_mut().entry(payment.instructor_id).or_insert(0) += payment.instructor_share;
+            });
+            credit_platform(payment.platform_fee);
+        }
+    });
+}
+
+// Takes the per-enrollment lock that keeps a learner's payments and refunds
+// for a course from running concurrently
+fn lock_enrollment(user_id: &Principal, course_id: &str) -> Result<String> {
+    let lock_key = enrollment_key(user_id, course_id);
+    if !PENDING_PAYMENTS.with(|pending| pending.borrow_mut().insert(lock_key.clone())) {
+        return Err(ApiError::InvalidInput("A payment for this course is already in progress".to_string()));
+    }
+    Ok(lock_key)
+}
+
+fn unlock_enrollment(lock_key: &str) {
+    PENDING_PAYMENTS.with(|pending| pending.borrow_mut().remove(lock_key));
+}
+
+// Adds to the platform's withdrawable balance, first paying back any ledger
+// fees it owes for refunds
+fn credit_platform(amount: u64) {
+    let repaid = PLATFORM_FEES_OWED.with(|owed| {
+        let mut owed = owed.borrow_mut();
+        let repaid = amount.min(*owed);
+        *owed -= repaid;
+        repaid
+    });
+    PLATFORM_EARNINGS.with(|earnings| *earnings.borrow_mut() += amount - repaid);
+}
+
+// Charges the platform for a ledger fee, taking it from the withdrawable
+// balance and carrying whatever that does not cover to future earnings
+fn debit_platform(amount: u64) {
+    let covered = PLATFORM_EARNINGS.with(|earnings| {
+        let mut earnings = earnings.borrow_mut();
+        let covered = amount.min(*earnings);
+        *earnings -= covered;
+        covered
+    });
+    PLATFORM_FEES_OWED.with(|owed| *owed.borrow_mut() += amount - covered);
+}
+
+async fn ledger_fee(ledger: Principal) -> Result<u64> {
+    let (fee,): (Nat,) = ic_cdk::call(ledger, "icrc1_fee", ())
+        .await
+        .map_err(|(_, message)| ApiError::PaymentFailed(message))?;
+    u64::try_from(fee.0)
+        .map_err(|_| ApiError::PaymentFailed("Ledger fee is out of range".to_string()))
+}
+
+// Pulls the course price from the learner's account into this canister with
+// ICRC-2 transfer_from (the learner must have approved us beforehand), then
+// holds it until the refund window closes. The caller holds the enrollment
+// lock. A price that does not cover the ledger fee cannot be collected.
+async fn collect_payment(user_id: Principal, course: &Course, run_id: Option<String>) -> Result<Payment> {
+    let config = payment_config();
+    let ledger = payment_ledger()?;
+
+    if course.price <= ledger_fee(ledger).await? {
+        return Err(ApiError::InvalidInput("Course price does not cover the ledger fee".to_string()));
+    }
+
+    let payment_id = generate_payment_id();
+    let args = TransferFromArgs {
+        spender_subaccount: None,
+        from: Account { owner: user_id, subaccount: None },
+        to: Account { owner: ic_cdk::id(), subaccount: None },
+        amount: Nat::from(course.price),
+        fee: None,
+        memo: Some(memo_for(&payment_id)),
+        created_at_time: Some(get_current_time()),
+    };
+    let result: std::result::Result<(std::result::Result<Nat, TransferFromError>,), _> =
+        ic_cdk::call(ledger, "icrc2_transfer_from", (args,)).await;
+
+    let block_index = match result {
+        Ok((Ok(block_index),)) => block_index,
+        Ok((Err(error),)) => return Err(ApiError::PaymentFailed(describe_transfer_from_error(error))),
+        Err((_, message)) => return Err(ApiError::PaymentFailed(message)),
+    };
+
+    let (instructor_share, platform_fee) = split_payment(course.price, config.platform_fee_bps);
+    let paid_at = get_current_time();
+    let payment = Payment {
+        id: payment_id,
+        user_id,
+        course_id: course.id.clone(),
+        run_id,
+        instructor_id: course.instructor_id,
+        amount: course.price,
+        instructor_share,
+        platform_fee,
+        block_index,
+        paid_at,
+        settles_at: paid_at + days_to_nanoseconds(config.refund_window_days as u64),
+        status: PaymentStatus::Held,
+    };
+    save_payment(&payment);
+    Ok(payment)
+}
+
+// Sends funds held by this canister to an account, less the ledger fee
+async fn pay_out(to: Account, amount: u64, memo: Vec<u8>) -> Result<Nat> {
+    let ledger = payment_ledger()?;
+
+    let fee = ledger_fee(ledger).await?;
+    if amount <= fee {
+        return Err(ApiError::InvalidInput("Balance does not cover the ledger fee".to_string()));
+    }
+    transfer(ledger, to, amount - fee, fee, memo).await
+}
+
+async fn transfer(ledger: Principal, to: Account, amount: u64, fee: u64, memo: Vec<u8>) -> Result<Nat> {
+    let args = TransferArg {
+        from_subaccount: None,
+        to,
+        amount: Nat::from(amount),
+        fee: Some(Nat::from(fee)),
+        memo: Some(memo),
+        created_at_time: Some(get_current_time()),
+    };
+    let result: std::result::Result<(std::result::Result<Nat, TransferError>,), _> =
+        ic_cdk::call(ledger, "icrc1_transfer", (args,)).await;
+    match result {
+        Ok((Ok(block_index),)) => Ok(block_index),
+        Ok((Err(error),)) => Err(ApiError::PaymentFailed(describe_transfer_error(error))),
+        Err((_, message)) => Err(ApiError::PaymentFailed(message)),
+    }
+}
+
+// A payment can be refunded while it is still held inside its refund window,
+// and only for the enrollment it paid for: the same run, or self-paced
+fn is_refundable(payment: &Payment, run_id: Option<&str>, current_time: u64) -> bool {
+    payment.run_id.as_deref() == run_id
+        && payment.status == PaymentStatus::Held
+        && current_time < payment.settles_at
+}
+
+// The learner's most recent payment for an enrollment, if it is refundable
+// and they have completed little enough of the course
+fn refundable_payment(
+    user_id: &Principal,
+    course_id: &str,
+    run_id: Option<&str>,
+    completion_percentage: f32,
+) -> Option<Payment> {
+    if completion_percentage >= payment_config().refund_max_completion {
+        return None;
+    }
+
+    let current_time = get_current_time();
+    PAYMENTS.with(|payments| {
+        payments.borrow()
+            .values()
+            .filter(|payment| payment.user_id == *user_id && payment.course_id == course_id)
+            .filter(|payment| is_refundable(payment, run_id, current_time))
+            .max_by_key(|payment| payment.paid_at)
+            .cloned()
+    })
+}
+
+// Refunds the learner's payment for an enrollment when the policy allows it
+async fn refund_if_eligible(
+    user_id: Principal,
+    course_id: &str,
+    run_id: Option<&str>,
+    completion_percentage: f32,
+) -> Result<Option<Payment>> {
+    match refundable_payment(&user_id, course_id, run_id, completion_percentage) {
+        Some(payment) => refund_payment(payment).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+// Returns a held payment to the learner in full. The ledger fee for the
+// refund is charged to the platform; the fees the learner paid to approve and
+// transfer the payment are not returned. The caller holds the enrollment
+// lock, and a refunded payment can never be refunded again.
+async fn refund_payment(mut payment: Payment) -> Result<Payment> {
+    let ledger = payment_ledger()?;
+
+    // Keep the payment from settling while the transfer is in flight
+    payment.status = PaymentStatus::Refunding;
+    save_payment(&payment);
+
+    let to = Account { owner: payment.user_id, subaccount: None };
+    let result = match ledger_fee(ledger).await {
+        Ok(fee) => transfer(ledger, to, payment.amount, fee, memo_for(&payment.id)).await.map(|block_index| (block_index, fee)),
+        Err(error) => Err(error),
+    };
+
+    match result {
+        Ok((block_index, fee)) => {
+            debit_platform(fee);
+            payment.status = PaymentStatus::Refunded { refunded_at: get_current_time(), block_index };
+            save_payment(&payment);
+            Ok(payment)
+        }
+        Err(error) => {
+            payment.status = PaymentStatus::Held;
+            save_payment(&payment);
+            Err(error)
+        }
+    }
+}
+
+// Charges the learner for a paid course and enrolls them. If the enrollment
+// cannot be created once the ledger call has returned, the charge is refunded.
+// Holds the enrollment lock throughout, so concurrent calls cannot charge twice.
+async fn charge_and_enroll(user_id: Principal, course: &Course, run_id: Option<String>) -> Result<Enrollment> {
+    let lock_key = lock_enrollment(&user_id, &course.id)?;
+    let result = charge_and_enroll_locked(user_id, course, run_id).await;
+    unlock_enrollment(&lock_key);
+    result
+}
+
+async fn charge_and_enroll_locked(user_id: Principal, course: &Course, run_id: Option<String>) -> Result<Enrollment> {
+    let payment = if course.price > 0 {
+        Some(collect_payment(user_id, course, run_id.clone()).await?)
+    } else {
+        None
+    };
+
+    // Re-check after the ledger call in case the learner enrolled meanwhile
+    let result = if is_enrolled(&user_id, &course.id) {
+        Err(ApiError::AlreadyExists("Already enrolled in this course".to_string()))
+    } else {
+        create_enrollment(user_id, &course.id, run_id)
+    };
+
+    match (result, payment) {
+        (Err(error), Some(payment)) => match refund_payment(payment).await {
+            Ok(_) => Err(error),
+            Err(refund_error) => Err(ApiError::PaymentFailed(format!(
+                "Enrollment failed ({:?}) and the refund did not go through: {:?}",
+                error, refund_error
+            ))),
+        },
+        (result, _) => result,
+    }
+}
+
+#[update]
+#[candid_method(update)]
+async fn set_payment_config(config: PaymentConfig) -> Result<PaymentConfig> {
+    if !ic_cdk::api::is_controller(&caller()) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    if config.platform_fee_bps as u64 > MAX_BPS {
+        return Err(ApiError::InvalidInput("Platform fee cannot exceed 10000 basis points".to_string()));
+    }
+
+    if !(0.0..=100.0).contains(&config.refund_max_completion) {
+        return Err(ApiError::InvalidInput("Refund completion limit must be between 0 and 100".to_string()));
+    }
+
+    PAYMENT_CONFIG.with(|current| *current.borrow_mut() = config.clone());
+    Ok(config)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_payment_config() -> PaymentConfig {
+    payment_config()
+}
+
+// Withdrawable earnings, including held payments whose refund window has
+// closed but that have not been settled yet
+#[query]
+#[candid_method(query)]
+fn get_earnings(instructor_id: Principal) -> u64 {
+    let current_time = get_current_time();
+    let settled = EARNINGS.with(|earnings| earnings.borrow().get(&instructor_id).copied().unwrap_or(0));
+    let matured: u64 = PAYMENTS.with(|payments| {
+        payments.borrow()
+            .values()
+            .filter(|payment| payment.instructor_id == instructor_id)
+            .filter(|payment| payment.status == PaymentStatus::Held && payment.settles_at <= current_time)
+            .map(|payment| payment.instructor_share)
+            .sum()
+    });
+    settled + matured
+}
+
+#[query]
+#[candid_method(query)]
+fn get_user_payments(user_id: Principal, cursor: Option<String>, limit: Option<u32>) -> Result<Page<Payment>> {
+    // Newest payments first
+    PAYMENTS.with(|payments| {
+        paginate(
+            payments.borrow().values().filter(|payment| payment.user_id == user_id),
+            |payment| format!("{:020}:{}", u64::MAX - payment.paid_at, payment.id),
+            cursor,
+            limit,
+        )
+    })
+}
+
+// Withdraws the caller's accumulated instructor earnings. The balance is
+// cleared before the ledger call and restored if the transfer fails.
+#[update]
+#[candid_method(update)]
+async fn withdraw_earnings(to: Option<Account>) -> Result<Nat> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    let to = to.unwrap_or(Account { owner: caller_id, subaccount: None });
+
+    settle_payments();
+    let amount = EARNINGS.with(|earnings| earnings.borrow_mut().remove(&caller_id).unwrap_or(0));
+    if amount == 0 {
+        return Err(ApiError::InvalidInput("No earnings to withdraw".to_string()));
+    }
+
+    let result = pay_out(to, amount, memo_for("earnings")).await;
+    if result.is_err() {
+        EARNINGS.with(|earnings| *earnings.borrow_mut().entry(caller_id).or_insert(0) += amount);
+    }
+    result
+}
+
+#[update]
+#[candid_method(update)]
+async fn withdraw_platform_earnings() -> Result<Nat> {
+    ensure_not_frozen()?;
+    if !ic_cdk::api::is_controller(&caller()) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    let to = payment_config().platform_account
+        .ok_or_else(|| ApiError::InvalidInput("Platform account is not configured".to_string()))?;
+
+    settle_payments();
+    let amount = PLATFORM_EARNINGS.with(|earnings| std::mem::take(&mut *earnings.borrow_mut()));
+    if amount == 0 {
+        return Err(ApiError::InvalidInput("No earnings to withdraw".to_string()));
+    }
+
+    let result = pay_out(to, amount, memo_for("platform_earnings")).await;
+    if result.is_err() {
+        PLATFORM_EARNINGS.with(|earnings| *earnings.borrow_mut() += amount);
+    }
+    result
+}
+
+// Enrollment Functions
+
+#[update]
+#[candid_method(update)]
+async fn enroll_in_course(course_id: String) -> Result<Enrollment> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let course = get_course(course_id.clone())?;
+    if !course.is_published {
+        return Err(ApiError::InvalidInput("Course is not published".to_string()));
+    }
+
+    if has_course_runs(&course_id) {
+        return Err(ApiError::InvalidInput("This course is taught in cohorts; enroll in a course run".to_string()));
+    }
+
+    if is_enrolled(&caller_id, &course_id) {
+        return Err(ApiError::AlreadyExists("Already enrolled in this course".to_string()));
+    }
+
+    charge_and_enroll(caller_id, &course, None).await
+}
+
+// Refunds and archives an enrollment in the given run, or a self-paced one.
+// The enrollment lock is taken before the payment is looked up, so a second
+// request made while a refund is in flight is rejected instead of archiving
+// the enrollment or paying out twice.
+async fn cancel_enrollment(user_id: Principal, course_id: &str, run_id: Option<&str>) -> Result<ArchivedEnrollment> {
+    let lock_key = lock_enrollment(&user_id, course_id)?;
+    let result = cancel_enrollment_locked(user_id, course_id, run_id).await;
+    unlock_enrollment(&lock_key);
+    result
+}
+
+async fn cancel_enrollment_locked(user_id: Principal, course_id: &str, run_id: Option<&str>) -> Result<ArchivedEnrollment> {
+    let enrollment = get_user_enrollment(user_id, course_id.to_string())?;
+    match (enrollment.run_id.as_deref(), run_id) {
+        (Some(_), None) => {
+            return Err(ApiError::InvalidInput("Cohort enrollments are cancelled with withdraw_from_run".to_string()));
+        }
+        (enrolled_run, Some(run_id)) if enrolled_run != Some(run_id) => {
+            return Err(ApiError::NotFound("Not enrolled in this run".to_string()));
+        }
+        _ => {}
+    }
+
+    let refund = refund_if_eligible(user_id, course_id, run_id, enrollment.completion_percentage).await?;
+    archive_enrollment(user_id, course_id, refund)
+}
+
+// Cancels the caller's self-paced enrollment. Inside the refund window, and
+// while less than the configured share of the course is complete, the price
+// is refunded in full; after that nothing is refunded.
+#[update]
+#[candid_method(update)]
+async fn unenroll(course_id: String) -> Result<ArchivedEnrollment> {
+    ensure_not_frozen()?;
+    cancel_enrollment(caller(), &course_id, None).await
+}
+
+#[query]
+#[candid_method(query)]
+fn get_archived_enrollments(user_id: Principal, cursor: Option<String>, limit: Option<u32>) -> Result<Page<ArchivedEnrollment>> {
+    // Most recently cancelled first
+    ARCHIVED_ENROLLMENTS.with(|archive| {
+        paginate(
+            archive.borrow().iter().filter(|archived| archived.enrollment.user_id == user_id),
+            |archived| {
+                format!("{:020}:{}", u64::MAX - archived.archived_at, archived.enrollment.course_id)
+            },
+            cursor,
+            limit,
+        )
+    })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_user_enrollment(user_id: Principal, course_id: String) -> Result<Enrollment> {
+    let enrollment_id = enrollment_key(&user_id, &course_id);
+    ENROLLMENTS.with(|enrollments| {
+        enrollments.borrow().get(&enrollment_id).cloned()
+            .ok_or_else(|| ApiError::NotFound("Enrollment not found".to_string()))
+    })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_user_enrollments(user_id: Principal, cursor: Option<String>, limit: Option<u32>) -> Result<Page<Enrollment>> {
+    ENROLLMENTS.with(|enrollments| {
+        paginate(
+            enrollments.borrow().values().filter(|enrollment| enrollment.user_id == user_id),
+            |enrollment| format!("{:020}:{}", enrollment.enrolled_at, enrollment.course_id),
+            cursor,
+            limit,
+        )
+    })
+}
+
+#[update]
+#[candid_method(update)]
+async fn mark_lesson_complete(course_id: String, lesson_id: String) -> Result<Enrollment> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    let enrollment_id = enrollment_key(&caller_id, &course_id);
+
+    let course = get_course(course_id)?;
+    let viewer = viewer_for(&course, caller_id);
+    if viewer.enrolled_at.is_none() {
+        return Err(ApiError::NotFound("Enrollment not found".to_string()));
+    }
+
+    // Completion is tracked against the syllabus version the learner is pinned to
+    if !viewer.syllabus.contains(&lesson_id) {
+        return Err(ApiError::InvalidInput("Lesson is not part of your course syllabus".to_string()));
+    }
+
+    let lesson = load_lesson(&lesson_id)?;
+    if is_locked(lesson_release_time(&lesson, &viewer), &viewer) {
+        return Err(ApiError::InvalidInput("Lesson has not been released yet".to_string()));
+    }
+
+    ENROLLMENTS.with(|enrollments| {
+        let mut enrollments = enrollments.borrow_mut();
+        match enrollments.get_mut(&enrollment_id) {
+            Some(enrollment) => {
+                if !enrollment.progress.completed_lessons.contains(&lesson_id) {
+                    enrollment.progress.completed_lessons.push(lesson_id);
+                }
+                enrollment.last_accessed = get_current_time();
+                enrollment.completion_percentage = calculate_completion(&enrollment.progress, &viewer.syllabus);
+                Ok(enrollment.clone())
+            }
+            None => Err(ApiError::NotFound("Enrollment not found".to_string()))
+        }
+    })
+}
+
+// Moves the caller's enrollment onto the latest published version of the course.
+// Completed lessons that are still part of the new syllabus keep counting.
+#[update]
+#[candid_method(update)]
+async fn migrate_enrollment(course_id: String) -> Result<Enrollment> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    let enrollment_id = enrollment_key(&caller_id, &course_id);
+
+    let course = get_course(course_id.clone())?;
+    let syllabus = version_syllabus(&course_id, course.version);
+
+    ENROLLMENTS.with(|enrollments| {
+        let mut enrollments = enrollments.borrow_mut();
+        match enrollments.get_mut(&enrollment_id) {
+            Some(enrollment) => {
+                if enrollment.course_version == course.version {
+                    return Err(ApiError::InvalidInput("Already on the latest course version".to_string()));
+                }
+
+                // Revisions of lessons the learner already completed stay completed
+                for lesson_id in &syllabus {
+                    let completed = &enrollment.progress.completed_lessons;
+                    if !completed.contains(lesson_id)
+                        && lesson_lineage(lesson_id).iter().any(|revision| completed.contains(revision))
+                    {
+                        enrollment.progress.completed_lessons.push(lesson_id.clone());
+                    }
+                }
+                enrollment.course_version = course.version;
+                enrollment.last_accessed = get_current_time();
+                enrollment.completion_percentage = calculate_completion(&enrollment.progress, &syllabus);
+                Ok(enrollment.clone())
+            }
+            None => Err(ApiError::NotFound("Enrollment not found".to_string()))
+        }
+    })
+}
+
+// Course Run Functions
+
+#[update]
+#[candid_method(update)]
+async fn create_course_run(request: CreateCourseRunRequest) -> Result<CourseRun> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    get_instructor_course(&request.course_id, caller_id)?;
+
+    if request.title.trim().is_empty() {
+        return Err(ApiError::InvalidInput("Run title cannot be empty".to_string()));
+    }
+
+    if request.start_date >= request.end_date {
+        return Err(ApiError::InvalidInput("Run must start before it ends".to_string()));
+    }
+
+    if request.enrollment_opens_at >= request.enrollment_closes_at {
+        return Err(ApiError::InvalidInput("Enrollment window must open before it closes".to_string()));
+    }
+
+    if request.enrollment_closes_at > request.end_date {
+        return Err(ApiError::InvalidInput("Enrollment must close before the run ends".to_string()));
+    }
+
+    if request.capacity == 0 {
+        return Err(ApiError::InvalidInput("Run capacity must be at least 1".to_string()));
+    }
+
+    let current_time = get_current_time();
+    let run = CourseRun {
+        id: generate_course_run_id(),
+        course_id: request.course_id,
+        title: request.title,
+        start_date: request.start_date,
+        end_date: request.end_date,
+        enrollment_opens_at: request.enrollment_opens_at,
+        enrollment_closes_at: request.enrollment_closes_at,
+        capacity: request.capacity,
+        enrollment_count: 0,
+        waitlist: vec![],
+        created_at: current_time,
+        updated_at: current_time,
+    };
+
+    save_course_run(&run);
+    Ok(run)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_course_run(run_id: String) -> Result<CourseRun> {
+    COURSE_RUNS.with(|runs| {
+        runs.borrow().get(&run_id).cloned()
+            .ok_or_else(|| ApiError::NotFound("Course run not found".to_string()))
+    })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_course_runs(course_id: String, cursor: Option<String>, limit: Option<u32>) -> Result<Page<CourseRun>> {
+    COURSE_RUNS.with(|runs| {
+        paginate(
+            runs.borrow().values().filter(|run| run.course_id == course_id),
+            |run| format!("{:020}:{}", run.start_date, run.id),
+            cursor,
+            limit,
+        )
+    })
+}
+
+// Raising the capacity immediately promotes learners from the waitlist
+#[update]
+#[candid_method(update)]
+async fn update_run_capacity(run_id: String, capacity: u32) -> Result<CourseRun> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let mut run = get_course_run(run_id)?;
+    get_instructor_course(&run.course_id, caller_id)?;
+
+    if capacity < run.enrollment_count {
+        return Err(ApiError::InvalidInput("Capacity cannot be lower than current enrollment".to_string()));
+    }
+
+    run.capacity = capacity;
+    run.updated_at = get_current_time();
+    save_course_run(&run);
+    promote_waitlist(&run.id).await
+}
+
+#[update]
+#[candid_method(update)]
+async fn enroll_in_run(run_id: String) -> Result<RunEnrollmentStatus> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let mut run = get_course_run(run_id)?;
+    let course = get_course(run.course_id.clone())?;
+    if !course.is_published {
+        return Err(ApiError::InvalidInput("Course is not published".to_string()));
+    }
+
+    let current_time = get_current_time();
+    if current_time < run.enrollment_opens_at || current_time > run.enrollment_closes_at {
+        return Err(ApiError::InvalidInput("Enrollment for this run is closed".to_string()));
+    }
+
+    if is_enrolled(&caller_id, &run.course_id) {
+        return Err(ApiError::AlreadyExists("Already enrolled in this course".to_string()));
+    }
+
+    if run.waitlist.contains(&caller_id) {
+        return Err(ApiError::AlreadyExists("Already on the waitlist for this run".to_string()));
+    }
+
+    // Waitlisted learners are only charged once they are promoted to a seat
+    if run.enrollment_count >= run.capacity {
+        run.waitlist.push(caller_id);
+        run.updated_at = current_time;
+        save_course_run(&run);
+        return Ok(RunEnrollmentStatus::Waitlisted { position: run.waitlist.len() as u32 });
+    }
+
+    // Hold the seat while the learner is being charged
+    run.enrollment_count += 1;
+    run.updated_at = current_time;
+    save_course_run(&run);
+
+    match charge_and_enroll(caller_id, &course, Some(run.id.clone())).await {
+        Ok(enrollment) => Ok(RunEnrollmentStatus::Enrolled(Box::new(enrollment))),
+        Err(error) => {
+            release_run_seat(&run.id)?;
+            promote_waitlist(&run.id).await?;
+            Err(error)
+        }
+    }
+}
+
+// Leaves a run's waitlist, or gives up a seat which then goes to the next
+// learner on the waitlist. Nobody on the waitlist has paid yet; seat payments
+// are refunded under the same policy as unenroll, and a failed refund leaves
+// the seat untouched.
+#[update]
+#[candid_method(update)]
+async fn withdraw_from_run(run_id: String) -> Result<CourseRun> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let mut run = get_course_run(run_id)?;
+
+    if run.waitlist.contains(&caller_id) {
+        run.waitlist.retain(|user_id| *user_id != caller_id);
+        run.updated_at = get_current_time();
+        save_course_run(&run);
+        return Ok(run);
+    }
+
+    cancel_enrollment(caller_id, &run.course_id, Some(&run.id)).await?;
+    release_run_seat(&run.id)?;
+    promote_waitlist(&run.id).await
+}
+
+#[query]
+#[candid_method(query)]
+fn get_run_enrollments(run_id: String, cursor: Option<String>, limit: Option<u32>) -> Result<Page<Enrollment>> {
+    let run = get_course_run(run_id)?;
+    get_instructor_course(&run.course_id, caller())?;
+
+    ENROLLMENTS.with(|enrollments| {
+        paginate(
+            enrollments.borrow().values().filter(|enrollment| enrollment.run_id.as_ref() == Some(&run.id)),
+            |enrollment| format!("{:020}:{}", enrollment.enrolled_at, enrollment.user_id),
+            cursor,
+            limit,
+        )
+    })
+}
+
+// Discussion Functions
+
+fn get_discussion(thread_id: &str) -> Result<DiscussionThread> {
+    DISCUSSIONS.with(|discussions| {
+        discussions.borrow().get(thread_id).cloned()
+            .ok_or_else(|| ApiError::NotFound("Discussion thread not found".to_string()))
+    })
+}
+
+fn is_shown_to(visibility: &ContentVisibility, author_id: &Principal, viewer_id: &Principal) -> bool {
+    match visibility {
+        ContentVisibility::Visible => true,
+        ContentVisibility::Hidden => author_id == viewer_id,
+        ContentVisibility::Deleted => false,
+    }
+}
+
+// Hidden threads and replies keep their text for the author only
+fn thread_view(mut thread: DiscussionThread, viewer_id: &Principal) -> DiscussionThread {
+    if !is_shown_to(&thread.visibility, &thread.author_id, viewer_id) {
+        thread.title = String::new();
+        thread.content = String::new();
+    }
+
+    for reply in thread.replies.iter_mut() {
+        if !is_shown_to(&reply.visibility, &reply.author_id, viewer_id) {
+            reply.content = String::new();
+        }
+    }
+    thread
+}
+
+fn save_discussion(thread: &DiscussionThread) {
+    DISCUSSIONS.with(|discussions| discussions.borrow_mut().insert(thread.id.clone(), thread.clone()));
+}
+
+fn discussion_vote_key(target_id: &str, user_id: &Principal) -> String {
+    format!("{}_{}", target_id, user_id.to_text())
+}
+
+// Instructors and enrolled learners can post in a course's discussions
+fn can_participate(course: &Course, user_id: &Principal) -> bool {
+    course.instructor_id == *user_id || is_enrolled(user_id, &course.id)
+}
+
+async fn can_moderate(course: &Course, user_id: Principal) -> bool {
+    course.instructor_id == user_id || is_user_moderator(user_id).await
+}
+
+// Records the caller's vote on a thread or reply (None retracts it) and adjusts
+// the counters, so each principal contributes at most one vote per item.
+fn apply_discussion_vote(
+    target_id: &str,
+    user_id: &Principal,
+    vote: Option<DiscussionVote>,
+    upvotes: &mut u32,
+    downvotes: &mut u32,
+) {
+    let vote_key = discussion_vote_key(target_id, user_id);
+    let previous = DISCUSSION_VOTES.with(|votes| {
+        let mut votes = votes.borrow_mut();
+        match vote {
+            Some(ref vote) => votes.insert(vote_key, vote.clone()),
+            None => votes.remove(&vote_key),
+        }
+    });
+
+    match previous {
+        Some(DiscussionVote::Upvote) => *upvotes = upvotes.saturating_sub(1),
+        Some(DiscussionVote::Downvote) => *downvotes = downvotes.saturating_sub(1),
+        None => {}
+    }
+    match vote {
+        Some(DiscussionVote::Upvote) => *upvotes += 1,
+        Some(DiscussionVote::Downvote) => *downvotes += 1,
+        None => {}
+    }
+}
+
+#[update]
+#[candid_method(update)]
+async fn create_thread(request: CreateThreadRequest) -> Result<DiscussionThread> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let course = get_course(request.course_id.clone())?;
+    if !can_participate(&course, &caller_id) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    if let Some(ref lesson_id) = request.lesson_id {
+        if load_lesson(lesson_id)?.course_id != course.id {
+            return Err(ApiError::InvalidInput("Lesson does not belong to this course".to_string()));
+        }
+    }
+
+    if request.title.trim().is_empty() {
+        return Err(ApiError::InvalidInput("Thread title cannot be empty".to_string()));
+    }
+
+    if request.content.trim().is_empty() {
+        return Err(ApiError::InvalidInput("Thread content cannot be empty".to_string()));
+    }
+
+    let run_id = get_user_enrollment(caller_id, course.id.clone())
+        .ok()
+        .and_then(|enrollment| enrollment.run_id);
+
+    let current_time = get_current_time();
+    let thread = DiscussionThread {
+        id: generate_discussion_id(),
+        course_id: request.course_id,
+        lesson_id: request.lesson_id,
+        run_id,
+        author_id: caller_id,
+        title: request.title,
+        content: request.content,
+        created_at: current_time,
+        updated_at: current_time,
+        last_activity_at: current_time,
+        replies: vec![],
+        tags: request.tags,
+        upvotes: 0,
+        downvotes: 0,
+        is_pinned: false,
+        is_locked: false,
+        visibility: ContentVisibility::Visible,
+    };
+
+    save_discussion(&thread);
+    Ok(thread)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_thread(thread_id: String) -> Result<DiscussionThread> {
+    let thread = get_discussion(&thread_id)?;
+    if thread.visibility == ContentVisibility::Deleted {
+        return Err(ApiError::NotFound("Discussion thread not found".to_string()));
+    }
+    Ok(thread_view(thread, &caller()))
+}
+
+// Pinned threads always come first, then the requested order. Passing a lesson
+// narrows the listing to that lesson, and a run narrows it to one cohort.
+#[query]
+#[candid_method(query)]
+fn list_threads(
+    course_id: String,
+    lesson_id: Option<String>,
+    run_id: Option<String>,
+    sort: Option<ThreadSort>,
+    cursor: Option<String>,
+    limit: Option<u32>,
+) -> Result<Page<DiscussionThread>> {
+    let sort = sort.unwrap_or(ThreadSort::Activity);
+    let caller_id = caller();
+
+    // Pinned threads first, then the chosen order with newer activity breaking ties
+    let thread_key = |thread: &DiscussionThread| {
+        let pinned = if thread.is_pinned { 0 } else { 1 };
+        let primary = match sort {
+            ThreadSort::Newest => u64::MAX - thread.created_at,
+            ThreadSort::Activity => u64::MAX - thread.last_activity_at,
+            ThreadSort::Score => u32::MAX as u64 - thread.upvotes as u64 + thread.downvotes as u64,
+        };
+        format!("{}:{:020}:{:020}:{}", pinned, primary, u64::MAX - thread.last_activity_at, thread.id)
+    };
+
+    let page = DISCUSSIONS.with(|discussions| {
+        paginate::<_, ApiError>(
+            discussions.borrow()
+                .values()
+                .filter(|thread| thread.course_id == course_id)
+                .filter(|thread| thread.visibility != ContentVisibility::Deleted)
+                .filter(|thread| lesson_id.is_none() || thread.lesson_id == lesson_id)
+                .filter(|thread| run_id.is_none() || thread.run_id == run_id),
+            thread_key,
+            cursor,
+            limit,
+        )
+    })?;
+    Ok(page.map(|thread| thread_view(thread, &caller_id)))
+}
+
+#[update]
+#[candid_method(update)]
+async fn reply_to_thread(
+    thread_id: String,
+    content: String,
+    parent_reply_id: Option<String>,
+) -> Result<DiscussionThread> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let mut thread = get_discussion(&thread_id)?;
+    let course = get_course(thread.course_id.clone())?;
+    if !can_participate(&course, &caller_id) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    if thread.is_locked || thread.visibility != ContentVisibility::Visible {
+        return Err(ApiError::InvalidInput("Thread is locked".to_string()));
+    }
+
+    if content.trim().is_empty() {
+        return Err(ApiError::InvalidInput("Reply content cannot be empty".to_string()));
+    }
+
+    if let Some(ref parent_id) = parent_reply_id {
+        if !thread.replies.iter().any(|reply| reply.id == *parent_id) {
+            return Err(ApiError::NotFound("Parent reply not found".to_string()));
+        }
+    }
+
+    let current_time = get_current_time();
+    thread.replies.push(ThreadReply {
+        id: generate_reply_id(),
+        author_id: caller_id,
+        content,
+        created_at: current_time,
+        updated_at: current_time,
+        upvotes: 0,
+        downvotes: 0,
+        parent_reply_id,
+        visibility: ContentVisibility::Visible,
+    });
+    thread.last_activity_at = current_time;
+
+    save_discussion(&thread);
+    Ok(thread)
+}
+
+#[update]
+#[candid_method(update)]
+async fn vote_on_thread(thread_id: String, vote: Option<DiscussionVote>) -> Result<DiscussionThread> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let mut thread = get_discussion(&thread_id)?;
+    let course = get_course(thread.course_id.clone())?;
+    if !can_participate(&course, &caller_id) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    apply_discussion_vote(&thread.id, &caller_id, vote, &mut thread.upvotes, &mut thread.downvotes);
+
+    save_discussion(&thread);
+    Ok(thread)
+}
+
+#[update]
+#[candid_method(update)]
+async fn vote_on_reply(
+    thread_id: String,
+    reply_id: String,
+    vote: Option<DiscussionVote>,
+) -> Result<DiscussionThread> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let mut thread = get_discussion(&thread_id)?;
+    let course = get_course(thread.course_id.clone())?;
+    if !can_participate(&course, &caller_id) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    let reply = thread.replies.iter_mut()
+        .find(|reply| reply.id == reply_id)
+        .ok_or_else(|| ApiError::NotFound("Reply not found".to_string()))?;
+    apply_discussion_vote(&reply.id, &caller_id, vote, &mut reply.upvotes, &mut reply.downvotes);
+
+    save_discussion(&thread);
+    Ok(thread)
+}
+
+#[update]
+#[candid_method(update)]
+async fn pin_thread(thread_id: String, pinned: bool) -> Result<DiscussionThread> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let thread = get_discussion(&thread_id)?;
+    let course = get_course(thread.course_id.clone())?;
+    if !can_moderate(&course, caller_id).await {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    let mut thread = get_discussion(&thread_id)?;
+    thread.is_pinned = pinned;
+    thread.updated_at = get_current_time();
+    save_discussion(&thread);
+    Ok(thread)
+}
+
+#[update]
+#[candid_method(update)]
+async fn lock_thread(thread_id: String, locked: bool) -> Result<DiscussionThread> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let thread = get_discussion(&thread_id)?;
+    let course = get_course(thread.course_id.clone())?;
+    if !can_moderate(&course, caller_id).await {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    let mut thread = get_discussion(&thread_id)?;
+    thread.is_locked = locked;
+    thread.updated_at = get_current_time();
+    save_discussion(&thread);
+    Ok(thread)
+}
+
+// Review Functions
+
+const MIN_REVIEW_COMPLETION: f32 = 50.0; // percent of the course a learner must complete
+const RATING_PRIOR_MEAN: f64 = 3.0;
+const RATING_PRIOR_WEIGHT: f64 = 5.0; // reviews' worth of confidence in the prior mean
+
+fn get_stored_review(review_id: &str) -> Result<Review> {
+    REVIEWS.with(|reviews| {
+        reviews.borrow().get(review_id).cloned()
+            .ok_or_else(|| ApiError::NotFound("Review not found".to_string()))
+    })
+}
+
+fn save_review(review: &Review) {
+    REVIEWS.with(|reviews| reviews.borrow_mut().insert(review.id.clone(), review.clone()));
+}
+
+fn validate_review(rating: u8, comment: &str) -> Result<()> {
+    if !(1..=5).contains(&rating) {
+        return Err(ApiError::InvalidInput("Rating must be between 1 and 5".to_string()));
+    }
+    if comment.len() > 5000 {
+        return Err(ApiError::InvalidInput("Review comment is too long".to_string()));
+    }
+    Ok(())
+}
+
+// Adds and/or removes a visible rating from the course totals and recomputes
+// the Bayesian average, which pulls courses with few reviews towards the prior
+// mean so that a handful of five-star reviews cannot top the rankings
+fn adjust_course_rating(course_id: &str, added: Option<u8>, removed: Option<u8>) {
+    let mut course = match get_course(course_id.to_string()) {
+        Ok(course) => course,
+        Err(_) => return,
+    };
+
+    let total = RATING_TOTALS.with(|totals| {
+        let mut totals = totals.borrow_mut();
+        let total = totals.entry(course_id.to_string()).or_insert(0);
+        *total = (*total + added.unwrap_or(0) as u64).saturating_sub(removed.unwrap_or(0) as u64);
+        *total
+    });
+    if added.is_some() {
+        course.review_count += 1;
+    }
+    if removed.is_some() {
+        course.review_count = course.review_count.saturating_sub(1);
+    }
+
+    course.rating = if course.review_count == 0 {
+        0.0
+    } else {
+        ((RATING_PRIOR_WEIGHT * RATING_PRIOR_MEAN + total as f64)
+            / (RATING_PRIOR_WEIGHT + course.review_count as f64)) as f32
+    };
+    save_course(&course);
+}
+
+// Records the caller's vote on a review (None retracts it) and adjusts the
+// counters, so each principal contributes at most one vote per review
+fn apply_review_vote(review: &mut Review, user_id: &Principal, vote: Option<ReviewVote>) {
+    let vote_key = discussion_vote_key(&review.id, user_id);
+    let previous = REVIEW_VOTES.with(|votes| {
+        let mut votes = votes.borrow_mut();
+        match vote {
+            Some(ref vote) => votes.insert(vote_key, vote.clone()),
+            None => votes.remove(&vote_key),
+        }
+    });
+
+    match previous {
+        Some(ReviewVote::Helpful) => review.helpful_votes = review.helpful_votes.saturating_sub(1),
+        Some(ReviewVote::Unhelpful) => review.unhelpful_votes = review.unhelpful_votes.saturating_sub(1),
+        None => {}
+    }
+    match vote {
+        Some(ReviewVote::Helpful) => review.helpful_votes += 1,
+        Some(ReviewVote::Unhelpful) => review.unhelpful_votes += 1,
+        None => {}
+    }
+}
+
+// Learners who have completed enough of a course can leave one review of it
+#[update]
+#[candid_method(update)]
+async fn add_review(course_id: String, rating: u8, comment: String) -> Result<Review> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let course = get_course(course_id.clone())?;
+    validate_review(rating, &comment)?;
+
+    let enrollment = get_user_enrollment(caller_id, course.id.clone())
+        .map_err(|_| ApiError::InvalidInput("You must be enrolled to review this course".to_string()))?;
+    if enrollment.completion_percentage < MIN_REVIEW_COMPLETION {
+        return Err(ApiError::InvalidInput(format!(
+            "Complete at least {}% of the course before reviewing it",
+            MIN_REVIEW_COMPLETION
+        )));
+    }
+
+    let already_reviewed = REVIEWS.with(|reviews| {
+        reviews.borrow().values().any(|review| review.course_id == course_id && review.user_id == caller_id)
+    });
+    if already_reviewed {
+        return Err(ApiError::AlreadyExists("You have already reviewed this course".to_string()));
+    }
+
+    let current_time = get_current_time();
+    let review = Review {
+        id: generate_review_id(),
+        course_id,
+        user_id: caller_id,
+        rating,
+        comment,
+        created_at: current_time,
+        updated_at: current_time,
+        helpful_votes: 0,
+        unhelpful_votes: 0,
+        instructor_response: None,
+        visibility: ContentVisibility::Visible,
+    };
+
+    save_review(&review);
+    adjust_course_rating(&review.course_id, Some(rating), None);
+    Ok(review)
+}
+
+#[update]
+#[candid_method(update)]
+async fn update_review(review_id: String, rating: Option<u8>, comment: Option<String>) -> Result<Review> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let mut review = get_stored_review(&review_id)?;
+    if review.user_id != caller_id {
+        return Err(ApiError::InsufficientPermissions);
+    }
+    if review.visibility == ContentVisibility::Deleted {
+        return Err(ApiError::NotFound("Review not found".to_string()));
+    }
+
+    let previous_rating = review.rating;
+    if let Some(rating) = rating {
+        review.rating = rating;
+    }
+    if let Some(comment) = comment {
+        review.comment = comment;
+    }
+    validate_review(review.rating, &review.comment)?;
+    review.updated_at = get_current_time();
+
+    save_review(&review);
+    if review.visibility == ContentVisibility::Visible && review.rating != previous_rating {
+        adjust_course_rating(&review.course_id, Some(review.rating), Some(previous_rating));
+    }
+    Ok(review)
+}
+
+// A review with an open report or a standing moderation action must stay in
+// place so that the report can be resolved and the action appealed
+fn review_under_moderation(review_id: &str) -> bool {
+    let target = ReportTarget::Review { review_id: review_id.to_string() };
+    let open_report = REPORTS.with(|reports| {
+        reports.borrow()
+            .values()
+            .any(|report| report.target == target && report.status == ReportStatus::Open)
+    });
+    let standing_action = MODERATION_LOG.with(|log| {
+        log.borrow().iter().any(|entry| {
+            entry.target.as_ref() == Some(&target)
+                && !entry.overturned
+                && matches!(entry.action, ModerationActionType::HideContent | ModerationActionType::DeleteContent)
+        })
+    });
+    open_report || standing_action
+}
+
+// Authors can remove their own review, which also discards its votes
+#[update]
+#[candid_method(update)]
+async fn delete_review(review_id: String) -> Result<bool> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let review = get_stored_review(&review_id)?;
+    if review.user_id != caller_id {
+        return Err(ApiError::InsufficientPermissions);
+    }
+    if review_under_moderation(&review_id) {
+        return Err(ApiError::InvalidInput("Reviews under moderation cannot be deleted".to_string()));
+    }
+
+    remove_review(&review);
+    Ok(true)
+}
+
+fn remove_review(review: &Review) {
+    REVIEWS.with(|reviews| reviews.borrow_mut().remove(&review.id));
+    let vote_prefix = format!("{}_", review.id);
+    REVIEW_VOTES.with(|votes| votes.borrow_mut().retain(|key, _| !key.starts_with(&vote_prefix)));
+    if review.visibility == ContentVisibility::Visible {
+        adjust_course_rating(&review.course_id, None, Some(review.rating));
+    }
+}
+
+// Enrolled learners can mark other people's reviews as helpful or not
+#[update]
+#[candid_method(update)]
+async fn vote_on_review(review_id: String, vote: Option<ReviewVote>) -> Result<Review> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let mut review = get_stored_review(&review_id)?;
+    if review.visibility != ContentVisibility::Visible {
+        return Err(ApiError::NotFound("Review not found".to_string()));
+    }
+    if review.user_id == caller_id {
+        return Err(ApiError::InvalidInput("You cannot vote on your own review".to_string()));
+    }
+    if !is_enrolled(&caller_id, &review.course_id) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    apply_review_vote(&mut review, &caller_id, vote);
+
+    save_review(&review);
+    Ok(review)
+}
+
+// The course instructor can publicly respond to a review; None removes the response
+#[update]
+#[candid_method(update)]
+async fn respond_to_review(review_id: String, response: Option<String>) -> Result<Review> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let mut review = get_stored_review(&review_id)?;
+    get_instructor_course(&review.course_id, caller_id)?;
+    if review.visibility == ContentVisibility::Deleted {
+        return Err(ApiError::NotFound("Review not found".to_string()));
+    }
+
+    let current_time = get_current_time();
+    review.instructor_response = match response {
+        Some(content) if content.trim().is_empty() => {
+            return Err(ApiError::InvalidInput("Response cannot be empty".to_string()));
+        }
+        Some(content) => Some(InstructorResponse {
+            content,
+            created_at: review.instructor_response.as_ref()
+                .map(|existing| existing.created_at)
+                .unwrap_or(current_time),
+            updated_at: current_time,
+        }),
+        None => None,
+    };
+
+    save_review(&review);
+    Ok(review)
+}
+
+// Hidden reviews are only returned to their author
+#[query]
+#[candid_method(query)]
+fn get_review(review_id: String) -> Result<Review> {
+    let review = get_stored_review(&review_id)?;
+    if !is_shown_to(&review.visibility, &review.user_id, &caller()) {
+        return Err(ApiError::NotFound("Review not found".to_string()));
+    }
+    Ok(review)
+}
+
+// Visible reviews of a course, newest first unless another order is requested
+#[query]
+#[candid_method(query)]
+fn get_course_reviews(
+    course_id: String,
+    sort: Option<ReviewSort>,
+    cursor: Option<String>,
+    limit: Option<u32>,
+) -> Result<Page<Review>> {
+    let sort = sort.unwrap_or(ReviewSort::Newest);
+
+    // The chosen order with newer reviews breaking ties
+    let review_key = |review: &Review| {
+        let primary = match sort {
+            ReviewSort::Newest => 0,
+            ReviewSort::MostHelpful => {
+                u32::MAX as u64 - review.helpful_votes as u64 + review.unhelpful_votes as u64
+            }
+            ReviewSort::HighestRating => 5 - review.rating as u64,
+            ReviewSort::LowestRating => review.rating as u64,
+        };
+        format!("{:020}:{:020}:{}", primary, u64::MAX - review.created_at, review.id)
+    };
+
+    REVIEWS.with(|reviews| {
+        paginate(
+            reviews.borrow()
+                .values()
+                .filter(|review| review.course_id == course_id)
+                .filter(|review| review.visibility == ContentVisibility::Visible),
+            review_key,
+            cursor,
+            limit,
+        )
+    })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_user_reviews(user_id: Principal, cursor: Option<String>, limit: Option<u32>) -> Result<Page<Review>> {
+    let caller_id = caller();
+
+    REVIEWS.with(|reviews| {
+        paginate(
+            reviews.borrow()
+                .values()
+                .filter(|review| review.user_id == user_id)
+                .filter(|review| is_shown_to(&review.visibility, &review.user_id, &caller_id)),
+            |review| format!("{:020}:{}", u64::MAX - review.created_at, review.id),
+            cursor,
+            limit,
+        )
+    })
+}
+
+// Moderation Functions
+
+fn get_report(report_id: u64) -> Result<ContentReport> {
+    REPORTS.with(|reports| {
+        reports.borrow().get(&report_id).cloned()
+            .ok_or_else(|| ApiError::NotFound("Report not found".to_string()))
+    })
+}
+
+fn save_report(report: &ContentReport) {
+    REPORTS.with(|reports| reports.borrow_mut().insert(report.id, report.clone()));
+}
+
+// Marks an open report as being resolved, so a second moderator cannot act
+// on it while the first one's calls to user_management are in flight
+fn claim_report(report_id: u64) -> Result<ContentReport> {
+    let report = get_report(report_id)?;
+    if report.status != ReportStatus::Open {
+        return Err(ApiError::InvalidInput("Report has already been resolved".to_string()));
+    }
+    if !RESOLVING_REPORTS.with(|resolving| resolving.borrow_mut().insert(report_id)) {
+        return Err(ApiError::InvalidInput("Report is already being resolved".to_string()));
+    }
+    Ok(report)
+}
+
+fn release_report(report_id: u64) {
+    RESOLVING_REPORTS.with(|resolving| resolving.borrow_mut().remove(&report_id));
+}
+
+fn log_moderation_action(
+    actor_id: Principal,
+    action: ModerationActionType,
+    target: Option<ReportTarget>,
+    affected_user: Option<Principal>,
+    report_id: Option<u64>,
+    reason: String,
+) -> ModerationAction {
+    MODERATION_LOG.with(|log| {
+        let mut log = log.borrow_mut();
+        let entry = ModerationAction {
+            id: log.len() as u64 + 1,
+            actor_id,
+            action,
+            target,
+            affected_user,
+            report_id,
+            reason,
+            created_at: get_current_time(),
+            overturned: false,
+        };
+        log.push(entry.clone());
+        entry
+    })
+}
+
+// The principal responsible for a piece of reported content
+fn content_author(target: &ReportTarget) -> Result<Principal> {
+    match target {
+        ReportTarget::Thread { thread_id } => Ok(get_discussion(thread_id)?.author_id),
+        ReportTarget::Reply { thread_id, reply_id } => get_discussion(thread_id)?
+            .replies
+            .iter()
+            .find(|reply| reply.id == *reply_id)
+            .map(|reply| reply.author_id)
+            .ok_or_else(|| ApiError::NotFound("Reply not found".to_string())),
+        ReportTarget::Profile { user_id } => Ok(*user_id),
+        ReportTarget::Review { review_id } => Ok(get_stored_review(review_id)?.user_id),
+    }
+}
+
+fn set_content_visibility(target: &ReportTarget, visibility: ContentVisibility) -> Result<()> {
+    match target {
+        ReportTarget::Thread { thread_id } => {
+            let mut thread = get_discussion(thread_id)?;
+            thread.visibility = visibility;
+            thread.updated_at = get_current_time();
+            save_discussion(&thread);
+            Ok(())
+        }
+        ReportTarget::Reply { thread_id, reply_id } => {
+            let mut thread = get_discussion(thread_id)?;
+            let reply = thread.replies.iter_mut()
+                .find(|reply| reply.id == *reply_id)
+                .ok_or_else(|| ApiError::NotFound("Reply not found".to_string()))?;
+            reply.visibility = visibility;
+            reply.updated_at = get_current_time();
+            save_discussion(&thread);
+            Ok(())
+        }
+        ReportTarget::Review { review_id } => {
+            let mut review = get_stored_review(review_id)?;
+            let was_visible = review.visibility == ContentVisibility::Visible;
+            let is_visible = visibility == ContentVisibility::Visible;
+            review.visibility = visibility;
+            save_review(&review);
+
+            // Only visible reviews count towards the course rating
+            if was_visible && !is_visible {
+                adjust_course_rating(&review.course_id, None, Some(review.rating));
+            } else if !was_visible && is_visible {
+                adjust_course_rating(&review.course_id, Some(review.rating), None);
+            }
+            Ok(())
+        }
+        ReportTarget::Profile { .. } => Err(ApiError::InvalidInput(
+            "Profiles are moderated through user_management".to_string(),
+        )),
+    }
+}
+
+// Lets a controller register canisters (such as governance) that may call
+// privileged inter-canister endpoints
+#[update]
+#[candid_method(update)]
+async fn set_trusted_canisters(canister_ids: Vec<Principal>) -> Result<bool> {
+    if !ic_cdk::api::is_controller(&caller()) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    TRUSTED_CANISTERS.with(|trusted| *trusted.borrow_mut() = canister_ids);
+    Ok(true)
+}
+
+#[update]
+#[candid_method(update)]
+async fn report_content(target: ReportTarget, reason: String) -> Result<ContentReport> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    if caller_id == Principal::anonymous() {
+        return Err(ApiError::Unauthorized);
+    }
+
+    if reason.trim().is_empty() {
+        return Err(ApiError::InvalidInput("Report reason cannot be empty".to_string()));
+    }
+
+    // Make sure the reported content exists
+    content_author(&target)?;
+
+    let already_reported = REPORTS.with(|reports| {
+        reports.borrow().values().any(|report| {
+            report.reporter_id == caller_id && report.target == target && report.status == ReportStatus::Open
+        })
+    });
+    if already_reported {
+        return Err(ApiError::AlreadyExists("You have already reported this content".to_string()));
+    }
+
+    let report_id = REPORT_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        *counter += 1;
+        *counter
+    });
+
+    let report = ContentReport {
+        id: report_id,
+        reporter_id: caller_id,
+        target,
+        reason,
+        status: ReportStatus::Open,
+        created_at: get_current_time(),
+        resolved_at: None,
+        resolved_by: None,
+        action_id: None,
+    };
+
+    save_report(&report);
+    Ok(report)
+}
+
+// Open reports, oldest first. This is an update call because checking the
+// moderator role requires an inter-canister call.
+#[update]
+#[candid_method(update)]
+async fn get_moderation_queue(cursor: Option<String>, limit: Option<u32>) -> Result<Page<ContentReport>> {
+    if !is_user_moderator(caller()).await {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    REPORTS.with(|reports| {
+        paginate(
+            reports.borrow().values().filter(|report| report.status == ReportStatus::Open),
+            |report| format!("{:020}", report.id),
+            cursor,
+            limit,
+        )
+    })
+}
+
+#[update]
+#[candid_method(update)]
+async fn resolve_report(
+    report_id: u64,
+    action: ModerationActionType,
+    reason: String,
+) -> Result<ModerationAction> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    if !is_user_moderator(caller_id).await {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    let report = claim_report(report_id)?;
+    let result = apply_resolution(caller_id, report, action, reason).await;
+    release_report(report_id);
+    result
+}
+
+async fn apply_resolution(
+    caller_id: Principal,
+    report: ContentReport,
+    action: ModerationActionType,
+    reason: String,
+) -> Result<ModerationAction> {
+    let report_id = report.id;
+    let affected_user = content_author(&report.target)?;
+    let is_profile = matches!(report.target, ReportTarget::Profile { .. });
+
+    match action {
+        ModerationActionType::Dismiss => {}
+        ModerationActionType::HideContent if is_profile => {
+            apply_user_moderation(affected_user, UserModeration::HideProfile).await?;
+        }
+        ModerationActionType::HideContent => {
+            set_content_visibility(&report.target, ContentVisibility::Hidden)?;
+        }
+        ModerationActionType::DeleteContent if is_profile => {
+            return Err(ApiError::InvalidInput("Profiles can only be hidden".to_string()));
+        }
+        ModerationActionType::DeleteContent => {
+            set_content_visibility(&report.target, ContentVisibility::Deleted)?;
+        }
+        ModerationActionType::WarnUser => {
+            apply_user_moderation(affected_user, UserModeration::Warn).await?;
+        }
+        ModerationActionType::SuspendUser { days } => {
+            if days == 0 || days > MAX_SUSPENSION_DAYS {
+                return Err(ApiError::InvalidInput(format!(
+                    "Suspension must last between 1 and {} days",
+                    MAX_SUSPENSION_DAYS
+                )));
+            }
+            let until = get_current_time() + days_to_nanoseconds(days as u64);
+            let suspension = UserModeration::Suspend { until, reason: reason.clone() };
+            apply_user_moderation(affected_user, suspension).await?;
+        }
+        ModerationActionType::Overturn { .. } => {
+            return Err(ApiError::InvalidInput("Actions can only be overturned by appeal".to_string()));
+        }
+    }
+
+    let is_dismissal = action == ModerationActionType::Dismiss;
+    let entry = log_moderation_action(
+        caller_id,
+        action,
+        Some(report.target.clone()),
+        if is_dismissal { None } else { Some(affected_user) },
+        Some(report_id),
+        reason,
+    );
+
+    let mut report = get_report(report_id)?;
+    report.status = if is_dismissal { ReportStatus::Dismissed } else { ReportStatus::Actioned };
+    report.resolved_at = Some(entry.created_at);
+    report.resolved_by = Some(caller_id);
+    report.action_id = Some(entry.id);
+    save_report(&report);
+
+    Ok(entry)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_moderation_action(action_id: u64) -> Result<ModerationAction> {
+    MODERATION_LOG.with(|log| {
+        log.borrow().iter().find(|entry| entry.id == action_id).cloned()
+            .ok_or_else(|| ApiError::NotFound("Moderation action not found".to_string()))
+    })
+}
+
+// The audit log is public so that appeals can be judged by the community
+#[query]
+#[candid_method(query)]
+fn get_moderation_log(
+    affected_user: Option<Principal>,
+    cursor: Option<String>,
+    limit: Option<u32>,
+) -> Result<Page<ModerationAction>> {
+    // Newest entries first
+    MODERATION_LOG.with(|log| {
+        paginate(
+            log.borrow().iter().filter(|entry| affected_user.is_none() || entry.affected_user == affected_user),
+            |entry| format!("{:020}", u64::MAX - entry.id),
+            cursor,
+            limit,
+        )
+    })
+}
+
+// Called by governance when a moderation appeal proposal is executed
+#[update]
+#[candid_method(update)]
+async fn overturn_moderation_action(action_id: u64, proposal_id: u64) -> Result<ModerationAction> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    if !is_trusted_canister(&caller_id) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    let original = get_moderation_action(action_id)?;
+    if original.overturned {
+        return Err(ApiError::InvalidInput("Moderation action has already been overturned".to_string()));
+    }
+
+    let affected_user = original.affected_user
+        .ok_or_else(|| ApiError::InvalidInput("Moderation action cannot be appealed".to_string()))?;
+
+    match (&original.action, &original.target) {
+        (ModerationActionType::HideContent, Some(ReportTarget::Profile { .. })) => {
+            apply_user_moderation(affected_user, UserModeration::RestoreProfile).await?;
+        }
+        (ModerationActionType::HideContent, Some(target))
+        | (ModerationActionType::DeleteContent, Some(target)) => {
+            set_content_visibility(target, ContentVisibility::Visible)?;
+        }
+        (ModerationActionType::WarnUser, _) => {
+            apply_user_moderation(affected_user, UserModeration::RemoveWarning).await?;
+        }
+        (ModerationActionType::SuspendUser { .. }, _) => {
+            apply_user_moderation(affected_user, UserModeration::Reinstate).await?;
+        }
+        _ => {
+            return Err(ApiError::InvalidInput("Moderation action cannot be appealed".to_string()));
+        }
+    }
+
+    MODERATION_LOG.with(|log| {
+        if let Some(entry) = log.borrow_mut().iter_mut().find(|entry| entry.id == action_id) {
+            entry.overturned = true;
+        }
+    });
+
+    Ok(log_moderation_action(
+        caller_id,
+        ModerationActionType::Overturn { action_id, proposal_id },
+        original.target,
+        Some(affected_user),
+        original.report_id,
+        format!("Overturned by governance proposal {}", proposal_id),
+    ))
+}
+
+// Learning Path Functions
+
+const DEFAULT_RECOMMENDATION_LIMIT: usize = 5;
+const MAX_RECOMMENDATION_LIMIT: usize = 20;
+const MAX_LEARNING_PATH_COURSES: usize = 50;
+
+fn normalize_skills(skills: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for skill in skills {
+        let skill = skill.trim().to_lowercase();
+        if !skill.is_empty() && !normalized.contains(&skill) {
+            normalized.push(skill);
+        }
+    }
+    normalized
+}
+
+// Target skills a course teaches, matched against its tags and category
+fn matched_skills(course: &Course, skills: &[String]) -> Vec<String> {
+    skills.iter()
+        .filter(|skill| {
+            course.category.trim().to_lowercase() == **skill
+                || course.tags.iter().any(|tag| tag.trim().to_lowercase() == **skill)
+        })
+        .cloned()
+        .collect()
+}
+
+// Completion percentage of every course the user is enrolled in
+fn user_course_progress(user_id: &Principal) -> HashMap<String, f32> {
+    ENROLLMENTS.with(|enrollments| {
+        enrollments.borrow()
+            .values()
+            .filter(|enrollment| enrollment.user_id == *user_id)
+            .map(|enrollment| (enrollment.course_id.clone(), enrollment.completion_percentage))
+            .collect()
+    })
+}
+
+fn remaining_hours(course: &Course, completion_percentage: f32) -> u32 {
+    let remaining = 1.0 - completion_percentage.clamp(0.0, 100.0) / 100.0;
+    (course.estimated_duration_hours as f32 * remaining).ceil() as u32
+}
+
+// Builds a prerequisite-first course ordering for a set of goal courses
+struct PathPlanner<'a> {
+    courses: &'a HashMap<String, Course>,
+    progress: &'a HashMap<String, f32>,
+    ordered: Vec<String>,
+    required_by: HashMap<String, Vec<String>>,
+    unavailable: Vec<String>,
+}
+
+impl PathPlanner<'_> {
+    fn is_completed(&self, course_id: &str) -> bool {
+        self.progress.get(course_id).map(|completion| *completion >= 100.0).unwrap_or(false)
+    }
+
+    fn add_goal(&mut self, goal_id: &str) {
+        let mut seen = HashSet::new();
+        self.visit(goal_id, goal_id, &mut seen);
+    }
+
+    // Depth-first so that a course is only placed after its prerequisites
+    fn visit(&mut self, course_id: &str, goal_id: &str, seen: &mut HashSet<String>) {
+        if !seen.insert(course_id.to_string()) || self.is_completed(course_id) {
+            return;
+        }
+        let course = match self.courses.get(course_id) {
+            Some(course) => course,
+            None => {
+                // Only published courses are planned; report the rest instead of hiding the gap
+                if !self.unavailable.iter().any(|id| id == course_id) {
+                    self.unavailable.push(course_id.to_string());
+                }
+                return;
+            }
+        };
+
+        for prerequisite_id in &course.prerequisites {
+            self.visit(prerequisite_id, goal_id, seen);
+        }
+
+        if course_id != goal_id {
+            let goals = self.required_by.entry(course_id.to_string()).or_default();
+            if !goals.iter().any(|id| id == goal_id) {
+                goals.push(goal_id.to_string());
+            }
+        }
+        if !self.ordered.iter().any(|id| id == course_id) {
+            self.ordered.push(course_id.to_string());
+        }
+    }
+}
+
+// Deterministic recommender: picks published courses that teach the target
+// skills, pulls in any prerequisites the user has not completed yet and
+// orders everything so prerequisites come first. Scores weigh how many target
+// skills a course covers (60%) and how many chosen goals depend on it (40%),
+// with a small boost for courses the user has already started. Also returns
+// the prerequisites that could not be planned because they are unpublished.
+fn recommend_courses_for(
+    user_id: &Principal,
+    target_skills: &[String],
+    limit: usize,
+) -> (Vec<CourseRecommendation>, Vec<String>) {
+    let skills = normalize_skills(target_skills);
+    let courses: HashMap<String, Course> = COURSES.with(|courses| {
+        courses.borrow()
+            .iter()
+            .filter(|(_, course)| course.is_published)
+            .map(|(id, course)| (id.clone(), course.clone()))
+            .collect()
+    });
+    let progress = user_course_progress(user_id);
+
+    let mut goals: Vec<(&Course, usize)> = courses.values()
+        .filter(|course| progress.get(&course.id).map(|completion| *completion < 100.0).unwrap_or(true))
+        .map(|course| (course, matched_skills(course, &skills).len()))
+        .filter(|(_, matched)| *matched > 0)
+        .collect();
+    goals.sort_by(|a, b| {
+        b.1.cmp(&a.1)
+            .then(b.0.enrollment_count.cmp(&a.0.enrollment_count))
+            .then(a.0.id.cmp(&b.0.id))
+    });
+    goals.truncate(limit);
+
+    let mut planner = PathPlanner {
+        courses: &courses,
+        progress: &progress,
+        ordered: Vec::new(),
+        required_by: HashMap::new(),
+        unavailable: Vec::new(),
+    };
+    for (goal, _) in &goals {
+        planner.add_goal(&goal.id);
+    }
+
+    let recommendations = planner.ordered.iter()
+        .filter_map(|course_id| courses.get(course_id))
+        .map(|course| {
+            let matched = matched_skills(course, &skills);
+            let dependents = planner.required_by.get(&course.id).cloned().unwrap_or_default();
+            let completion = progress.get(&course.id).copied().unwrap_or(0.0);
+
+            let coverage = matched.len() as f32 / skills.len().max(1) as f32;
+            let dependency = dependents.len() as f32 / goals.len().max(1) as f32;
+            let started_bonus = if completion > 0.0 { 0.1 } else { 0.0 };
+            let priority_score = (0.6 * coverage + 0.4 * dependency + started_bonus).min(1.0);
+
+            let mut reasons = Vec::new();
+            if !matched.is_empty() {
+                reasons.push(format!("Teaches {}", matched.join(", ")));
+            }
+            if !dependents.is_empty() {
+                let titles: Vec<String> = dependents.iter()
+                    .filter_map(|id| courses.get(id).map(|dependent| dependent.title.clone()))
+                    .collect();
+                reasons.push(format!("Prerequisite for {}", titles.join(", ")));
+            }
+            if completion > 0.0 {
+                reasons.push(format!("{:.0}% complete", completion));
+            }
+
+            CourseRecommendation {
+                course_id: course.id.clone(),
+                reason: reasons.join("; "),
+                priority_score: (priority_score * 100.0).round() / 100.0,
+                estimated_completion_time: remaining_hours(course, completion),
+            }
+        })
+        .collect();
+    (recommendations, planner.unavailable)
+}
+
+// Curated paths keep the author's order; earlier steps get higher priority
+fn curated_recommendations(course_ids: &[String]) -> Result<Vec<CourseRecommendation>> {
+    if course_ids.is_empty() {
+        return Err(ApiError::InvalidInput("A learning path needs at least one course".to_string()));
+    }
+    if course_ids.len() > MAX_LEARNING_PATH_COURSES {
+        return Err(ApiError::InvalidInput("Too many courses in learning path".to_string()));
+    }
+
+    let total = course_ids.len();
+    let mut seen = HashSet::new();
+    let mut recommendations = Vec::new();
+    for (index, course_id) in course_ids.iter().enumerate() {
+        if !seen.insert(course_id) {
+            return Err(ApiError::InvalidInput("Duplicate course in learning path".to_string()));
+        }
+        let course = get_course(course_id.clone())?;
+        if !course.is_published {
+            return Err(ApiError::InvalidInput("Learning paths can only include published courses".to_string()));
+        }
+
+        recommendations.push(CourseRecommendation {
+            course_id: course.id.clone(),
+            reason: format!("Step {} of {}", index + 1, total),
+            priority_score: ((total - index) as f32 / total as f32 * 100.0).round() / 100.0,
+            estimated_completion_time: course.estimated_duration_hours,
+        });
+    }
+    Ok(recommendations)
+}
+
+fn get_owned_learning_path(path_id: &str, user_id: Principal) -> Result<LearningPath> {
+    let path = LEARNING_PATHS.with(|paths| {
+        paths.borrow().get(path_id).cloned()
+            .ok_or_else(|| ApiError::NotFound("Learning path not found".to_string()))
+    })?;
+    if path.user_id != user_id {
+        return Err(ApiError::InsufficientPermissions);
+    }
+    Ok(path)
+}
+
+fn save_learning_path(path: &LearningPath) {
+    LEARNING_PATHS.with(|paths| paths.borrow_mut().insert(path.id.clone(), path.clone()));
+}
+
 #[update]
 #[candid_method(update)]
-async fn create_course(request: CreateCourseRequest) -> Result<Course> {
+async fn create_learning_path(request: CreateLearningPathRequest) -> Result<LearningPath> {
+    ensure_not_frozen()?;
     let caller_id = caller();
-    
-    // Validate input
+    ensure_account_active(caller_id).await?;
+
     if request.title.trim().is_empty() {
-        return Err(ApiError::InvalidInput("Course title cannot be empty".to_string()));
-    }
-    
-    if request.description.trim().is_empty() {
-        return Err(ApiError::InvalidInput("Course description cannot be empty".to_string()));
+        return Err(ApiError::InvalidInput("Learning path title cannot be empty".to_string()));
     }
 
-    let course_id = generate_course_id();
+    let recommended_courses = curated_recommendations(&request.course_ids)?;
     let current_time = get_current_time();
 
-    let course = Course {
-        id: course_id.clone(),
+    let path = LearningPath {
+        id: generate_learning_path_id(),
+        user_id: caller_id,
         title: request.title,
         description: request.description,
-        instructor_id: caller_id,
-        category: request.category,
-        tags: request.tags,
-        difficulty_level: request.difficulty_level,
-        estimated_duration_hours: request.estimated_duration_hours,
-        price: request.price,
-        is_published: false,
+        target_skills: vec![],
+        recommended_courses,
+        unavailable_prerequisites: vec![],
+        is_public: request.is_public,
+        is_generated: false,
         created_at: current_time,
         updated_at: current_time,
     };
 
-    COURSES.with(|courses| courses.borrow_mut().insert(course_id, course.clone()));
-    Ok(course)
-}
-
-#[query]
-#[candid_method(query)]
-fn get_course(course_id: String) -> Result<Course> {
-    COURSES.with(|courses| {
-        courses.borrow().get(&course_id).cloned()
-            .ok_or_else(|| ApiError::NotFound("Course not found".to_string()))
-    })
+    save_learning_path(&path);
+    Ok(path)
 }
 
+// Generates and saves a path for the caller from their target skills
 #[update]
 #[candid_method(update)]
-async fn publish_course(course_id: String) -> Result<Course> {
+async fn generate_learning_path(target_skills: Vec<String>, limit: Option<u32>) -> Result<LearningPath> {
+    ensure_not_frozen()?;
     let caller_id = caller();
+    ensure_account_active(caller_id).await?;
 
-    COURSES.with(|courses| {
-        let mut courses = courses.borrow_mut();
-        match courses.get(&course_id).cloned() {
-            Some(mut course) => {
-                if course.instructor_id != caller_id {
-                    return Err(ApiError::InsufficientPermissions);
-                }
+    let skills = normalize_skills(&target_skills);
+    if skills.is_empty() {
+        return Err(ApiError::InvalidInput("At least one target skill is required".to_string()));
+    }
 
-                course.is_published = true;
-                course.updated_at = get_current_time();
-                courses.insert(course_id, course.clone());
-                Ok(course)
-            }
-            None => Err(ApiError::NotFound("Course not found".to_string()))
-        }
-    })
+    let limit = limit.map(|limit| limit as usize).unwrap_or(DEFAULT_RECOMMENDATION_LIMIT).min(MAX_RECOMMENDATION_LIMIT);
+    let (recommended_courses, unavailable_prerequisites) = recommend_courses_for(&caller_id, &skills, limit);
+    if recommended_courses.is_empty() {
+        return Err(ApiError::NotFound("No courses match these skills".to_string()));
+    }
+
+    let current_time = get_current_time();
+    let total_hours: u32 = recommended_courses.iter().map(|course| course.estimated_completion_time).sum();
+
+    let path = LearningPath {
+        id: generate_learning_path_id(),
+        user_id: caller_id,
+        title: format!("Path to {}", skills.join(", ")),
+        description: format!("{} courses, about {} hours", recommended_courses.len(), total_hours),
+        target_skills: skills,
+        recommended_courses,
+        unavailable_prerequisites,
+        is_public: false,
+        is_generated: true,
+        created_at: current_time,
+        updated_at: current_time,
+    };
+
+    save_learning_path(&path);
+    Ok(path)
 }
 
+// Previews recommendations for the caller without saving a path
 #[query]
 #[candid_method(query)]
-fn get_all_courses() -> Vec<Course> {
-    COURSES.with(|courses| {
-        courses.borrow().values().cloned().collect()
-    })
+fn recommend_courses(target_skills: Vec<String>, limit: Option<u32>) -> Result<Vec<CourseRecommendation>> {
+    if normalize_skills(&target_skills).is_empty() {
+        return Err(ApiError::InvalidInput("At least one target skill is required".to_string()));
+    }
+
+    let limit = limit.map(|limit| limit as usize).unwrap_or(DEFAULT_RECOMMENDATION_LIMIT).min(MAX_RECOMMENDATION_LIMIT);
+    Ok(recommend_courses_for(&caller(), &target_skills, limit).0)
 }
 
 #[query]
 #[candid_method(query)]
-fn get_published_courses() -> Vec<Course> {
-    COURSES.with(|courses| {
-        courses.borrow()
-            .values()
-            .filter(|course| course.is_published)
-            .cloned()
-            .collect()
-    })
+fn get_learning_path(path_id: String) -> Result<LearningPath> {
+    let path = LEARNING_PATHS.with(|paths| {
+        paths.borrow().get(&path_id).cloned()
+            .ok_or_else(|| ApiError::NotFound("Learning path not found".to_string()))
+    })?;
+    if !path.is_public && path.user_id != caller() {
+        return Err(ApiError::NotFound("Learning path not found".to_string()));
+    }
+    Ok(path)
 }
 
+// Owners see all of their paths; everyone else only sees public ones
 #[query]
 #[candid_method(query)]
-fn get_instructor_courses(instructor_id: Principal) -> Vec<Course> {
-    COURSES.with(|courses| {
-        courses.borrow()
-            .values()
-            .filter(|course| course.instructor_id == instructor_id)
-            .cloned()
-            .collect()
+fn get_user_learning_paths(
+    user_id: Principal,
+    cursor: Option<String>,
+    limit: Option<u32>,
+) -> Result<Page<LearningPath>> {
+    let caller_id = caller();
+    LEARNING_PATHS.with(|paths| {
+        paginate(
+            paths.borrow()
+                .values()
+                .filter(|path| path.user_id == user_id && (path.is_public || user_id == caller_id)),
+            |path| format!("{:020}:{}", u64::MAX - path.created_at, path.id),
+            cursor,
+            limit,
+        )
     })
 }
 
+// Recently updated paths first
 #[query]
 #[candid_method(query)]
-fn search_courses(query: Option<String>, category: Option<String>) -> Vec<Course> {
-    COURSES.with(|courses| {
-        courses.borrow()
-            .values()
-            .filter(|course| {
-                if !course.is_published {
-                    return false;
-                }
+fn get_public_learning_paths(cursor: Option<String>, limit: Option<u32>) -> Result<Page<LearningPath>> {
+    LEARNING_PATHS.with(|paths| {
+        paginate(
+            paths.borrow().values().filter(|path| path.is_public),
+            |path| format!("{:020}:{}", u64::MAX - path.updated_at, path.id),
+            cursor,
+            limit,
+        )
+    })
+}
 
-                if let Some(ref q) = query {
-                    let q_lower = q.to_lowercase();
-                    if !course.title.to_lowercase().contains(&q_lower) &&
-                       !course.description.to_lowercase().contains(&q_lower) {
-                        return false;
-                    }
-                }
+#[update]
+#[candid_method(update)]
+async fn update_learning_path(path_id: String, request: UpdateLearningPathRequest) -> Result<LearningPath> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
 
-                if let Some(ref cat) = category {
-                    if course.category != *cat {
-                        return false;
-                    }
-                }
+    let mut path = get_owned_learning_path(&path_id, caller_id)?;
 
-                true
-            })
-            .cloned()
-            .collect()
-    })
+    if let Some(title) = request.title {
+        if title.trim().is_empty() {
+            return Err(ApiError::InvalidInput("Learning path title cannot be empty".to_string()));
+        }
+        path.title = title;
+    }
+    if let Some(description) = request.description {
+        path.description = description;
+    }
+    if let Some(course_ids) = request.course_ids {
+        // Hand-editing the course list turns a generated path into a curated one
+        path.recommended_courses = curated_recommendations(&course_ids)?;
+        path.unavailable_prerequisites = vec![];
+        path.is_generated = false;
+    }
+    if let Some(is_public) = request.is_public {
+        path.is_public = is_public;
+    }
+
+    path.updated_at = get_current_time();
+    save_learning_path(&path);
+    Ok(path)
+}
+
+#[update]
+#[candid_method(update)]
+async fn delete_learning_path(path_id: String) -> Result<LearningPath> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    let path = get_owned_learning_path(&path_id, caller_id)?;
+    LEARNING_PATHS.with(|paths| paths.borrow_mut().remove(&path_id));
+    Ok(path)
+}
+
+// Freeze Functions
+
+fn ensure_not_frozen() -> Result<()> {
+    freeze::ensure_not_frozen(get_current_time()).map_err(|until| ApiError::Frozen { until })
+}
+
+#[update]
+#[candid_method(update)]
+fn set_frozen_until(frozen_until: Option<u64>) -> Result<bool> {
+    freeze::set_frozen_until(frozen_until).map_err(|_| ApiError::InsufficientPermissions)?;
+    Ok(true)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_frozen_until() -> Option<u64> {
+    freeze::frozen_until(get_current_time())
 }
 
 // Export candid interface
 export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::Future;
+
+    thread_local! {
+        static NOW: RefCell<u64> = const { RefCell::new(0) };
+    }
+
+    pub(super) fn now() -> u64 {
+        NOW.with(|now| *now.borrow())
+    }
+
+    fn set_now(time: u64) {
+        NOW.with(|now| *now.borrow_mut() = time);
+    }
+
+    fn principal(n: u8) -> Principal {
+        Principal::from_slice(&[n])
+    }
+
+    // Runs a future that makes no inter-canister calls to completion
+    fn now_or_never<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+        match future.as_mut().poll(&mut context) {
+            std::task::Poll::Ready(output) => output,
+            std::task::Poll::Pending => panic!("future made an inter-canister call"),
+        }
+    }
+
+    fn lesson(id: &str) -> Lesson {
+        Lesson {
+            id: id.to_string(),
+            course_id: "course_1".to_string(),
+            module_id: None,
+            title: id.to_string(),
+            description: String::new(),
+            content_type: ContentType::Text,
+            content_url: String::new(),
+            duration_minutes: 10,
+            order_index: 0,
+            prerequisites: vec![],
+            learning_objectives: vec![],
+            release_schedule: None,
+            is_archived: false,
+            previous_revision: None,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    fn course(id: &str) -> Course {
+        Course {
+            id: id.to_string(),
+            title: id.to_string(),
+            description: String::new(),
+            instructor_id: Principal::anonymous(),
+            category: "Programming".to_string(),
+            tags: vec![],
+            difficulty_level: DifficultyLevel::Beginner,
+            estimated_duration_hours: 1,
+            price: 0,
+            prerequisites: vec![],
+            language: "en".to_string(),
+            modules: vec![],
+            lessons: vec![],
+            enrollment_count: 0,
+            rating: 0.0,
+            review_count: 0,
+            is_published: false,
+            version: 0,
+            draft: None,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    // Stores course_1 with the given unassigned lessons, in order
+    fn store_course(lesson_ids: &[&str]) -> Course {
+        let course = Course {
+            lessons: lesson_ids.iter().map(|id| id.to_string()).collect(),
+            ..course("course_1")
+        };
+        LESSONS.with(|lessons| {
+            let mut lessons = lessons.borrow_mut();
+            for lesson_id in lesson_ids {
+                lessons.insert(lesson_id.to_string(), lesson(lesson_id));
+            }
+        });
+        save_course(&course);
+        renumber_lessons(&course);
+        course
+    }
+
+    // Publishes the course's current syllabus as its next version
+    fn publish(course: &mut Course) {
+        course.version += 1;
+        course.is_published = true;
+        save_course(course);
+        COURSE_VERSIONS.with(|versions| {
+            versions.borrow_mut().insert(course_version_key(&course.id, course.version), CourseVersion {
+                course_id: course.id.clone(),
+                version: course.version,
+                title: course.title.clone(),
+                description: course.description.clone(),
+                category: course.category.clone(),
+                tags: course.tags.clone(),
+                difficulty_level: course.difficulty_level.clone(),
+                estimated_duration_hours: course.estimated_duration_hours,
+                price: course.price,
+                prerequisites: course.prerequisites.clone(),
+                language: course.language.clone(),
+                modules: course.modules.clone(),
+                lessons: course.lessons.clone(),
+                published_at: now(),
+            })
+        });
+    }
+
+    // Enrolls a learner in course_1 with the given lessons completed
+    fn enroll(learner: Principal, completed_lessons: &[&str]) {
+        create_enrollment(learner, "course_1", None).unwrap();
+        ENROLLMENTS.with(|enrollments| {
+            let mut enrollments = enrollments.borrow_mut();
+            let enrollment = enrollments.get_mut(&enrollment_key(&learner, "course_1")).unwrap();
+            enrollment.progress.completed_lessons = completed_lessons.iter().map(|id| id.to_string()).collect();
+        });
+    }
+
+    // Adds an empty module to the end of the course
+    fn store_module(course: &mut Course, module_id: &str) {
+        save_module(&Module {
+            id: module_id.to_string(),
+            course_id: course.id.clone(),
+            title: module_id.to_string(),
+            description: String::new(),
+            order_index: course.modules.len() as u32,
+            release_schedule: None,
+            lessons: vec![],
+            created_at: 0,
+            updated_at: 0,
+        });
+        course.modules.push(module_id.to_string());
+        save_course(course);
+    }
+
+    fn module_lessons(module_id: &str) -> Vec<String> {
+        get_module(module_id.to_string()).unwrap().lessons
+    }
+
+    #[test]
+    fn days_to_nanoseconds_saturates() {
+        assert_eq!(days_to_nanoseconds(1), 86_400_000_000_000);
+        assert_eq!(days_to_nanoseconds(u32::MAX as u64), u64::MAX);
+    }
+
+    #[test]
+    fn release_delays_are_bounded() {
+        let within = ReleaseSchedule::DaysAfterEnrollment(MAX_RELEASE_DELAY_DAYS);
+        let beyond = ReleaseSchedule::DaysAfterEnrollment(MAX_RELEASE_DELAY_DAYS + 1);
+        assert!(validate_release_schedule(Some(&within)).is_ok());
+        assert!(validate_release_schedule(Some(&beyond)).is_err());
+        assert!(validate_release_schedule(None).is_ok());
+        assert_eq!(release_time(&beyond, u64::MAX - 1), u64::MAX);
+    }
+
+    #[test]
+    fn rating_buckets_are_cumulative() {
+        assert_eq!(rating_labels(4.5), vec!["4+ stars", "3+ stars", "2+ stars", "1+ stars"]);
+        assert_eq!(rating_labels(2.0), vec!["2+ stars", "1+ stars"]);
+        assert!(rating_labels(0.0).is_empty());
+    }
+
+    fn held_payment(run_id: Option<&str>, settles_at: u64) -> Payment {
+        Payment {
+            id: "payment_1".to_string(),
+            user_id: Principal::anonymous(),
+            course_id: "course_1".to_string(),
+            run_id: run_id.map(str::to_string),
+            instructor_id: Principal::anonymous(),
+            amount: 1_000,
+            instructor_share: 900,
+            platform_fee: 100,
+            block_index: Nat::from(1u64),
+            paid_at: 0,
+            settles_at,
+            status: PaymentStatus::Held,
+        }
+    }
+
+    #[test]
+    fn refunds_stay_inside_the_window() {
+        let payment = held_payment(None, 100);
+        assert!(is_refundable(&payment, None, 99));
+        assert!(!is_refundable(&payment, None, 100));
+
+        let settled = Payment { status: PaymentStatus::Settled, ..payment.clone() };
+        assert!(!is_refundable(&settled, None, 0));
+        let refunding = Payment { status: PaymentStatus::Refunding, ..payment };
+        assert!(!is_refundable(&refunding, None, 0));
+    }
+
+    #[test]
+    fn refunds_match_the_enrollment_paid_for() {
+        let run_payment = held_payment(Some("run_1"), 100);
+        assert!(is_refundable(&run_payment, Some("run_1"), 0));
+        assert!(!is_refundable(&run_payment, Some("run_2"), 0));
+        assert!(!is_refundable(&run_payment, None, 0));
+        assert!(!is_refundable(&held_payment(None, 100), Some("run_1"), 0));
+    }
+
+    #[test]
+    fn cancelling_is_rejected_while_a_payment_is_in_flight() {
+        let user_id = Principal::anonymous();
+        let lock_key = lock_enrollment(&user_id, "course_1").unwrap();
+        assert!(lock_enrollment(&user_id, "course_1").is_err());
+
+        // The second request fails before reaching the enrollment or the ledger
+        let cancelled = now_or_never(cancel_enrollment(user_id, "course_1", None));
+        assert!(matches!(cancelled, Err(ApiError::InvalidInput(_))));
+
+        unlock_enrollment(&lock_key);
+        assert!(lock_enrollment(&user_id, "course_1").is_ok());
+    }
+
+    #[test]
+    fn refund_fees_are_charged_to_the_platform() {
+        credit_platform(100);
+        debit_platform(30);
+        assert_eq!(PLATFORM_EARNINGS.with(|earnings| *earnings.borrow()), 70);
+
+        // A fee the balance cannot cover is taken from the next earnings
+        debit_platform(100);
+        assert_eq!(PLATFORM_EARNINGS.with(|earnings| *earnings.borrow()), 0);
+        credit_platform(50);
+        assert_eq!(PLATFORM_EARNINGS.with(|earnings| *earnings.borrow()), 20);
+        assert_eq!(PLATFORM_FEES_OWED.with(|owed| *owed.borrow()), 0);
+    }
+
+    #[test]
+    fn payment_split() {
+        assert_eq!(split_payment(1_000, 1_000), (900, 100));
+        assert_eq!(split_payment(u64::MAX, MAX_BPS as u16), (0, u64::MAX));
+    }
+
+    fn revision(id: &str, previous_revision: Option<&str>) -> Lesson {
+        Lesson { previous_revision: previous_revision.map(str::to_string), ..lesson(id) }
+    }
+
+    #[test]
+    fn pinned_syllabus_keeps_published_revision() {
+        LESSONS.with(|lessons| {
+            let mut lessons = lessons.borrow_mut();
+            for lesson in [revision("lesson_1", None), revision("lesson_2", Some("lesson_1")), revision("lesson_3", None)] {
+                lessons.insert(lesson.id.clone(), lesson);
+            }
+        });
+
+        assert_eq!(lesson_lineage("lesson_2"), vec!["lesson_2", "lesson_1"]);
+        let working = vec!["lesson_2".to_string(), "lesson_3".to_string()];
+        assert_eq!(pinned_lessons(&working, &["lesson_1".to_string()]), vec!["lesson_1"]);
+        assert_eq!(pinned_lessons(&working, &working), working);
+    }
+
+    #[test]
+    fn lessons_move_within_their_siblings() {
+        let mut course = store_course(&["lesson_1", "lesson_2", "lesson_3"]);
+
+        reorder_lesson(&mut course, &lesson("lesson_3"), 0).unwrap();
+        assert_eq!(course.lessons, vec!["lesson_3", "lesson_1", "lesson_2"]);
+        let order: Vec<u32> = load_lessons(&course.lessons).iter().map(|lesson| lesson.order_index).collect();
+        assert_eq!(order, vec![0, 1, 2]);
+
+        assert!(reorder_lesson(&mut course, &lesson("lesson_1"), 3).is_err());
+        assert_eq!(get_course("course_1".to_string()).unwrap().lessons, vec!["lesson_3", "lesson_1", "lesson_2"]);
+    }
+
+    #[test]
+    fn archived_lessons_leave_the_completion_denominator() {
+        let mut course = store_course(&["lesson_1", "lesson_2"]);
+        publish(&mut course);
+        let learner = Principal::anonymous();
+        enroll(learner, &["lesson_1"]);
+
+        let mut archived = lesson("lesson_2");
+        retire_lesson(&mut course, &mut archived).unwrap();
+        assert!(archived.is_archived);
+        assert_eq!(course.lessons, vec!["lesson_1"]);
+        let enrollment = get_user_enrollment(learner, "course_1".to_string()).unwrap();
+        assert_eq!(enrollment.completion_percentage, 100.0);
+    }
+
+    #[test]
+    fn lessons_are_assigned_to_modules_in_place() {
+        let mut course = store_course(&["lesson_1", "lesson_2", "lesson_3"]);
+        store_module(&mut course, "module_1");
+        store_module(&mut course, "module_2");
+
+        assign_lesson(&mut course, load_lesson("lesson_1").unwrap(), Some("module_1".to_string()), None).unwrap();
+        assign_lesson(&mut course, load_lesson("lesson_2").unwrap(), Some("module_1".to_string()), Some(0)).unwrap();
+        assert_eq!(module_lessons("module_1"), vec!["lesson_2", "lesson_1"]);
+        assert_eq!(course.lessons, vec!["lesson_3", "lesson_2", "lesson_1"]);
+
+        // Moving within the same module reorders it
+        assign_lesson(&mut course, load_lesson("lesson_1").unwrap(), Some("module_1".to_string()), Some(0)).unwrap();
+        assert_eq!(module_lessons("module_1"), vec!["lesson_1", "lesson_2"]);
+    }
+
+    #[test]
+    fn invalid_positions_leave_every_module_untouched() {
+        let mut course = store_course(&["lesson_1", "lesson_2"]);
+        store_module(&mut course, "module_1");
+        store_module(&mut course, "module_2");
+        assign_lesson(&mut course, load_lesson("lesson_1").unwrap(), Some("module_1".to_string()), None).unwrap();
+
+        let moved = assign_lesson(&mut course, load_lesson("lesson_1").unwrap(), Some("module_2".to_string()), Some(1));
+        assert!(moved.is_err());
+        assert_eq!(module_lessons("module_1"), vec!["lesson_1"]);
+        assert!(module_lessons("module_2").is_empty());
+        assert_eq!(load_lesson("lesson_1").unwrap().module_id.as_deref(), Some("module_1"));
+    }
+
+    #[test]
+    fn progress_is_reported_per_module() {
+        let mut course = store_course(&["lesson_1", "lesson_2", "lesson_3"]);
+        store_module(&mut course, "module_1");
+        store_module(&mut course, "module_2");
+        assign_lesson(&mut course, load_lesson("lesson_1").unwrap(), Some("module_1".to_string()), None).unwrap();
+        assign_lesson(&mut course, load_lesson("lesson_2").unwrap(), Some("module_1".to_string()), None).unwrap();
+        publish(&mut course);
+
+        let learner = Principal::anonymous();
+        enroll(learner, &["lesson_1", "lesson_3"]);
+        let progress = get_module_progress(learner, "course_1".to_string()).unwrap();
+        let summary: Vec<(&str, u32, u32)> = progress.iter()
+            .map(|module| (module.module_id.as_str(), module.completed_lessons, module.total_lessons))
+            .collect();
+        assert_eq!(summary, vec![("module_1", 1, 2), ("module_2", 0, 0)]);
+        assert_eq!(progress[0].completion_percentage, 50.0);
+    }
+
+    fn store_run(capacity: u32, waitlist: Vec<Principal>) -> CourseRun {
+        let run = CourseRun {
+            id: "run_1".to_string(),
+            course_id: "course_1".to_string(),
+            title: "Cohort".to_string(),
+            start_date: 100,
+            end_date: 1_000,
+            enrollment_opens_at: 0,
+            enrollment_closes_at: 100,
+            capacity,
+            enrollment_count: 0,
+            waitlist,
+            created_at: 0,
+            updated_at: 0,
+        };
+        save_course_run(&run);
+        run
+    }
+
+    #[test]
+    fn waitlist_is_promoted_in_order() {
+        let mut course = store_course(&["lesson_1"]);
+        publish(&mut course);
+        store_run(2, vec![principal(1), principal(2), principal(3)]);
+        // Already enrolled learners give up their place without taking a seat
+        enroll(principal(1), &[]);
+
+        let run = now_or_never(promote_waitlist("run_1")).unwrap();
+        assert_eq!(run.enrollment_count, 2);
+        assert!(run.waitlist.is_empty());
+        for learner in [principal(2), principal(3)] {
+            let enrollment = get_user_enrollment(learner, "course_1".to_string()).unwrap();
+            assert_eq!(enrollment.run_id.as_deref(), Some("run_1"));
+        }
+        assert_eq!(get_user_enrollment(principal(1), "course_1".to_string()).unwrap().run_id, None);
+    }
+
+    #[test]
+    fn waitlist_stops_at_capacity_and_after_the_run() {
+        let mut course = store_course(&["lesson_1"]);
+        publish(&mut course);
+        store_run(1, vec![principal(1), principal(2)]);
+
+        let run = now_or_never(promote_waitlist("run_1")).unwrap();
+        assert_eq!(run.waitlist, vec![principal(2)]);
+
+        release_run_seat("run_1").unwrap();
+        set_now(1_000);
+        let run = now_or_never(promote_waitlist("run_1")).unwrap();
+        assert_eq!(run.enrollment_count, 0);
+        assert_eq!(run.waitlist, vec![principal(2)]);
+    }
+
+    #[test]
+    fn discussion_votes_count_once_per_principal() {
+        let (mut upvotes, mut downvotes) = (0, 0);
+        let mut vote = |user_id: Principal, vote: Option<DiscussionVote>| {
+            apply_discussion_vote("thread_1", &user_id, vote, &mut upvotes, &mut downvotes);
+        };
+
+        vote(principal(1), Some(DiscussionVote::Upvote));
+        vote(principal(1), Some(DiscussionVote::Upvote));
+        vote(principal(2), Some(DiscussionVote::Upvote));
+        // Switching sides moves the vote, and retracting removes it
+        vote(principal(1), Some(DiscussionVote::Downvote));
+        vote(principal(2), None);
+        vote(principal(3), None);
+        assert_eq!((upvotes, downvotes), (0, 1));
+
+        let (mut reply_upvotes, mut reply_downvotes) = (0, 0);
+        apply_discussion_vote("reply_1", &principal(1), Some(DiscussionVote::Upvote), &mut reply_upvotes, &mut reply_downvotes);
+        assert_eq!((reply_upvotes, reply_downvotes), (1, 0));
+    }
+
+    fn thread(author_id: Principal, visibility: ContentVisibility, replies: Vec<ThreadReply>) -> DiscussionThread {
+        DiscussionThread {
+            id: "thread_1".to_string(),
+            course_id: "course_1".to_string(),
+            lesson_id: None,
+            run_id: None,
+            author_id,
+            title: "Title".to_string(),
+            content: "Content".to_string(),
+            created_at: 0,
+            updated_at: 0,
+            last_activity_at: 0,
+            replies,
+            tags: vec![],
+            upvotes: 0,
+            downvotes: 0,
+            is_pinned: false,
+            is_locked: false,
+            visibility,
+        }
+    }
+
+    fn reply(author_id: Principal, visibility: ContentVisibility) -> ThreadReply {
+        ThreadReply {
+            id: "reply_1".to_string(),
+            author_id,
+            content: "Reply".to_string(),
+            created_at: 0,
+            updated_at: 0,
+            upvotes: 0,
+            downvotes: 0,
+            parent_reply_id: None,
+            visibility,
+        }
+    }
+
+    #[test]
+    fn hidden_content_is_shown_to_its_author_only() {
+        let (author, reader) = (principal(1), principal(2));
+        let hidden = thread(author, ContentVisibility::Hidden, vec![reply(reader, ContentVisibility::Hidden)]);
+
+        let seen_by_author = thread_view(hidden.clone(), &author);
+        assert_eq!(seen_by_author.content, "Content");
+        assert_eq!(seen_by_author.replies[0].content, "");
+
+        let seen_by_reader = thread_view(hidden, &reader);
+        assert_eq!((seen_by_reader.title.as_str(), seen_by_reader.content.as_str()), ("", ""));
+        assert_eq!(seen_by_reader.replies[0].content, "Reply");
+    }
+
+    #[test]
+    fn deleted_replies_are_shown_to_no_one() {
+        let author = principal(1);
+        let visible = thread(author, ContentVisibility::Visible, vec![reply(author, ContentVisibility::Deleted)]);
+
+        let seen_by_author = thread_view(visible, &author);
+        assert_eq!(seen_by_author.content, "Content");
+        assert_eq!(seen_by_author.replies[0].content, "");
+    }
+
+    #[test]
+    fn reports_are_resolved_once() {
+        save_report(&ContentReport {
+            id: 1,
+            reporter_id: principal(2),
+            target: ReportTarget::Thread { thread_id: "thread_1".to_string() },
+            reason: "Spam".to_string(),
+            status: ReportStatus::Open,
+            created_at: 0,
+            resolved_at: None,
+            resolved_by: None,
+            action_id: None,
+        });
+
+        // A second moderator is turned away while the first is resolving it
+        assert!(claim_report(1).is_ok());
+        assert!(claim_report(1).is_err());
+        release_report(1);
+        assert!(claim_report(1).is_ok());
+        release_report(1);
+
+        let mut report = get_report(1).unwrap();
+        report.status = ReportStatus::Dismissed;
+        save_report(&report);
+        assert!(claim_report(1).is_err());
+    }
+
+    // Stores a published course teaching the given tags
+    fn store_catalog_course(id: &str, tags: &[&str], prerequisites: &[&str]) {
+        save_course(&Course {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            prerequisites: prerequisites.iter().map(|id| id.to_string()).collect(),
+            is_published: true,
+            version: 1,
+            estimated_duration_hours: 10,
+            ..course(id)
+        });
+    }
+
+    fn recommended_ids(user_id: &Principal, skills: &[&str]) -> (Vec<String>, Vec<String>) {
+        let skills: Vec<String> = skills.iter().map(|skill| skill.to_string()).collect();
+        let (recommendations, unavailable) = recommend_courses_for(user_id, &skills, 5);
+        (recommendations.into_iter().map(|recommendation| recommendation.course_id).collect(), unavailable)
+    }
+
+    #[test]
+    fn prerequisites_are_planned_before_their_goals() {
+        store_catalog_course("rust_basics", &["rust"], &[]);
+        store_catalog_course("async_rust", &["async"], &["rust_basics", "draft_course"]);
+        save_course(&course("draft_course"));
+
+        let learner = principal(1);
+        let (ordered, unavailable) = recommended_ids(&learner, &["Async"]);
+        assert_eq!(ordered, vec!["rust_basics", "async_rust"]);
+        assert_eq!(unavailable, vec!["draft_course"]);
+
+        // Completed prerequisites drop out of the plan
+        create_enrollment(learner, "rust_basics", None).unwrap();
+        ENROLLMENTS.with(|enrollments| {
+            let mut enrollments = enrollments.borrow_mut();
+            enrollments.get_mut(&enrollment_key(&learner, "rust_basics")).unwrap().completion_percentage = 100.0;
+        });
+        assert_eq!(recommended_ids(&learner, &["async"]).0, vec!["async_rust"]);
+    }
+
+    #[test]
+    fn prerequisite_cycles_are_planned_once() {
+        store_catalog_course("graphs_1", &["graphs"], &["graphs_2"]);
+        store_catalog_course("graphs_2", &[], &["graphs_1"]);
+
+        let (ordered, unavailable) = recommended_ids(&principal(1), &["graphs"]);
+        assert_eq!(ordered, vec!["graphs_2", "graphs_1"]);
+        assert!(unavailable.is_empty());
+    }
+
+    #[test]
+    fn recommendations_favour_skill_coverage() {
+        store_catalog_course("rust_web", &["rust", "web"], &[]);
+        store_catalog_course("rust_cli", &["rust"], &[]);
+
+        let skills = vec!["rust".to_string(), "web".to_string()];
+        let (recommendations, _) = recommend_courses_for(&principal(1), &skills, 1);
+        assert_eq!(recommendations.len(), 1);
+        assert_eq!(recommendations[0].course_id, "rust_web");
+        assert_eq!(recommendations[0].priority_score, 0.6);
+        assert_eq!(recommendations[0].estimated_completion_time, 10);
+    }
+
+    fn search_request(query: Option<&str>) -> SearchRequest {
+        SearchRequest {
+            query: query.map(str::to_string),
+            category: None,
+            difficulty_level: None,
+            min_price: None,
+            max_price: None,
+            free_only: None,
+            min_duration_hours: None,
+            max_duration_hours: None,
+            min_rating: None,
+            instructor_id: None,
+            tags: None,
+            tag_match: None,
+            language: None,
+            sort: None,
+            cursor: None,
+            limit: None,
+        }
+    }
+
+    fn hit_ids(results: &SearchResults) -> Vec<&str> {
+        results.hits.items.iter().map(|hit| hit.course.id.as_str()).collect()
+    }
+
+    fn facet(counts: &[FacetCount]) -> Vec<(&str, u32)> {
+        counts.iter().map(|count| (count.value.as_str(), count.count)).collect()
+    }
+
+    #[test]
+    fn search_ranks_title_matches_above_description_matches() {
+        save_course(&Course {
+            description: "Writing tests for every module".to_string(),
+            is_published: true,
+            ..course("described")
+        });
+        save_course(&Course {
+            title: "Testing in Rust".to_string(),
+            is_published: true,
+            ..course("titled")
+        });
+        save_course(&Course { title: "Testing drafts".to_string(), ..course("unpublished") });
+
+        // "tests" and "Testing" share a stem; unpublished courses are not indexed
+        let results = search_courses(search_request(Some("tests"))).unwrap();
+        assert_eq!(hit_ids(&results), vec!["titled", "described"]);
+        assert!(results.hits.items[0].score > results.hits.items[1].score);
+        assert!(search_courses(search_request(Some("the"))).unwrap().hits.items.is_empty());
+    }
+
+    #[test]
+    fn facets_ignore_their_own_filter() {
+        for (id, category) in [("rust", "Programming"), ("go", "Programming"), ("figma", "Design")] {
+            save_course(&Course { category: category.to_string(), is_published: true, ..course(id) });
+        }
+
+        let request = SearchRequest { category: Some("Design".to_string()), ..search_request(None) };
+        let results = search_courses(request).unwrap();
+        assert_eq!(hit_ids(&results), vec!["figma"]);
+        assert_eq!(results.total, 1);
+        assert_eq!(facet(&results.categories), vec![("Programming", 2), ("Design", 1)]);
+        assert_eq!(facet(&results.difficulty_levels), vec![("Beginner", 1)]);
+    }
+
+    #[test]
+    fn catalog_filters_sort_and_count_prices() {
+        let courses = [("free_short", 0, 2), ("free_long", 0, 30), ("paid_short", 5_000, 3)];
+        for (id, price, hours) in courses {
+            save_course(&Course {
+                price,
+                estimated_duration_hours: hours,
+                is_published: true,
+                ..course(id)
+            });
+        }
+
+        let request = SearchRequest {
+            free_only: Some(true),
+            sort: Some(CourseSort::Shortest),
+            ..search_request(None)
+        };
+        let results = search_courses(request).unwrap();
+        assert_eq!(hit_ids(&results), vec!["free_short", "free_long"]);
+        assert_eq!(facet(&results.price_ranges), vec![("Free", 2), ("Paid", 1)]);
+        assert_eq!(facet(&results.durations), vec![("Over 20 hours", 1), ("Under 5 hours", 1)]);
+
+        // Equally popular courses fall back to id order
+        let request = SearchRequest { max_duration_hours: Some(5), ..search_request(None) };
+        assert_eq!(hit_ids(&search_courses(request).unwrap()), vec!["free_short", "paid_short"]);
+    }
+
+    // Stores a visible review of course_1 and counts it towards the rating
+    fn store_review(id: &str, rating: u8) -> Review {
+        let review = Review {
+            id: id.to_string(),
+            course_id: "course_1".to_string(),
+            user_id: principal(1),
+            rating,
+            comment: String::new(),
+            created_at: 0,
+            updated_at: 0,
+            helpful_votes: 0,
+            unhelpful_votes: 0,
+            instructor_response: None,
+            visibility: ContentVisibility::Visible,
+        };
+        save_review(&review);
+        adjust_course_rating("course_1", Some(rating), None);
+        review
+    }
+
+    fn course_rating() -> (f32, u32) {
+        let course = get_course("course_1".to_string()).unwrap();
+        (course.rating, course.review_count)
+    }
+
+    #[test]
+    fn ratings_are_pulled_towards_the_prior() {
+        store_course(&[]);
+        assert_eq!(course_rating(), (0.0, 0));
+
+        // (5 * 3 + 5) / (5 + 1)
+        store_review("review_1", 5);
+        assert_eq!(course_rating(), (20.0 / 6.0, 1));
+        store_review("review_2", 1);
+        assert_eq!(course_rating(), (3.0, 2));
+    }
+
+    #[test]
+    fn hidden_and_deleted_reviews_leave_the_rating() {
+        store_course(&[]);
+        store_review("review_1", 5);
+        let review = store_review("review_2", 1);
+        let target = ReportTarget::Review { review_id: review.id.clone() };
+
+        set_content_visibility(&target, ContentVisibility::Hidden).unwrap();
+        assert_eq!(course_rating(), (20.0 / 6.0, 1));
+        // Moving between hidden and deleted does not count the review twice
+        set_content_visibility(&target, ContentVisibility::Deleted).unwrap();
+        assert_eq!(course_rating(), (20.0 / 6.0, 1));
+        set_content_visibility(&target, ContentVisibility::Visible).unwrap();
+        assert_eq!(course_rating(), (3.0, 2));
+
+        remove_review(&get_stored_review("review_2").unwrap());
+        assert_eq!(course_rating(), (20.0 / 6.0, 1));
+        assert!(get_stored_review("review_2").is_err());
+
+        // Deleting a hidden review does not take it out of the rating again
+        set_content_visibility(&ReportTarget::Review { review_id: "review_1".to_string() }, ContentVisibility::Hidden).unwrap();
+        assert_eq!(course_rating(), (0.0, 0));
+        remove_review(&get_stored_review("review_1").unwrap());
+        assert_eq!(course_rating(), (0.0, 0));
+    }
+}
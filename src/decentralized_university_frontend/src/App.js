@@ -25,21 +25,36 @@ class App {
     this.#setupEventListeners();
   }
 
+  // List queries return one page at a time; follow the cursors to load everything
+  #loadAllPages = async (fetchPage) => {
+    const items = [];
+    let cursor = [];
+    do {
+      const result = await fetchPage(cursor, [100]);
+      if (!('Ok' in result)) {
+        throw new Error(Object.keys(result.Err)[0]);
+      }
+      items.push(...result.Ok.items);
+      cursor = result.Ok.next_cursor;
+    } while (cursor.length > 0);
+    return items;
+  };
+
   #loadStats = async () => {
     try {
       // Load user statistics
       this.userCount = await user_management.get_user_count();
       
       // Load course statistics
-      this.courses = await course_management.get_all_courses();
+      this.courses = await this.#loadAllPages((cursor, limit) => course_management.get_all_courses(cursor, limit));
       this.courseCount = this.courses.length;
       
       // Load certification statistics
-      this.certifications = await certification_system.get_all_certifications();
+      this.certifications = await this.#loadAllPages((cursor, limit) => certification_system.get_all_certifications(cursor, limit));
       this.certificationCount = this.certifications.length;
       
       // Load governance statistics (using active proposals since get_governance_stats doesn't exist)
-      this.proposals = await governance.get_active_proposals(null);
+      this.proposals = await this.#loadAllPages((cursor, limit) => governance.get_active_proposals(cursor, limit));
       this.proposalCount = this.proposals.length;
       
       this.status = 'Platform statistics loaded successfully!';
@@ -130,7 +145,9 @@ class App {
       tags: data.tags ? data.tags.split(',').map(s => s.trim()) : [],
       difficulty_level: { [data.difficulty]: null },
       estimated_duration_hours: parseInt(data.duration) || 1,
-      price: 0
+      price: 0,
+      prerequisites: [],
+      language: 'en'
     };
     
     const result = await course_management.create_course(courseRequest);
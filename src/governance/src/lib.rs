@@ -1,8 +1,18 @@
-use candid::{candid_method, CandidType, Deserialize, Principal};
-use ic_cdk::api::time;
-use ic_cdk::{caller, export_candid, query, update};
+use candid::{candid_method, CandidType, Deserialize, Nat, Principal};
+use ic_cdk::api::management_canister::main::{
+    clear_chunk_store, install_chunked_code, start_canister, stop_canister, upload_chunk, CanisterIdRecord,
+    CanisterInstallMode, ChunkHash, ClearChunkStoreArgument, InstallChunkedCodeArgument, UploadChunkArgument,
+};
+use ic_cdk::{caller, export_candid, id, post_upgrade, pre_upgrade, query, update};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use shared::icrc::{
+    describe_transfer_error, describe_transfer_from_error, memo_for, Account, TransferArg, TransferError,
+    TransferFromArgs, TransferFromError, MAX_MEMO_BYTES,
+};
+use shared::pagination::{paginate, InvalidCursor, Page, MAX_PAGE_SIZE};
+use shared::stable;
 
 // Define simple types inline
 #[derive(Clone, Debug, CandidType, Deserialize)]
@@ -14,29 +24,184 @@ pub struct Proposal {
     pub proposal_type: ProposalType,
     pub status: ProposalStatus,
     pub created_at: u64,
+    pub co_sponsors: Vec<Principal>,
+    pub version: u32, // bumped by each amendment
+    pub voting_duration_days: u64,
+    pub voting_opens_at: Option<u64>, // set when the draft is submitted for discussion
     pub voting_deadline: u64,
     pub votes_for: u64,
     pub votes_against: u64,
     pub votes_abstain: u64,
+    pub delegated_for: u64, // power that followed a delegate's vote, counted when voting closes
+    pub delegated_against: u64,
+    pub delegated_abstain: u64,
     pub minimum_threshold: u64,
+    pub quorum: u64, // vote weight that must take part, including abstentions; set when discussion starts
+    pub approval: ApprovalThreshold,
     pub executed_at: Option<u64>,
+    pub execution_error: Option<String>, // why the last execution attempt failed, if it did
+    pub fee: ProposalFee,
+    pub spam_flags: u64, // vote weight of Against voters who flagged the proposal as spam
+    pub mechanism: VotingMechanism, // fixed when the proposal is created
+}
+
+// Share of the For and Against weight that must be For. Abstentions are
+// left out, so they count toward quorum but not toward approval.
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum ApprovalThreshold {
+    SimpleMajority,
+    TwoThirds,
+    ThreeQuarters,
+}
+
+// Quorum is a percentage of the total eligible voting power when the
+// proposal is created; minimum_voting_threshold still applies as a floor
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct VotingRule {
+    pub topic: ProposalTopic,
+    pub quorum_percent: u8,
+    pub approval: ApprovalThreshold,
+}
+
+// How voting power turns into vote weight. Quadratic voting gives each voter
+// the square root of their power, so weight costs power squared. Conviction
+// voting weights a vote by how long it has been held, approaching its full
+// power over time. A conviction vote cast just before the deadline counts for
+// almost nothing, and changing a vote starts its conviction over from zero.
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum VotingMechanism {
+    Linear,
+    Quadratic,
+    Conviction,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct MechanismSetting {
+    pub topic: ProposalTopic,
+    pub mechanism: VotingMechanism,
+}
+
+// Anti-spam deposit taken from the proposer when a proposal is created. It
+// is returned once the proposal reaches quorum and otherwise goes to the treasury.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ProposalFee {
+    pub amount: u64,
+    pub collected_in: Option<Nat>, // ledger block of the escrow transfer
+    pub status: ProposalFeeStatus,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum ProposalFeeStatus {
+    Waived,
+    Escrowed,
+    Settling, // refund or forfeit transfer in flight
+    Refunded { block_index: Nat },
+    Forfeited { block_index: Nat },
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
 pub enum ProposalStatus {
+    Draft,      // gathering co-sponsors
+    Discussion, // open for comment and amendment before voting
     Active,
     Passed,
     Rejected,
     Executed,
+    Cancelled,
+}
+
+// A proposal's text and type before an amendment replaced them
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ProposalRevision {
+    pub proposal_id: u64,
+    pub version: u32,
+    pub title: String,
+    pub description: String,
+    pub proposal_type: ProposalType,
+    pub amended_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct AmendProposalRequest {
+    pub title: String,
+    pub description: String,
+    pub proposal_type: ProposalType, // must stay on the same topic
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
 pub enum ProposalType {
     CourseApproval { course_id: String },
     InstructorVerification { instructor_id: Principal },
-    PlatformUpgrade { upgrade_details: String },
+    // Upgrades one of the platform canisters to the wasm with this SHA-256
+    // hash, which the proposer uploads in chunks before execution
+    PlatformUpgrade { canister_id: Principal, wasm_hash: Vec<u8>, arg: Vec<u8>, upgrade_details: String },
     TokenomicsChange { change_details: String },
-    GovernanceParameter { parameter: String, new_value: String },
+    GovernanceParameter { parameter: GovernanceParameter },
+    ModerationAppeal { action_id: u64 },
+    TreasurySpend { recipient: Account, amount: u64, memo: Option<String> },
+    ScholarshipGrant { recipient: Principal, course_id: String, amount: u64 },
+    // Guardian pauses are short; keeping one in place for longer needs a
+    // proposal, which votes on the emergency fast path
+    ExtendPause { scope: PauseScope, hours: u64 },
+}
+
+// A new value for one GovernanceConfig field. Values are checked against
+// their bounds when the proposal is created, so passing proposals always apply.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum GovernanceParameter {
+    MinimumProposalThreshold { value: u64 },
+    MinimumVotingThreshold { value: u64 },
+    VotingPeriodDays { value: u64 },
+    ExecutionDelayDays { value: u64 },
+    ProposalFee { value: u64 },
+    VotingMechanism { topic: ProposalTopic, mechanism: VotingMechanism },
+    VotingRule { topic: ProposalTopic, quorum_percent: u8, approval: ApprovalThreshold },
+    RequiredCoSponsors { value: u32 },
+    DiscussionPeriodDays { value: u64 },
+}
+
+// Delegations can cover a single kind of proposal or, with no topic, all of them
+#[derive(Clone, Copy, Debug, CandidType, Deserialize, PartialEq)]
+pub enum ProposalTopic {
+    CourseApproval,
+    InstructorVerification,
+    PlatformUpgrade,
+    Tokenomics,
+    GovernanceParameter,
+    ModerationAppeal,
+    Treasury,
+    Emergency,
+}
+
+const ALL_TOPICS: [ProposalTopic; 8] = [
+    ProposalTopic::CourseApproval,
+    ProposalTopic::InstructorVerification,
+    ProposalTopic::PlatformUpgrade,
+    ProposalTopic::Tokenomics,
+    ProposalTopic::GovernanceParameter,
+    ProposalTopic::ModerationAppeal,
+    ProposalTopic::Treasury,
+    ProposalTopic::Emergency,
+];
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct Delegation {
+    pub delegator: Principal,
+    pub delegate: Principal,
+    pub topic: Option<ProposalTopic>,
+    pub created_at: u64,
+}
+
+// Voting power on a proposal, split by whether it was cast directly or
+// followed a delegate
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ProposalTally {
+    pub direct_for: u64,
+    pub direct_against: u64,
+    pub direct_abstain: u64,
+    pub delegated_for: u64,
+    pub delegated_against: u64,
+    pub delegated_abstain: u64,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
@@ -52,7 +217,9 @@ pub struct Vote {
     pub voter_id: Principal,
     pub vote_type: VoteType,
     pub voting_power: u64,
+    pub weight: u64, // voting power under the proposal's mechanism, before any conviction decay
     pub timestamp: u64,
+    pub revision: u32, // 1 for the first vote, incremented each time it is changed
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
@@ -63,33 +230,252 @@ pub struct CreateProposalRequest {
     pub voting_duration_days: u64,
 }
 
+// Mirrors user_management's account status
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum AccountStatus {
+    Active,
+    Suspended { until: u64, reason: String },
+    Banned { until: Option<u64>, reason: String },
+}
+
 #[derive(Clone, Debug, CandidType, Deserialize)]
 pub enum ApiError {
     NotFound(String),
+    Unauthorized,
     InvalidInput(String),
     InsufficientPermissions,
     AlreadyExists(String),
     InternalError(String),
+    AccountRestricted(AccountStatus),
+    PaymentFailed(String),
 }
 
 type Result<T> = std::result::Result<T, ApiError>;
 
-// In-memory storage
+impl From<InvalidCursor> for ApiError {
+    fn from(_: InvalidCursor) -> Self {
+        ApiError::InvalidInput("Invalid cursor".to_string())
+    }
+}
+
+// The parts of a course_management moderation action that appeals need
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ModerationActionSummary {
+    pub id: u64,
+    pub affected_user: Option<Principal>,
+    pub overturned: bool,
+}
+
+// What a pause covers: execution of passed proposals, or the mutating
+// endpoints of one platform canister
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum PauseScope {
+    ProposalExecution,
+    Canister { canister_id: Principal },
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct Pause {
+    pub scope: PauseScope,
+    pub paused_until: u64,
+    pub guardian_cooldown_until: u64, // guardians cannot pause this scope again before then
+}
+
+// Emergency actions that take effect once enough guardians approve them
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum GuardianAction {
+    Pause { scope: PauseScope, hours: u64 },
+    Resume { scope: PauseScope },
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct GuardianRequest {
+    pub id: u64,
+    pub action: GuardianAction,
+    pub requested_by: Principal,
+    pub approvals: Vec<Principal>,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub applied_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct GuardianSet {
+    pub guardians: Vec<Principal>,
+    pub threshold: u32, // approvals needed for an action
+}
+
+// Public record of every use of guardian power
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct GuardianLogEntry {
+    pub id: u64,
+    pub actor: Principal,
+    pub event: GuardianEvent,
+    pub timestamp: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum GuardianEvent {
+    GuardiansSet { guardians: Vec<Principal>, threshold: u32 },
+    Requested { request_id: u64, action: GuardianAction },
+    Approved { request_id: u64 },
+    Applied { request_id: u64 },
+    ApplyFailed { request_id: u64, message: String },
+    ExtendedByProposal { proposal_id: u64, scope: PauseScope, paused_until: u64 },
+}
+
+// Progress of a proposal's wasm upload. The chunks are checked against the
+// expected hash when the wasm is installed.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct WasmUploadStatus {
+    pub proposal_id: u64,
+    pub uploaded_bytes: u64,
+    pub chunk_count: u64,
+    pub expected_hash: Vec<u8>,
+}
+
+// Chunks of a proposal's wasm, in order. The chunks themselves are kept in
+// the chunk store of the canister being upgraded.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+struct WasmUpload {
+    canister_id: Principal,
+    chunk_hashes: Vec<ChunkHash>,
+    uploaded_bytes: u64,
+    installed: bool, // the canister runs this wasm, but may not have been started yet
+}
+
+// A transfer into or out of the treasury
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct TreasuryMovement {
+    pub id: u64,
+    pub kind: TreasuryMovementKind,
+    pub direction: MovementDirection,
+    pub amount: u64,
+    pub counterparty: Account,
+    pub block_index: Nat,
+    pub timestamp: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum TreasuryMovementKind {
+    ForfeitedFee { proposal_id: u64 },
+    Spend { proposal_id: u64, memo: Option<String> },
+    ScholarshipGrant { proposal_id: u64, course_id: String },
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum MovementDirection {
+    Inflow,
+    Outflow,
+}
+
+// In-memory storage. Other calls can run while an update awaits an
+// inter-canister call, so updates read what they change again after the call
+// instead of writing back a copy read before it.
 thread_local! {
     static PROPOSALS: RefCell<HashMap<u64, Proposal>> = RefCell::new(HashMap::new());
-    static VOTES: RefCell<HashMap<String, Vote>> = RefCell::new(HashMap::new());
+    static VOTES: RefCell<HashMap<u64, HashMap<Principal, Vote>>> = RefCell::new(HashMap::new()); // by proposal id, then voter
     static USER_VOTING_POWER: RefCell<HashMap<String, u64>> = RefCell::new(HashMap::new());
     static GOVERNANCE_CONFIG: RefCell<GovernanceConfig> = RefCell::new(GovernanceConfig::default());
-    static PROPOSAL_COUNTER: RefCell<u64> = RefCell::new(0);
+    static DELEGATIONS: RefCell<HashMap<String, Delegation>> = RefCell::new(HashMap::new());
+    static PROPOSAL_REVISIONS: RefCell<Vec<ProposalRevision>> = const { RefCell::new(Vec::new()) };
+    static VOTE_HISTORY: RefCell<Vec<Vote>> = const { RefCell::new(Vec::new()) }; // every vote cast, including replaced ones
+    static SPAM_FLAGS: RefCell<HashSet<String>> = RefCell::new(HashSet::new()); // vote keys of voters who flagged spam
+    static LEDGER_CANISTER: RefCell<Option<Principal>> = const { RefCell::new(None) };
+    static TREASURY_MOVEMENTS: RefCell<Vec<TreasuryMovement>> = const { RefCell::new(Vec::new()) };
+    static WASM_UPLOADS: RefCell<HashMap<u64, WasmUpload>> = RefCell::new(HashMap::new()); // by proposal id
+    static UPLOADS_IN_FLIGHT: RefCell<HashSet<u64>> = RefCell::new(HashSet::new()); // proposal ids with a chunk being stored
+    static GUARDIANS: RefCell<GuardianSet> = const { RefCell::new(GuardianSet { guardians: Vec::new(), threshold: 0 }) };
+    static GUARDIAN_REQUESTS: RefCell<HashMap<u64, GuardianRequest>> = RefCell::new(HashMap::new());
+    static GUARDIAN_LOG: RefCell<Vec<GuardianLogEntry>> = const { RefCell::new(Vec::new()) };
+    static PAUSES: RefCell<HashMap<String, Pause>> = RefCell::new(HashMap::new());
+    static GUARDIAN_REQUEST_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    static EXECUTING_PROPOSALS: RefCell<HashSet<u64>> = RefCell::new(HashSet::new());
+    static PROPOSAL_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+}
+
+// Stable Memory Functions
+
+// Everything kept across upgrades. Executions and chunk uploads in flight
+// are not kept, since the canister is stopped for the upgrade.
+#[derive(CandidType, Deserialize)]
+struct StableState {
+    proposals: HashMap<u64, Proposal>,
+    votes: HashMap<u64, HashMap<Principal, Vote>>,
+    user_voting_power: HashMap<String, u64>,
+    governance_config: GovernanceConfig,
+    delegations: HashMap<String, Delegation>,
+    proposal_revisions: Vec<ProposalRevision>,
+    vote_history: Vec<Vote>,
+    spam_flags: HashSet<String>,
+    ledger_canister: Option<Principal>,
+    treasury_movements: Vec<TreasuryMovement>,
+    wasm_uploads: HashMap<u64, WasmUpload>,
+    guardians: GuardianSet,
+    guardian_requests: HashMap<u64, GuardianRequest>,
+    guardian_log: Vec<GuardianLogEntry>,
+    pauses: HashMap<String, Pause>,
+    guardian_request_counter: u64,
+    proposal_counter: u64,
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let state = StableState {
+        proposals: PROPOSALS.with(RefCell::take),
+        votes: VOTES.with(RefCell::take),
+        user_voting_power: USER_VOTING_POWER.with(RefCell::take),
+        governance_config: GOVERNANCE_CONFIG.with(|governance_config| governance_config.borrow().clone()),
+        delegations: DELEGATIONS.with(RefCell::take),
+        proposal_revisions: PROPOSAL_REVISIONS.with(RefCell::take),
+        vote_history: VOTE_HISTORY.with(RefCell::take),
+        spam_flags: SPAM_FLAGS.with(RefCell::take),
+        ledger_canister: LEDGER_CANISTER.with(RefCell::take),
+        treasury_movements: TREASURY_MOVEMENTS.with(RefCell::take),
+        wasm_uploads: WASM_UPLOADS.with(RefCell::take),
+        guardians: GUARDIANS.with(|guardians| guardians.borrow().clone()),
+        guardian_requests: GUARDIAN_REQUESTS.with(RefCell::take),
+        guardian_log: GUARDIAN_LOG.with(RefCell::take),
+        pauses: PAUSES.with(RefCell::take),
+        guardian_request_counter: GUARDIAN_REQUEST_COUNTER.with(RefCell::take),
+        proposal_counter: PROPOSAL_COUNTER.with(RefCell::take),
+    };
+    stable::save(state);
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    let Some(state) = stable::restore::<StableState>() else { return };
+    PROPOSALS.with(|proposals| *proposals.borrow_mut() = state.proposals);
+    VOTES.with(|votes| *votes.borrow_mut() = state.votes);
+    USER_VOTING_POWER.with(|user_voting_power| *user_voting_power.borrow_mut() = state.user_voting_power);
+    GOVERNANCE_CONFIG.with(|governance_config| *governance_config.borrow_mut() = state.governance_config);
+    DELEGATIONS.with(|delegations| *delegations.borrow_mut() = state.delegations);
+    PROPOSAL_REVISIONS.with(|proposal_revisions| *proposal_revisions.borrow_mut() = state.proposal_revisions);
+    VOTE_HISTORY.with(|vote_history| *vote_history.borrow_mut() = state.vote_history);
+    SPAM_FLAGS.with(|spam_flags| *spam_flags.borrow_mut() = state.spam_flags);
+    LEDGER_CANISTER.with(|ledger_canister| *ledger_canister.borrow_mut() = state.ledger_canister);
+    TREASURY_MOVEMENTS.with(|treasury_movements| *treasury_movements.borrow_mut() = state.treasury_movements);
+    WASM_UPLOADS.with(|wasm_uploads| *wasm_uploads.borrow_mut() = state.wasm_uploads);
+    GUARDIANS.with(|guardians| *guardians.borrow_mut() = state.guardians);
+    GUARDIAN_REQUESTS.with(|guardian_requests| *guardian_requests.borrow_mut() = state.guardian_requests);
+    GUARDIAN_LOG.with(|guardian_log| *guardian_log.borrow_mut() = state.guardian_log);
+    PAUSES.with(|pauses| *pauses.borrow_mut() = state.pauses);
+    GUARDIAN_REQUEST_COUNTER.with(|guardian_request_counter| *guardian_request_counter.borrow_mut() = state.guardian_request_counter);
+    PROPOSAL_COUNTER.with(|proposal_counter| *proposal_counter.borrow_mut() = state.proposal_counter);
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
 pub struct GovernanceConfig {
     pub minimum_proposal_threshold: u64,
     pub minimum_voting_threshold: u64,
-    pub voting_period_days: u64,
+    pub voting_period_days: u64, // shortest voting period a proposal can ask for
     pub execution_delay_days: u64,
     pub proposal_fee: u64,
+    pub voting_mechanisms: Vec<MechanismSetting>, // topics not listed use linear voting
+    pub voting_rules: Vec<VotingRule>, // topics not listed need a simple majority and the minimum threshold
+    pub required_co_sponsors: u32, // moderation appeals need none
+    pub discussion_period_days: u64,
 }
 
 impl Default for GovernanceConfig {
@@ -99,24 +485,53 @@ impl Default for GovernanceConfig {
             minimum_voting_threshold: 10000, // Minimum total votes for proposal to pass
             voting_period_days: 7,
             execution_delay_days: 2,
-            proposal_fee: 10, // Fee in tokens to create proposal (prevents spam)
+            proposal_fee: 10, // Fee in tokens to create proposal (prevents spam); waived unless it exceeds the ledger fee
+            required_co_sponsors: 2,
+            discussion_period_days: 2,
+            voting_mechanisms: vec![],
+            voting_rules: vec![
+                VotingRule { topic: ProposalTopic::CourseApproval, quorum_percent: 5, approval: ApprovalThreshold::SimpleMajority },
+                VotingRule { topic: ProposalTopic::Treasury, quorum_percent: 10, approval: ApprovalThreshold::TwoThirds },
+                VotingRule { topic: ProposalTopic::Tokenomics, quorum_percent: 15, approval: ApprovalThreshold::TwoThirds },
+                VotingRule { topic: ProposalTopic::GovernanceParameter, quorum_percent: 15, approval: ApprovalThreshold::TwoThirds },
+                VotingRule { topic: ProposalTopic::PlatformUpgrade, quorum_percent: 20, approval: ApprovalThreshold::ThreeQuarters },
+                VotingRule { topic: ProposalTopic::Emergency, quorum_percent: 10, approval: ApprovalThreshold::TwoThirds },
+            ],
         }
     }
 }
 
 // Helper functions
+#[cfg(not(test))]
+fn get_current_time() -> u64 {
+    ic_cdk::api::time()
+}
+
+// Unit tests run outside a canister, where there is no system clock
+#[cfg(test)]
 fn get_current_time() -> u64 {
-    time()
+    tests::now()
+}
+
+// Proposals are listed newest first
+fn proposal_page_key(proposal: &Proposal) -> String {
+    format!("{:020}:{:020}", u64::MAX - proposal.created_at, proposal.id)
 }
 
 fn days_to_nanoseconds(days: u64) -> u64 {
-    days * 24 * 60 * 60 * 1_000_000_000
+    days.saturating_mul(24 * 60 * 60 * 1_000_000_000)
 }
 
 fn vote_key(proposal_id: u64, user_id: &Principal) -> String {
     format!("{}_{}", proposal_id, user_id.to_text())
 }
 
+fn get_vote(proposal_id: u64, user_id: &Principal) -> Option<Vote> {
+    VOTES.with(|votes| {
+        votes.borrow().get(&proposal_id).and_then(|votes| votes.get(user_id)).cloned()
+    })
+}
+
 fn calculate_voting_power(user_id: &Principal) -> u64 {
     // In a real implementation, this would calculate voting power based on:
     // - Reputation score
@@ -129,73 +544,1566 @@ fn calculate_voting_power(user_id: &Principal) -> u64 {
     })
 }
 
-async fn is_user_admin(user_id: Principal) -> bool {
-    // This would be an actual inter-canister call to user_management
-    false
+// Set by dfx at build time when user_management is listed as a dependency
+fn user_management_canister() -> Result<Principal> {
+    option_env!("CANISTER_ID_USER_MANAGEMENT")
+        .and_then(|id| Principal::from_text(id).ok())
+        .ok_or_else(|| ApiError::InternalError("user_management canister is not configured".to_string()))
+}
+
+// Inter-canister call to reject suspended or banned users. This must run
+// before any state is read so that nothing goes stale across the await.
+async fn ensure_account_active(user_id: Principal) -> Result<()> {
+    let (status,): (AccountStatus,) =
+        ic_cdk::call(user_management_canister()?, "get_account_status", (user_id,))
+            .await
+            .map_err(|(_, message)| ApiError::InternalError(message))?;
+    match status {
+        AccountStatus::Active => Ok(()),
+        status => Err(ApiError::AccountRestricted(status)),
+    }
+}
+
+// Walks one of user_management's paged account lists, so no single reply
+// grows with the number of users
+async fn for_each_account(method: &str, mut visit: impl FnMut(&[Principal])) -> Result<()> {
+    let canister_id = user_management_canister()?;
+    let mut cursor: Option<String> = None;
+    loop {
+        let (result,): (Result<Page<Principal>>,) = ic_cdk::call(canister_id, method, (cursor, Some(MAX_PAGE_SIZE)))
+            .await
+            .map_err(|(_, message)| ApiError::InternalError(message))?;
+        let page = result?;
+        visit(&page.items);
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => return Ok(()),
+        }
+    }
+}
+
+// Vote weight of the registered users who are not suspended or banned, the
+// voter base that quorums are measured against
+async fn active_weight(mechanism: &VotingMechanism) -> Result<u64> {
+    let mut total = 0u64;
+    for_each_account("get_active_users", |users| {
+        total = total.saturating_add(eligible_weight(mechanism, users));
+    })
+    .await?;
+    Ok(total)
+}
+
+// Delegators whose accounts are currently suspended or banned, whose power
+// is left out when voting closes. Like ensure_account_active, this must run
+// before any state is read.
+async fn restricted_delegators() -> Result<HashSet<Principal>> {
+    let delegators: HashSet<Principal> = DELEGATIONS.with(|delegations| {
+        delegations.borrow().values().map(|delegation| delegation.delegator).collect()
+    });
+    if delegators.is_empty() {
+        return Ok(HashSet::new());
+    }
+
+    let mut restricted = HashSet::new();
+    for_each_account("get_restricted_accounts", |accounts| {
+        restricted.extend(accounts.iter().filter(|account| delegators.contains(account)));
+    })
+    .await?;
+    Ok(restricted)
+}
+
+// Set by dfx at build time when course_management is listed as a dependency
+fn course_management_canister() -> Result<Principal> {
+    option_env!("CANISTER_ID_COURSE_MANAGEMENT")
+        .and_then(|id| Principal::from_text(id).ok())
+        .ok_or_else(|| ApiError::InternalError("course_management canister is not configured".to_string()))
+}
+
+async fn get_moderation_action(action_id: u64) -> Result<ModerationActionSummary> {
+    let (result,): (Result<ModerationActionSummary>,) =
+        ic_cdk::call(course_management_canister()?, "get_moderation_action", (action_id,))
+            .await
+            .map_err(|(_, message)| ApiError::InternalError(message))?;
+    result
+}
+
+// Appeals can only be raised by the user the action was taken against, and
+// only once at a time for a given action
+async fn validate_moderation_appeal(proposer_id: Principal, action_id: u64) -> Result<()> {
+    let action = get_moderation_action(action_id).await?;
+
+    if action.affected_user != Some(proposer_id) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    if action.overturned {
+        return Err(ApiError::InvalidInput("Moderation action has already been overturned".to_string()));
+    }
+
+    let already_appealed = PROPOSALS.with(|proposals| {
+        proposals.borrow().values().any(|proposal| {
+            matches!(
+                proposal.status,
+                ProposalStatus::Draft | ProposalStatus::Discussion | ProposalStatus::Active | ProposalStatus::Passed
+            )
+                && matches!(proposal.proposal_type, ProposalType::ModerationAppeal { action_id: id } if id == action_id)
+        })
+    });
+    if already_appealed {
+        return Err(ApiError::AlreadyExists("An appeal for this action is already open".to_string()));
+    }
+
+    Ok(())
+}
+
+// Voting Mechanism Functions
+
+const CONVICTION_HALF_LIFE_DAYS: f64 = 2.0;
+
+fn voting_mechanism(config: &GovernanceConfig, topic: ProposalTopic) -> VotingMechanism {
+    config.voting_mechanisms.iter()
+        .find(|setting| setting.topic == topic)
+        .map(|setting| setting.mechanism.clone())
+        .unwrap_or(VotingMechanism::Linear)
+}
+
+fn vote_weight(mechanism: &VotingMechanism, voting_power: u64) -> u64 {
+    match mechanism {
+        VotingMechanism::Linear | VotingMechanism::Conviction => voting_power,
+        VotingMechanism::Quadratic => voting_power.isqrt(),
+    }
+}
+
+// Thresholds are configured in voting power and converted to vote weight
+// when a proposal is created
+fn weight_threshold(mechanism: &VotingMechanism, threshold: u64) -> u64 {
+    vote_weight(mechanism, threshold)
+}
+
+// Conviction reaches half of a vote's weight after one half-life and keeps
+// approaching the full weight. There is no floor: a vote cast at `at` weighs
+// nothing, which stops last-minute swings, and changing a vote starts it over
+fn weight_at(mechanism: &VotingMechanism, weight: u64, cast_at: u64, at: u64) -> u64 {
+    if *mechanism != VotingMechanism::Conviction {
+        return weight;
+    }
+    let held_days = at.saturating_sub(cast_at) as f64 / days_to_nanoseconds(1) as f64;
+    (weight as f64 * (1.0 - 0.5f64.powf(held_days / CONVICTION_HALF_LIFE_DAYS))) as u64
+}
+
+fn voting_rule(config: &GovernanceConfig, topic: ProposalTopic) -> VotingRule {
+    config.voting_rules.iter()
+        .find(|rule| rule.topic == topic)
+        .cloned()
+        .unwrap_or(VotingRule { topic, quorum_percent: 0, approval: ApprovalThreshold::SimpleMajority })
+}
+
+// Total voting power of the eligible voters, as vote weight under the mechanism
+fn eligible_weight(mechanism: &VotingMechanism, voters: &[Principal]) -> u64 {
+    voters.iter().map(|voter| vote_weight(mechanism, calculate_voting_power(voter))).sum()
+}
+
+fn quorum_for(config: &GovernanceConfig, mechanism: &VotingMechanism, rule: &VotingRule, eligible: u64) -> u64 {
+    let share = (eligible as u128 * rule.quorum_percent as u128 / 100) as u64;
+    share.max(weight_threshold(mechanism, config.minimum_voting_threshold))
+}
+
+fn meets_approval(approval: &ApprovalThreshold, votes_for: u64, votes_against: u64) -> bool {
+    let (votes_for, decided) = (votes_for as u128, votes_for as u128 + votes_against as u128);
+    match approval {
+        ApprovalThreshold::SimpleMajority => votes_for * 2 > decided,
+        ApprovalThreshold::TwoThirds => votes_for > 0 && votes_for * 3 >= decided * 2,
+        ApprovalThreshold::ThreeQuarters => votes_for > 0 && votes_for * 4 >= decided * 3,
+    }
+}
+
+// Proposers choose how long voting runs, but never for less than the
+// configured voting period
+fn validate_voting_duration(config: &GovernanceConfig, voting_duration_days: u64) -> Result<()> {
+    check_bounds("voting_duration_days", voting_duration_days, VOTING_PERIOD_DAYS)?;
+    if voting_duration_days < config.voting_period_days {
+        return Err(ApiError::InvalidInput(format!(
+            "Voting must run for at least {} days",
+            config.voting_period_days
+        )));
+    }
+    Ok(())
+}
+
+fn validate_voting_rules(rules: &[VotingRule]) -> Result<()> {
+    for (index, rule) in rules.iter().enumerate() {
+        check_bounds("quorum_percent", rule.quorum_percent as u64, QUORUM_PERCENT)?;
+        if rules[..index].iter().any(|other| other.topic == rule.topic) {
+            return Err(ApiError::InvalidInput(format!("Duplicate voting rule for {:?}", rule.topic)));
+        }
+    }
+    Ok(())
+}
+
+// Delegation Functions
+
+fn proposal_topic(proposal_type: &ProposalType) -> ProposalTopic {
+    match proposal_type {
+        ProposalType::CourseApproval { .. } => ProposalTopic::CourseApproval,
+        ProposalType::InstructorVerification { .. } => ProposalTopic::InstructorVerification,
+        ProposalType::PlatformUpgrade { .. } => ProposalTopic::PlatformUpgrade,
+        ProposalType::TokenomicsChange { .. } => ProposalTopic::Tokenomics,
+        ProposalType::GovernanceParameter { .. } => ProposalTopic::GovernanceParameter,
+        ProposalType::ModerationAppeal { .. } => ProposalTopic::ModerationAppeal,
+        ProposalType::TreasurySpend { .. } | ProposalType::ScholarshipGrant { .. } => ProposalTopic::Treasury,
+        ProposalType::ExtendPause { .. } => ProposalTopic::Emergency,
+    }
+}
+
+fn delegation_key(delegator: &Principal, topic: Option<ProposalTopic>) -> String {
+    match topic {
+        Some(topic) => format!("{}_{:?}", delegator.to_text(), topic),
+        None => format!("{}_all", delegator.to_text()),
+    }
+}
+
+// A topic delegation takes precedence over the delegator's global one
+fn delegate_of(delegator: &Principal, topic: ProposalTopic) -> Option<Principal> {
+    DELEGATIONS.with(|delegations| {
+        let delegations = delegations.borrow();
+        delegations.get(&delegation_key(delegator, Some(topic)))
+            .or_else(|| delegations.get(&delegation_key(delegator, None)))
+            .map(|delegation| delegation.delegate)
+    })
+}
+
+// Whether following delegations from `start` on a topic leads back to `target`
+fn delegation_reaches(start: Principal, target: &Principal, topic: ProposalTopic) -> bool {
+    let mut visited = HashSet::new();
+    let mut current = Some(start);
+    while let Some(principal) = current {
+        if principal == *target {
+            return true;
+        }
+        if !visited.insert(principal) {
+            return false;
+        }
+        current = delegate_of(&principal, topic);
+    }
+    false
+}
+
+// Follows the delegator's chain to the first principal who voted directly.
+// Power is not counted if the chain ends without one or runs into a cycle.
+fn resolve_delegated_vote<'a>(
+    delegator: &Principal,
+    topic: ProposalTopic,
+    direct_votes: &'a HashMap<Principal, Vote>,
+) -> Option<&'a Vote> {
+    let mut visited = HashSet::from([*delegator]);
+    let mut current = delegate_of(delegator, topic);
+    while let Some(principal) = current {
+        if let Some(vote) = direct_votes.get(&principal) {
+            return Some(vote);
+        }
+        if !visited.insert(principal) {
+            return None;
+        }
+        current = delegate_of(&principal, topic);
+    }
+    None
+}
+
+// Vote weights as of `at`. Delegated power is worked out from the current
+// delegations, so it can change until voting closes, and follows the
+// conviction of the vote it joins. A direct vote always overrides the
+// voter's own delegation, and restricted delegators are not counted.
+fn tally_proposal(proposal: &Proposal, at: u64, restricted: &HashSet<Principal>) -> ProposalTally {
+    let topic = proposal_topic(&proposal.proposal_type);
+    let direct_votes: HashMap<Principal, Vote> = VOTES.with(|votes| {
+        votes.borrow().get(&proposal.id).cloned().unwrap_or_default()
+    });
+    let delegators: HashSet<Principal> = DELEGATIONS.with(|delegations| {
+        delegations.borrow().values().map(|delegation| delegation.delegator).collect()
+    });
+
+    let mut tally = ProposalTally {
+        direct_for: 0,
+        direct_against: 0,
+        direct_abstain: 0,
+        delegated_for: 0,
+        delegated_against: 0,
+        delegated_abstain: 0,
+    };
+    for vote in direct_votes.values() {
+        let weight = weight_at(&proposal.mechanism, vote.weight, vote.timestamp, at);
+        match vote.vote_type {
+            VoteType::For => tally.direct_for += weight,
+            VoteType::Against => tally.direct_against += weight,
+            VoteType::Abstain => tally.direct_abstain += weight,
+        }
+    }
+    let counted = delegators.iter()
+        .filter(|delegator| !direct_votes.contains_key(delegator) && !restricted.contains(delegator));
+    for delegator in counted {
+        let vote = match resolve_delegated_vote(delegator, topic, &direct_votes) {
+            Some(vote) => vote,
+            None => continue,
+        };
+        let weight = vote_weight(&proposal.mechanism, calculate_voting_power(delegator));
+        let weight = weight_at(&proposal.mechanism, weight, vote.timestamp, at);
+        match vote.vote_type {
+            VoteType::For => tally.delegated_for += weight,
+            VoteType::Against => tally.delegated_against += weight,
+            VoteType::Abstain => tally.delegated_abstain += weight,
+        }
+    }
+    tally
+}
+
+// Delegates the caller's voting power on a topic, or on every topic when none
+// is given. Replaces any existing delegation for the same scope.
+#[update]
+#[candid_method(update)]
+async fn delegate_vote(delegate: Principal, topic: Option<ProposalTopic>) -> Result<Delegation> {
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    if delegate == caller_id {
+        return Err(ApiError::InvalidInput("Cannot delegate to yourself".to_string()));
+    }
+    if delegate == Principal::anonymous() {
+        return Err(ApiError::InvalidInput("Cannot delegate to the anonymous principal".to_string()));
+    }
+
+    let topics = match topic {
+        Some(topic) => vec![topic],
+        None => ALL_TOPICS.to_vec(),
+    };
+    if topics.into_iter().any(|topic| delegation_reaches(delegate, &caller_id, topic)) {
+        return Err(ApiError::InvalidInput("Delegation would create a cycle".to_string()));
+    }
+
+    let delegation = Delegation {
+        delegator: caller_id,
+        delegate,
+        topic,
+        created_at: get_current_time(),
+    };
+    DELEGATIONS.with(|delegations| {
+        delegations.borrow_mut().insert(delegation_key(&caller_id, topic), delegation.clone())
+    });
+    Ok(delegation)
+}
+
+#[update]
+#[candid_method(update)]
+fn revoke_delegation(topic: Option<ProposalTopic>) -> Result<bool> {
+    let caller_id = caller();
+    DELEGATIONS.with(|delegations| delegations.borrow_mut().remove(&delegation_key(&caller_id, topic)))
+        .map(|_| true)
+        .ok_or_else(|| ApiError::NotFound("Delegation not found".to_string()))
+}
+
+#[query]
+#[candid_method(query)]
+fn get_delegations(delegator: Principal) -> Vec<Delegation> {
+    DELEGATIONS.with(|delegations| {
+        delegations.borrow().values().filter(|delegation| delegation.delegator == delegator).cloned().collect()
+    })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_delegators(delegate: Principal, cursor: Option<String>, limit: Option<u32>) -> Result<Page<Delegation>> {
+    DELEGATIONS.with(|delegations| {
+        paginate(
+            delegations.borrow().values().filter(|delegation| delegation.delegate == delegate),
+            |delegation| delegation_key(&delegation.delegator, delegation.topic),
+            cursor,
+            limit,
+        )
+    })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_proposal_tally(proposal_id: u64) -> Result<ProposalTally> {
+    let proposal = get_proposal(proposal_id)?;
+    if proposal.status == ProposalStatus::Active {
+        // A preview; restricted delegators are only left out once voting closes
+        let at = get_current_time().min(proposal.voting_deadline);
+        return Ok(tally_proposal(&proposal, at, &HashSet::new()));
+    }
+
+    Ok(ProposalTally {
+        direct_for: proposal.votes_for,
+        direct_against: proposal.votes_against,
+        direct_abstain: proposal.votes_abstain,
+        delegated_for: proposal.delegated_for,
+        delegated_against: proposal.delegated_against,
+        delegated_abstain: proposal.delegated_abstain,
+    })
+}
+
+// Ledger Functions
+
+// Treasury funds live in a dedicated subaccount of this canister; escrowed
+// proposal fees are held in the default account
+const TREASURY_SUBACCOUNT: [u8; 32] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+];
+
+fn ledger_canister() -> Option<Principal> {
+    LEDGER_CANISTER.with(|ledger| *ledger.borrow())
+}
+
+fn treasury_account() -> Account {
+    Account { owner: ic_cdk::id(), subaccount: Some(TREASURY_SUBACCOUNT.to_vec()) }
+}
+
+// Transfers made for a proposal carry its id so they can be traced on the ledger
+fn proposal_memo(proposal_id: u64) -> Vec<u8> {
+    memo_for(&format!("proposal_{}", proposal_id))
+}
+
+async fn ledger_fee(ledger: Principal) -> Result<u64> {
+    let (fee,): (Nat,) = ic_cdk::call(ledger, "icrc1_fee", ())
+        .await
+        .map_err(|(_, message)| ApiError::PaymentFailed(message))?;
+    u64::try_from(fee.0)
+        .map_err(|_| ApiError::PaymentFailed("Ledger fee is out of range".to_string()))
+}
+
+// Appeals are never charged, and a fee that does not cover the ledger fee
+// could never be refunded or forfeited, so it is not charged either
+fn is_fee_waived(proposal_type: &ProposalType, amount: u64, ledger_fee: u64) -> bool {
+    amount <= ledger_fee || matches!(proposal_type, ProposalType::ModerationAppeal { .. })
+}
+
+// Escrows the proposal fee with ICRC-2 transfer_from; the proposer must have
+// approved this canister beforehand. Until a ledger is configured the fee
+// cannot be collected and is waived.
+async fn collect_proposal_fee(proposer_id: Principal, proposal_type: &ProposalType, amount: u64) -> Result<ProposalFee> {
+    let waived = ProposalFee { amount: 0, collected_in: None, status: ProposalFeeStatus::Waived };
+    let ledger = match ledger_canister() {
+        Some(ledger) => ledger,
+        None => return Ok(waived),
+    };
+    if is_fee_waived(proposal_type, amount, ledger_fee(ledger).await?) {
+        return Ok(waived);
+    }
+
+    let args = TransferFromArgs {
+        spender_subaccount: None,
+        from: Account { owner: proposer_id, subaccount: None },
+        to: Account { owner: ic_cdk::id(), subaccount: None },
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+        created_at_time: Some(get_current_time()),
+    };
+    let result: std::result::Result<(std::result::Result<Nat, TransferFromError>,), _> =
+        ic_cdk::call(ledger, "icrc2_transfer_from", (args,)).await;
+    match result {
+        Ok((Ok(block_index),)) => Ok(ProposalFee {
+            amount,
+            collected_in: Some(block_index),
+            status: ProposalFeeStatus::Escrowed,
+        }),
+        Ok((Err(error),)) => Err(ApiError::PaymentFailed(describe_transfer_from_error(error))),
+        Err((_, message)) => Err(ApiError::PaymentFailed(message)),
+    }
+}
+
+// Sends funds from one of this canister's accounts, less the ledger fee.
+// Returns the block index and the amount that arrived.
+async fn transfer(from_subaccount: Option<Vec<u8>>, to: Account, amount: u64, memo: Vec<u8>) -> Result<(Nat, u64)> {
+    let ledger = ledger_canister()
+        .ok_or_else(|| ApiError::InternalError("Ledger is not configured".to_string()))?;
+
+    let fee = ledger_fee(ledger).await?;
+    if amount <= fee {
+        return Err(ApiError::InvalidInput("Amount does not cover the ledger fee".to_string()));
+    }
+
+    let args = TransferArg {
+        from_subaccount,
+        to,
+        amount: Nat::from(amount - fee),
+        fee: Some(Nat::from(fee)),
+        memo: Some(memo),
+        created_at_time: Some(get_current_time()),
+    };
+    let result: std::result::Result<(std::result::Result<Nat, TransferError>,), _> =
+        ic_cdk::call(ledger, "icrc1_transfer", (args,)).await;
+    match result {
+        Ok((Ok(block_index),)) => Ok((block_index, amount - fee)),
+        Ok((Err(error),)) => Err(ApiError::PaymentFailed(describe_transfer_error(error))),
+        Err((_, message)) => Err(ApiError::PaymentFailed(message)),
+    }
+}
+
+fn load_proposal(proposal_id: u64) -> Result<Proposal> {
+    PROPOSALS.with(|proposals| {
+        proposals.borrow().get(&proposal_id).cloned()
+            .ok_or_else(|| ApiError::NotFound("Proposal not found".to_string()))
+    })
+}
+
+fn save_proposal(proposal: &Proposal) {
+    PROPOSALS.with(|proposals| proposals.borrow_mut().insert(proposal.id, proposal.clone()));
+}
+
+fn total_votes(proposal: &Proposal) -> u64 {
+    proposal.votes_for + proposal.votes_against + proposal.votes_abstain
+        + proposal.delegated_for + proposal.delegated_against + proposal.delegated_abstain
+}
+
+// Quorum counts every vote cast, including abstentions and delegated power
+fn reached_quorum(proposal: &Proposal) -> bool {
+    total_votes(proposal) >= proposal.quorum
+}
+
+// Spam when voters holding more than half of the voting power cast flagged it
+fn is_spam(proposal: &Proposal) -> bool {
+    let total = total_votes(proposal);
+    total > 0 && proposal.spam_flags * 2 > total
+}
+
+// Weight of the Against votes flagged as spam as of `at`. Flags decay with
+// the votes they were raised with, so they compare against the same totals.
+fn flagged_weight(proposal: &Proposal, at: u64) -> u64 {
+    let votes = VOTES.with(|votes| votes.borrow().get(&proposal.id).cloned().unwrap_or_default());
+    SPAM_FLAGS.with(|flags| {
+        let flags = flags.borrow();
+        votes.values()
+            .filter(|vote| flags.contains(&vote_key(proposal.id, &vote.voter_id)))
+            .map(|vote| weight_at(&proposal.mechanism, vote.weight, vote.timestamp, at))
+            .sum()
+    })
+}
+
+// Closes voting as of `at`, recording final weights under the proposal's
+// mechanism and adding the power of everyone who delegated to a direct voter
+// and is not restricted
+fn close_voting(proposal: &mut Proposal, at: u64, restricted: &HashSet<Principal>) {
+    let tally = tally_proposal(proposal, at, restricted);
+    proposal.spam_flags = flagged_weight(proposal, at);
+    proposal.votes_for = tally.direct_for;
+    proposal.votes_against = tally.direct_against;
+    proposal.votes_abstain = tally.direct_abstain;
+    proposal.delegated_for = tally.delegated_for;
+    proposal.delegated_against = tally.delegated_against;
+    proposal.delegated_abstain = tally.delegated_abstain;
+
+    let votes_for = proposal.votes_for + proposal.delegated_for;
+    let votes_against = proposal.votes_against + proposal.delegated_against;
+    proposal.status = if reached_quorum(proposal)
+        && meets_approval(&proposal.approval, votes_for, votes_against)
+        && !is_spam(proposal)
+    {
+        ProposalStatus::Passed
+    } else {
+        ProposalStatus::Rejected
+    };
+}
+
+// Returns an escrowed fee to the proposer if the proposal reached quorum and
+// was not flagged as spam, or was cancelled before any votes, and otherwise
+// sends it to the treasury. The fee is marked as settling across the ledger
+// call so it can only be paid out once; if the transfer fails it goes back to
+// escrow and can be retried.
+async fn settle_proposal_fee(proposal_id: u64) -> Result<Proposal> {
+    let mut proposal = load_proposal(proposal_id)?;
+    let open = matches!(
+        proposal.status,
+        ProposalStatus::Draft | ProposalStatus::Discussion | ProposalStatus::Active
+    );
+    if open || proposal.fee.status != ProposalFeeStatus::Escrowed {
+        return Ok(proposal);
+    }
+
+    let refund = proposal.status == ProposalStatus::Cancelled
+        || (reached_quorum(&proposal) && !is_spam(&proposal));
+    proposal.fee.status = ProposalFeeStatus::Settling;
+    save_proposal(&proposal);
+
+    let to = if refund {
+        Account { owner: proposal.proposer_id, subaccount: None }
+    } else {
+        treasury_account()
+    };
+    let result = transfer(None, to, proposal.fee.amount, proposal_memo(proposal_id)).await;
+
+    let mut proposal = load_proposal(proposal_id)?;
+    proposal.fee.status = match result {
+        Ok((block_index, _)) if refund => ProposalFeeStatus::Refunded { block_index },
+        Ok((block_index, received)) => {
+            record_treasury_movement(
+                TreasuryMovementKind::ForfeitedFee { proposal_id },
+                MovementDirection::Inflow,
+                received,
+                Account { owner: proposal.proposer_id, subaccount: None },
+                block_index.clone(),
+            );
+            ProposalFeeStatus::Forfeited { block_index }
+        }
+        Err(error) => {
+            proposal.fee.status = ProposalFeeStatus::Escrowed;
+            save_proposal(&proposal);
+            return Err(error);
+        }
+    };
+    save_proposal(&proposal);
+    Ok(proposal)
+}
+
+// Treasury Functions
+
+fn record_treasury_movement(
+    kind: TreasuryMovementKind,
+    direction: MovementDirection,
+    amount: u64,
+    counterparty: Account,
+    block_index: Nat,
+) {
+    TREASURY_MOVEMENTS.with(|movements| {
+        let mut movements = movements.borrow_mut();
+        let movement = TreasuryMovement {
+            id: movements.len() as u64 + 1,
+            kind,
+            direction,
+            amount,
+            counterparty,
+            block_index,
+            timestamp: get_current_time(),
+        };
+        movements.push(movement);
+    });
+}
+
+// Checks treasury proposals when they are created so that a proposal which
+// passes can actually be executed
+async fn validate_treasury_proposal(proposal_type: &ProposalType) -> Result<()> {
+    match proposal_type {
+        ProposalType::TreasurySpend { amount, memo, .. } => {
+            if *amount == 0 {
+                return Err(ApiError::InvalidInput("Amount must be greater than zero".to_string()));
+            }
+            if memo.as_ref().map(|memo| memo.len() > MAX_MEMO_BYTES).unwrap_or(false) {
+                return Err(ApiError::InvalidInput(format!("Memo cannot exceed {} bytes", MAX_MEMO_BYTES)));
+            }
+            Ok(())
+        }
+        ProposalType::ScholarshipGrant { course_id, amount, .. } => {
+            if *amount == 0 {
+                return Err(ApiError::InvalidInput("Amount must be greater than zero".to_string()));
+            }
+            let (result,): (Result<candid::Reserved>,) =
+                ic_cdk::call(course_management_canister()?, "get_course", (course_id.clone(),))
+                    .await
+                    .map_err(|(_, message)| ApiError::InternalError(message))?;
+            result.map(|_| ())
+        }
+        _ => Ok(()),
+    }
+}
+
+// Pays out of the treasury subaccount. The recipient receives the full
+// amount; the ledger fee is charged to the treasury on top. Without a memo
+// from the proposal, the transfer is tagged with the proposal id.
+async fn treasury_transfer(proposal_id: u64, to: Account, amount: u64, memo: Option<String>) -> Result<Nat> {
+    let ledger = ledger_canister()
+        .ok_or_else(|| ApiError::InternalError("Ledger is not configured".to_string()))?;
+
+    let args = TransferArg {
+        from_subaccount: Some(TREASURY_SUBACCOUNT.to_vec()),
+        to,
+        amount: Nat::from(amount),
+        fee: None,
+        memo: Some(memo.map(String::into_bytes).unwrap_or_else(|| proposal_memo(proposal_id))),
+        created_at_time: Some(get_current_time()),
+    };
+    let result: std::result::Result<(std::result::Result<Nat, TransferError>,), _> =
+        ic_cdk::call(ledger, "icrc1_transfer", (args,)).await;
+    match result {
+        Ok((Ok(block_index),)) => Ok(block_index),
+        Ok((Err(error),)) => Err(ApiError::PaymentFailed(describe_transfer_error(error))),
+        Err((_, message)) => Err(ApiError::PaymentFailed(message)),
+    }
+}
+
+// Keeps the reason a proposal could not be executed so it can be shown and
+// the execution retried once the cause is fixed
+fn record_execution_error(proposal_id: u64, error: ApiError) {
+    let message = match error {
+        ApiError::PaymentFailed(message) | ApiError::InternalError(message) => message,
+        error => format!("{:?}", error),
+    };
+    PROPOSALS.with(|proposals| {
+        if let Some(proposal) = proposals.borrow_mut().get_mut(&proposal_id) {
+            proposal.execution_error = Some(message);
+        }
+    });
+}
+
+async fn execute_treasury_spend(proposal_id: u64, recipient: Account, amount: u64, memo: Option<String>) -> bool {
+    match treasury_transfer(proposal_id, recipient.clone(), amount, memo.clone()).await {
+        Ok(block_index) => {
+            let kind = TreasuryMovementKind::Spend { proposal_id, memo };
+            record_treasury_movement(kind, MovementDirection::Outflow, amount, recipient, block_index);
+            true
+        }
+        Err(error) => {
+            record_execution_error(proposal_id, error);
+            false
+        }
+    }
+}
+
+async fn execute_scholarship_grant(proposal_id: u64, recipient: Principal, course_id: String, amount: u64) -> bool {
+    let recipient = Account { owner: recipient, subaccount: None };
+    match treasury_transfer(proposal_id, recipient.clone(), amount, None).await {
+        Ok(block_index) => {
+            let kind = TreasuryMovementKind::ScholarshipGrant { proposal_id, course_id };
+            record_treasury_movement(kind, MovementDirection::Outflow, amount, recipient, block_index);
+            true
+        }
+        Err(error) => {
+            record_execution_error(proposal_id, error);
+            false
+        }
+    }
+}
+
+// Upgrade Functions
+
+// Chunks are stored by the management canister, which takes at most 1 MiB
+// per chunk and installs modules of up to 100 MiB from them
+const MAX_CHUNK_BYTES: usize = 1024 * 1024;
+const MAX_WASM_BYTES: u64 = 100 * 1024 * 1024;
+
+// Canisters that governance controls and can upgrade, set by dfx at build time
+fn upgradable_canisters() -> Vec<Principal> {
+    [
+        option_env!("CANISTER_ID_USER_MANAGEMENT"),
+        option_env!("CANISTER_ID_COURSE_MANAGEMENT"),
+        option_env!("CANISTER_ID_CERTIFICATION_SYSTEM"),
+    ]
+    .into_iter()
+    .flatten()
+    .filter_map(|id| Principal::from_text(id).ok())
+    .collect()
+}
+
+fn validate_upgrade_proposal(proposal_type: &ProposalType) -> Result<()> {
+    if let ProposalType::PlatformUpgrade { canister_id, wasm_hash, .. } = proposal_type {
+        if !upgradable_canisters().contains(canister_id) {
+            return Err(ApiError::InvalidInput("Canister is not managed by governance".to_string()));
+        }
+        if wasm_hash.len() != 32 {
+            return Err(ApiError::InvalidInput("Wasm hash must be a 32-byte SHA-256 digest".to_string()));
+        }
+    }
+    Ok(())
+}
+
+// An upload only counts towards the canister the proposal currently targets
+fn wasm_upload(proposal_id: u64, canister_id: Principal) -> Option<WasmUpload> {
+    WASM_UPLOADS.with(|uploads| uploads.borrow().get(&proposal_id).cloned())
+        .filter(|upload| upload.canister_id == canister_id)
+}
+
+fn wasm_upload_status(proposal_id: u64, canister_id: Principal, expected_hash: Vec<u8>) -> WasmUploadStatus {
+    let upload = wasm_upload(proposal_id, canister_id);
+    WasmUploadStatus {
+        proposal_id,
+        uploaded_bytes: upload.as_ref().map(|upload| upload.uploaded_bytes).unwrap_or(0),
+        chunk_count: upload.map(|upload| upload.chunk_hashes.len() as u64).unwrap_or(0),
+        expected_hash,
+    }
+}
+
+fn upgrade_target(proposal: &Proposal) -> Result<(Principal, Vec<u8>)> {
+    match proposal.proposal_type {
+        ProposalType::PlatformUpgrade { canister_id, ref wasm_hash, .. } => Ok((canister_id, wasm_hash.clone())),
+        _ => Err(ApiError::InvalidInput("Not a platform upgrade proposal".to_string())),
+    }
+}
+
+// An upload can no longer change once its proposal starts executing
+fn ensure_upload_open(proposal_id: u64) -> Result<()> {
+    let executing = EXECUTING_PROPOSALS.with(|executing| executing.borrow().contains(&proposal_id));
+    let installed = WASM_UPLOADS.with(|uploads| {
+        uploads.borrow().get(&proposal_id).is_some_and(|upload| upload.installed)
+    });
+    if executing || installed {
+        return Err(ApiError::InvalidInput("The wasm is being or has been installed".to_string()));
+    }
+    Ok(())
+}
+
+// Stores the next chunk of the proposal's wasm in the target canister's chunk
+// store. The upload can continue after the proposal passes. Chunks are
+// uploaded one at a time so that they are recorded in order.
+#[update]
+#[candid_method(update)]
+async fn upload_wasm_chunk(proposal_id: u64, chunk: Vec<u8>) -> Result<WasmUploadStatus> {
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let proposal = load_proposal(proposal_id)?;
+    ensure_proposer(&proposal, caller_id)?;
+    let (canister_id, wasm_hash) = upgrade_target(&proposal)?;
+    if matches!(
+        proposal.status,
+        ProposalStatus::Rejected | ProposalStatus::Executed | ProposalStatus::Cancelled
+    ) {
+        return Err(ApiError::InvalidInput("Proposal is closed".to_string()));
+    }
+    ensure_upload_open(proposal_id)?;
+    if chunk.is_empty() || chunk.len() > MAX_CHUNK_BYTES {
+        return Err(ApiError::InvalidInput(format!("Chunks must be between 1 and {} bytes", MAX_CHUNK_BYTES)));
+    }
+    let status = wasm_upload_status(proposal_id, canister_id, wasm_hash);
+    if status.uploaded_bytes + chunk.len() as u64 > MAX_WASM_BYTES {
+        return Err(ApiError::InvalidInput(format!("Wasm cannot exceed {} bytes", MAX_WASM_BYTES)));
+    }
+    if !UPLOADS_IN_FLIGHT.with(|uploads| uploads.borrow_mut().insert(proposal_id)) {
+        return Err(ApiError::InvalidInput("Another chunk is still being uploaded".to_string()));
+    }
+
+    let chunk_bytes = chunk.len() as u64;
+    let result = upload_chunk(UploadChunkArgument { canister_id, chunk }).await;
+    UPLOADS_IN_FLIGHT.with(|uploads| uploads.borrow_mut().remove(&proposal_id));
+    let (chunk_hash,) = result.map_err(|(_, message)| ApiError::InternalError(message))?;
+
+    // Re-read after the management canister call; if the upload was cleared
+    // in the meantime this chunk no longer belongs at the end of it
+    let mut upload = wasm_upload(proposal_id, canister_id).unwrap_or(WasmUpload {
+        canister_id,
+        chunk_hashes: Vec::new(),
+        uploaded_bytes: 0,
+        installed: false,
+    });
+    if upload.chunk_hashes.len() as u64 != status.chunk_count {
+        return Err(ApiError::InvalidInput("The upload was cleared while the chunk was stored".to_string()));
+    }
+    upload.chunk_hashes.push(chunk_hash);
+    upload.uploaded_bytes += chunk_bytes;
+    WASM_UPLOADS.with(|uploads| uploads.borrow_mut().insert(proposal_id, upload));
+    Ok(wasm_upload_status(proposal_id, canister_id, status.expected_hash))
+}
+
+// Discards an upload so it can be restarted, for example after an
+// amendment changed the hash
+#[update]
+#[candid_method(update)]
+fn clear_wasm_upload(proposal_id: u64) -> Result<bool> {
+    let proposal = load_proposal(proposal_id)?;
+    ensure_proposer(&proposal, caller())?;
+    ensure_upload_open(proposal_id)?;
+    Ok(WASM_UPLOADS.with(|uploads| uploads.borrow_mut().remove(&proposal_id).is_some()))
+}
+
+#[query]
+#[candid_method(query)]
+fn get_wasm_upload(proposal_id: u64) -> Result<WasmUploadStatus> {
+    let proposal = load_proposal(proposal_id)?;
+    let (canister_id, wasm_hash) = upgrade_target(&proposal)?;
+    Ok(wasm_upload_status(proposal_id, canister_id, wasm_hash))
+}
+
+// Stops the canister, installs the uploaded chunks in upgrade mode and starts
+// it again whether or not the install succeeded. The management canister
+// rejects the install unless the chunks hash to the approved wasm hash. An
+// install that succeeded is not repeated, so if the canister then fails to
+// start, executing the proposal again only starts it.
+async fn execute_platform_upgrade(proposal_id: u64, canister_id: Principal, wasm_hash: Vec<u8>, arg: Vec<u8>) -> bool {
+    let Some(mut upload) = wasm_upload(proposal_id, canister_id) else {
+        record_execution_error(proposal_id, ApiError::InvalidInput("No wasm has been uploaded".to_string()));
+        return false;
+    };
+
+    if !upload.installed {
+        if let Err((_, message)) = stop_canister(CanisterIdRecord { canister_id }).await {
+            record_execution_error(proposal_id, ApiError::InternalError(message));
+            return false;
+        }
+        let installed = install_chunked_code(InstallChunkedCodeArgument {
+            mode: CanisterInstallMode::Upgrade(None),
+            target_canister: canister_id,
+            store_canister: None,
+            chunk_hashes_list: upload.chunk_hashes.clone(),
+            wasm_module_hash: wasm_hash,
+            arg,
+        })
+        .await;
+        if let Err((_, message)) = installed {
+            record_execution_error(proposal_id, ApiError::InternalError(format!("Install failed: {}", message)));
+            let _ = start_canister(CanisterIdRecord { canister_id }).await;
+            return false;
+        }
+        upload.installed = true;
+        WASM_UPLOADS.with(|uploads| uploads.borrow_mut().insert(proposal_id, upload));
+    }
+
+    if let Err((_, message)) = start_canister(CanisterIdRecord { canister_id }).await {
+        let message = format!("Installed, but the canister did not start: {}", message);
+        record_execution_error(proposal_id, ApiError::InternalError(message));
+        return false;
+    }
+
+    // The chunk store is shared by every upload to the canister, so it is
+    // only cleared once no other upload needs it
+    let still_needed = WASM_UPLOADS.with(|uploads| {
+        let mut uploads = uploads.borrow_mut();
+        uploads.remove(&proposal_id);
+        uploads.values().any(|upload| upload.canister_id == canister_id)
+    });
+    if !still_needed {
+        let _ = clear_chunk_store(ClearChunkStoreArgument { canister_id }).await;
+    }
+    true
+}
+
+// Guardian Functions
+
+const MAX_GUARDIAN_PAUSE_HOURS: u64 = 72;
+const MAX_PAUSE_EXTENSION_HOURS: u64 = 720;
+const GUARDIAN_REQUEST_TTL_HOURS: u64 = 24;
+// Emergency proposals skip discussion and the execution delay and vote for a
+// day, so a pause can be extended before a guardian pause runs out
+const EMERGENCY_VOTING_HOURS: u64 = 24;
+
+fn hours_to_nanoseconds(hours: u64) -> u64 {
+    hours.saturating_mul(60 * 60 * 1_000_000_000)
+}
+
+fn pause_key(scope: &PauseScope) -> String {
+    match scope {
+        PauseScope::ProposalExecution => "execution".to_string(),
+        PauseScope::Canister { canister_id } => canister_id.to_text(),
+    }
+}
+
+fn get_pause(scope: &PauseScope) -> Option<Pause> {
+    PAUSES.with(|pauses| pauses.borrow().get(&pause_key(scope)).cloned())
+}
+
+// The end of the pause on a scope, if one is in effect
+fn paused_until(scope: &PauseScope) -> Option<u64> {
+    get_pause(scope)
+        .map(|pause| pause.paused_until)
+        .filter(|until| get_current_time() < *until)
+}
+
+fn validate_pause_scope(scope: &PauseScope) -> Result<()> {
+    if let PauseScope::Canister { canister_id } = scope {
+        if !upgradable_canisters().contains(canister_id) {
+            return Err(ApiError::InvalidInput("Canister is not managed by governance".to_string()));
+        }
+    }
+    Ok(())
+}
+
+// Extensions run on from the end of the pause in effect, or from now
+fn pause_extension_end(scope: &PauseScope, hours: u64) -> u64 {
+    paused_until(scope).unwrap_or_else(get_current_time) + hours_to_nanoseconds(hours)
+}
+
+fn validate_pause_extension(proposal_type: &ProposalType) -> Result<()> {
+    if let ProposalType::ExtendPause { scope, hours } = proposal_type {
+        validate_pause_scope(scope)?;
+        check_bounds("hours", *hours, 1..=MAX_PAUSE_EXTENSION_HOURS)?;
+    }
+    Ok(())
+}
+
+fn log_guardian_event(actor: Principal, event: GuardianEvent) {
+    GUARDIAN_LOG.with(|log| {
+        let mut log = log.borrow_mut();
+        let entry = GuardianLogEntry {
+            id: log.len() as u64 + 1,
+            actor,
+            event,
+            timestamp: get_current_time(),
+        };
+        log.push(entry);
+    });
+}
+
+fn ensure_guardian(principal: &Principal) -> Result<GuardianSet> {
+    let set = GUARDIANS.with(|guardians| guardians.borrow().clone());
+    if !set.guardians.contains(principal) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+    Ok(set)
+}
+
+// Checks that an action can be taken now. Guardians cannot lengthen a pause
+// that is in effect, and must wait out a cooldown after one ends.
+fn validate_guardian_action(action: &GuardianAction) -> Result<()> {
+    match action {
+        GuardianAction::Pause { scope, hours } => {
+            validate_pause_scope(scope)?;
+            check_bounds("hours", *hours, 1..=MAX_GUARDIAN_PAUSE_HOURS)?;
+            if let Some(pause) = get_pause(scope) {
+                let current_time = get_current_time();
+                if current_time < pause.paused_until {
+                    return Err(ApiError::InvalidInput("Already paused; extending it needs a proposal".to_string()));
+                }
+                if current_time < pause.guardian_cooldown_until {
+                    return Err(ApiError::InvalidInput("Guardian pause is cooling down".to_string()));
+                }
+            }
+            Ok(())
+        }
+        GuardianAction::Resume { scope } => {
+            if paused_until(scope).is_none() {
+                return Err(ApiError::InvalidInput("Not paused".to_string()));
+            }
+            Ok(())
+        }
+    }
+}
+
+// Freezes or unfreezes the target canister, which must have governance as a
+// controller, and records the pause
+async fn apply_pause(scope: &PauseScope, paused_until: u64) -> Result<()> {
+    if let PauseScope::Canister { canister_id } = scope {
+        let frozen_until = Some(paused_until).filter(|until| get_current_time() < *until);
+        let (result,): (Result<bool>,) = ic_cdk::call(*canister_id, "set_frozen_until", (frozen_until,))
+            .await
+            .map_err(|(_, message)| ApiError::InternalError(message))?;
+        result?;
+    }
+
+    PAUSES.with(|pauses| {
+        let mut pauses = pauses.borrow_mut();
+        let pause = pauses.entry(pause_key(scope)).or_insert(Pause {
+            scope: scope.clone(),
+            paused_until: 0,
+            guardian_cooldown_until: 0,
+        });
+        if paused_until > pause.paused_until {
+            pause.guardian_cooldown_until = pause.guardian_cooldown_until
+                .max(paused_until + hours_to_nanoseconds(MAX_GUARDIAN_PAUSE_HOURS));
+        }
+        pause.paused_until = paused_until;
+    });
+    Ok(())
+}
+
+// Applies a request once enough current guardians have approved it. The
+// request is marked applied across the call so it only takes effect once.
+async fn apply_guardian_request(request_id: u64) -> Result<GuardianRequest> {
+    let set = GUARDIANS.with(|guardians| guardians.borrow().clone());
+    let mut request = get_guardian_request(request_id)?;
+    let approvals = request.approvals.iter().filter(|approver| set.guardians.contains(approver)).count();
+    if request.applied_at.is_some() || set.threshold == 0 || approvals < set.threshold as usize {
+        return Ok(request);
+    }
+    validate_guardian_action(&request.action)?;
+
+    let current_time = get_current_time();
+    request.applied_at = Some(current_time);
+    GUARDIAN_REQUESTS.with(|requests| requests.borrow_mut().insert(request_id, request.clone()));
+
+    let result = match request.action {
+        GuardianAction::Pause { ref scope, hours } => {
+            apply_pause(scope, current_time + hours_to_nanoseconds(hours)).await
+        }
+        GuardianAction::Resume { ref scope } => apply_pause(scope, current_time).await,
+    };
+
+    let mut request = get_guardian_request(request_id)?;
+    match result {
+        Ok(()) => log_guardian_event(id(), GuardianEvent::Applied { request_id }),
+        Err(ref error) => {
+            request.applied_at = None;
+            log_guardian_event(id(), GuardianEvent::ApplyFailed { request_id, message: format!("{:?}", error) });
+        }
+    }
+    GUARDIAN_REQUESTS.with(|requests| requests.borrow_mut().insert(request_id, request.clone()));
+    result.map(|_| request)
+}
+
+fn get_guardian_request(request_id: u64) -> Result<GuardianRequest> {
+    GUARDIAN_REQUESTS.with(|requests| {
+        requests.borrow()
+            .get(&request_id)
+            .cloned()
+            .ok_or_else(|| ApiError::NotFound("Guardian request not found".to_string()))
+    })
+}
+
+// Replaces the guardian multisig. An empty set with a threshold of zero
+// disables guardian actions.
+#[update]
+#[candid_method(update)]
+fn set_guardians(guardians: Vec<Principal>, threshold: u32) -> Result<GuardianSet> {
+    if !ic_cdk::api::is_controller(&caller()) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    let mut unique: Vec<Principal> = Vec::new();
+    for guardian in guardians {
+        if !unique.contains(&guardian) {
+            unique.push(guardian);
+        }
+    }
+    let valid = if unique.is_empty() { threshold == 0 } else { threshold >= 1 && threshold as usize <= unique.len() };
+    if !valid {
+        return Err(ApiError::InvalidInput("Threshold must be between 1 and the number of guardians".to_string()));
+    }
+
+    let set = GuardianSet { guardians: unique, threshold };
+    GUARDIANS.with(|guardians| *guardians.borrow_mut() = set.clone());
+    log_guardian_event(caller(), GuardianEvent::GuardiansSet { guardians: set.guardians.clone(), threshold });
+    Ok(set)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_guardians() -> GuardianSet {
+    GUARDIANS.with(|guardians| guardians.borrow().clone())
+}
+
+// Proposes an emergency action, counting as the caller's approval
+#[update]
+#[candid_method(update)]
+async fn request_guardian_action(action: GuardianAction) -> Result<GuardianRequest> {
+    let caller_id = caller();
+    ensure_guardian(&caller_id)?;
+    validate_guardian_action(&action)?;
+
+    let request_id = GUARDIAN_REQUEST_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        *counter += 1;
+        *counter
+    });
+    let current_time = get_current_time();
+    let request = GuardianRequest {
+        id: request_id,
+        action: action.clone(),
+        requested_by: caller_id,
+        approvals: vec![caller_id],
+        created_at: current_time,
+        expires_at: current_time + hours_to_nanoseconds(GUARDIAN_REQUEST_TTL_HOURS),
+        applied_at: None,
+    };
+    GUARDIAN_REQUESTS.with(|requests| requests.borrow_mut().insert(request_id, request));
+    log_guardian_event(caller_id, GuardianEvent::Requested { request_id, action });
+
+    apply_guardian_request(request_id).await
+}
+
+// Adds the caller's approval and applies the action once the threshold is
+// met. Approving again retries an action whose freeze call failed.
+#[update]
+#[candid_method(update)]
+async fn approve_guardian_action(request_id: u64) -> Result<GuardianRequest> {
+    let caller_id = caller();
+    ensure_guardian(&caller_id)?;
+
+    let mut request = get_guardian_request(request_id)?;
+    if request.applied_at.is_some() {
+        return Err(ApiError::InvalidInput("Guardian request has already been applied".to_string()));
+    }
+    if get_current_time() > request.expires_at {
+        return Err(ApiError::InvalidInput("Guardian request has expired".to_string()));
+    }
+    if !request.approvals.contains(&caller_id) {
+        request.approvals.push(caller_id);
+        GUARDIAN_REQUESTS.with(|requests| requests.borrow_mut().insert(request_id, request));
+        log_guardian_event(caller_id, GuardianEvent::Approved { request_id });
+    }
+
+    apply_guardian_request(request_id).await
+}
+
+#[query]
+#[candid_method(query)]
+fn get_guardian_requests(cursor: Option<String>, limit: Option<u32>) -> Result<Page<GuardianRequest>> {
+    GUARDIAN_REQUESTS.with(|requests| {
+        paginate(
+            requests.borrow().values(),
+            |request| format!("{:020}", u64::MAX - request.id),
+            cursor,
+            limit,
+        )
+    })
+}
+
+// Pauses currently in effect
+#[query]
+#[candid_method(query)]
+fn get_active_pauses() -> Vec<Pause> {
+    let current_time = get_current_time();
+    PAUSES.with(|pauses| {
+        pauses.borrow().values().filter(|pause| current_time < pause.paused_until).cloned().collect()
+    })
+}
+
+// Every guardian action in the order it happened
+#[query]
+#[candid_method(query)]
+fn get_guardian_log(cursor: Option<String>, limit: Option<u32>) -> Result<Page<GuardianLogEntry>> {
+    GUARDIAN_LOG.with(|log| {
+        paginate(log.borrow().iter(), |entry| format!("{:020}", entry.id), cursor, limit)
+    })
+}
+
+// Extends a pause from its current end, or starts one if it has lapsed
+async fn execute_pause_extension(proposal_id: u64, scope: PauseScope, hours: u64) -> bool {
+    let until = pause_extension_end(&scope, hours);
+    if apply_pause(&scope, until).await.is_err() {
+        return false;
+    }
+    log_guardian_event(id(), GuardianEvent::ExtendedByProposal { proposal_id, scope, paused_until: until });
+    true
+}
+
+// Treated as not an admin if user_management cannot be reached
+async fn is_user_admin(user_id: Principal) -> bool {
+    let Ok(canister_id) = user_management_canister() else {
+        return false;
+    };
+    let result: std::result::Result<(bool,), _> = ic_cdk::call(canister_id, "is_admin", (user_id,)).await;
+    result.map(|(is_admin,)| is_admin).unwrap_or(false)
+}
+
+// Governance Functions
+
+#[update]
+#[candid_method(update)]
+async fn create_proposal(request: CreateProposalRequest) -> Result<Proposal> {
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+    
+    // Validate input
+    if request.title.trim().is_empty() {
+        return Err(ApiError::InvalidInput("Proposal title cannot be empty".to_string()));
+    }
+    
+    if request.description.trim().is_empty() {
+        return Err(ApiError::InvalidInput("Proposal description cannot be empty".to_string()));
+    }
+
+    // Check if user has enough voting power to create proposal. Moderation
+    // appeals are exempt so that any affected user can raise one.
+    let user_voting_power = calculate_voting_power(&caller_id);
+    let config = GOVERNANCE_CONFIG.with(|config| config.borrow().clone());
+
+    if let ProposalType::ModerationAppeal { action_id } = request.proposal_type {
+        validate_moderation_appeal(caller_id, action_id).await?;
+    } else if user_voting_power < config.minimum_proposal_threshold {
+        return Err(ApiError::InsufficientPermissions);
+    }
+    if !is_emergency(&request.proposal_type) {
+        validate_voting_duration(&config, request.voting_duration_days)?;
+    }
+    if let ProposalType::GovernanceParameter { ref parameter } = request.proposal_type {
+        validate_governance_parameter(parameter)?;
+    }
+    validate_upgrade_proposal(&request.proposal_type)?;
+    validate_pause_extension(&request.proposal_type)?;
+    validate_treasury_proposal(&request.proposal_type).await?;
+
+    let fee = collect_proposal_fee(caller_id, &request.proposal_type, config.proposal_fee).await?;
+    let topic = proposal_topic(&request.proposal_type);
+    let mechanism = voting_mechanism(&config, topic);
+    let rule = voting_rule(&config, topic);
+
+    let proposal_id = PROPOSAL_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        *counter += 1;
+        *counter
+    });
+
+    let current_time = get_current_time();
+    
+    // Voting times and the quorum are set once the draft is submitted for
+    // discussion
+    let proposal = Proposal {
+        id: proposal_id,
+        proposer_id: caller_id,
+        title: request.title,
+        description: request.description,
+        proposal_type: request.proposal_type,
+        status: ProposalStatus::Draft,
+        created_at: current_time,
+        co_sponsors: Vec::new(),
+        version: 1,
+        voting_duration_days: request.voting_duration_days,
+        voting_opens_at: None,
+        voting_deadline: 0,
+        votes_for: 0,
+        votes_against: 0,
+        votes_abstain: 0,
+        delegated_for: 0,
+        delegated_against: 0,
+        delegated_abstain: 0,
+        minimum_threshold: weight_threshold(&mechanism, config.minimum_voting_threshold),
+        quorum: 0,
+        approval: rule.approval,
+        executed_at: None,
+        execution_error: None,
+        fee,
+        spam_flags: 0,
+        mechanism,
+    };
+
+    PROPOSALS.with(|proposals| proposals.borrow_mut().insert(proposal_id, proposal.clone()));
+    Ok(proposal)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_proposal(proposal_id: u64) -> Result<Proposal> {
+    load_proposal(proposal_id).map(|proposal| current_view(proposal, get_current_time()))
+}
+
+// Proposal Lifecycle Functions
+
+fn ensure_proposer(proposal: &Proposal, caller_id: Principal) -> Result<()> {
+    if proposal.proposer_id != caller_id {
+        return Err(ApiError::InsufficientPermissions);
+    }
+    Ok(())
+}
+
+fn has_votes(proposal_id: u64) -> bool {
+    VOTES.with(|votes| votes.borrow().get(&proposal_id).is_some_and(|votes| !votes.is_empty()))
+}
+
+// Discussion moves on to voting once its period is over
+fn open_voting_if_due(proposal: &mut Proposal, current_time: u64) -> bool {
+    let due = proposal.status == ProposalStatus::Discussion
+        && proposal.voting_opens_at.map(|opens_at| current_time >= opens_at).unwrap_or(false);
+    if due {
+        proposal.status = ProposalStatus::Active;
+    }
+    due
+}
+
+// Voting only opens on the next update call, so queries report a proposal
+// whose discussion period is over as Active
+fn current_view(mut proposal: Proposal, current_time: u64) -> Proposal {
+    open_voting_if_due(&mut proposal, current_time);
+    proposal
+}
+
+fn current_proposals() -> Vec<Proposal> {
+    let current_time = get_current_time();
+    PROPOSALS.with(|proposals| {
+        proposals.borrow().values().map(|proposal| current_view(proposal.clone(), current_time)).collect()
+    })
+}
+
+fn is_emergency(proposal_type: &ProposalType) -> bool {
+    proposal_topic(proposal_type) == ProposalTopic::Emergency
+}
+
+// Starts the discussion period now, with voting to follow for the
+// proposal's duration. Emergency proposals go straight to a day of voting.
+// The quorum is measured against the voter base as it stands now, `eligible`
+// being its vote weight.
+fn schedule_discussion(proposal: &mut Proposal, config: &GovernanceConfig, current_time: u64, eligible: u64) {
+    let (discussion, voting) = if is_emergency(&proposal.proposal_type) {
+        (0, hours_to_nanoseconds(EMERGENCY_VOTING_HOURS))
+    } else {
+        (days_to_nanoseconds(config.discussion_period_days), days_to_nanoseconds(proposal.voting_duration_days))
+    };
+    let voting_opens_at = current_time + discussion;
+    proposal.voting_opens_at = Some(voting_opens_at);
+    proposal.voting_deadline = voting_opens_at + voting;
+    let rule = voting_rule(config, proposal_topic(&proposal.proposal_type));
+    proposal.quorum = quorum_for(config, &proposal.mechanism, &rule, eligible);
+}
+
+// Signs the caller on as a co-sponsor of a draft. Co-sponsors need the same
+// voting power as a proposer.
+#[update]
+#[candid_method(update)]
+async fn sponsor_proposal(proposal_id: u64) -> Result<Proposal> {
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let mut proposal = load_proposal(proposal_id)?;
+    if proposal.status != ProposalStatus::Draft {
+        return Err(ApiError::InvalidInput("Only draft proposals can be sponsored".to_string()));
+    }
+    if proposal.proposer_id == caller_id {
+        return Err(ApiError::InvalidInput("Proposers cannot sponsor their own proposal".to_string()));
+    }
+    if proposal.co_sponsors.contains(&caller_id) {
+        return Err(ApiError::AlreadyExists("Already a co-sponsor of this proposal".to_string()));
+    }
+    let config = GOVERNANCE_CONFIG.with(|config| config.borrow().clone());
+    if calculate_voting_power(&caller_id) < config.minimum_proposal_threshold {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    proposal.co_sponsors.push(caller_id);
+    save_proposal(&proposal);
+    Ok(proposal)
+}
+
+// Moves a draft with enough co-sponsors into its discussion period. Voting
+// opens when discussion ends and runs for the requested duration.
+#[update]
+#[candid_method(update)]
+async fn submit_proposal(proposal_id: u64) -> Result<Proposal> {
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+    let eligible = active_weight(&load_proposal(proposal_id)?.mechanism).await?;
+
+    let mut proposal = load_proposal(proposal_id)?;
+    ensure_proposer(&proposal, caller_id)?;
+    if proposal.status != ProposalStatus::Draft {
+        return Err(ApiError::InvalidInput("Only draft proposals can be submitted".to_string()));
+    }
+
+    let config = GOVERNANCE_CONFIG.with(|config| config.borrow().clone());
+    let required = match proposal.proposal_type {
+        ProposalType::ModerationAppeal { .. } => 0,
+        _ => config.required_co_sponsors as usize,
+    };
+    if proposal.co_sponsors.len() < required {
+        return Err(ApiError::InvalidInput(format!("Proposal needs {} co-sponsors", required)));
+    }
+
+    proposal.status = ProposalStatus::Discussion;
+    schedule_discussion(&mut proposal, &config, get_current_time(), eligible);
+    save_proposal(&proposal);
+    Ok(proposal)
 }
 
-// Governance Functions
+// Opens voting on a proposal whose discussion period has ended. Voting also
+// opens on the first vote cast after that.
+#[update]
+#[candid_method(update)]
+fn open_voting(proposal_id: u64) -> Result<Proposal> {
+    let mut proposal = load_proposal(proposal_id)?;
+    if !open_voting_if_due(&mut proposal, get_current_time()) {
+        return Err(ApiError::InvalidInput("Proposal is not ready for voting".to_string()));
+    }
+    save_proposal(&proposal);
+    Ok(proposal)
+}
 
+// Replaces the text or details of a proposal before voting opens, keeping the
+// previous version. Amending a draft clears its co-sponsors, since they
+// signed on to the earlier version, and amending during discussion starts
+// the discussion period over.
 #[update]
 #[candid_method(update)]
-async fn create_proposal(request: CreateProposalRequest) -> Result<Proposal> {
+async fn amend_proposal(proposal_id: u64, request: AmendProposalRequest) -> Result<Proposal> {
     let caller_id = caller();
-    
-    // Validate input
+    ensure_account_active(caller_id).await?;
+
     if request.title.trim().is_empty() {
         return Err(ApiError::InvalidInput("Proposal title cannot be empty".to_string()));
     }
-    
     if request.description.trim().is_empty() {
         return Err(ApiError::InvalidInput("Proposal description cannot be empty".to_string()));
     }
 
-    // Check if user has enough voting power to create proposal
-    let user_voting_power = calculate_voting_power(&caller_id);
-    let config = GOVERNANCE_CONFIG.with(|config| config.borrow().clone());
-    
-    if user_voting_power < config.minimum_proposal_threshold {
-        return Err(ApiError::InsufficientPermissions);
+    let proposal = load_proposal(proposal_id)?;
+    ensure_proposer(&proposal, caller_id)?;
+    if proposal_topic(&request.proposal_type) != proposal_topic(&proposal.proposal_type) {
+        return Err(ApiError::InvalidInput("Amendments cannot change the proposal topic".to_string()));
     }
+    if let ProposalType::ModerationAppeal { action_id } = request.proposal_type {
+        let unchanged = matches!(proposal.proposal_type, ProposalType::ModerationAppeal { action_id: id } if id == action_id);
+        if !unchanged {
+            return Err(ApiError::InvalidInput("Appeals cannot be moved to another action".to_string()));
+        }
+    }
+    if let ProposalType::GovernanceParameter { ref parameter } = request.proposal_type {
+        validate_governance_parameter(parameter)?;
+    }
+    validate_upgrade_proposal(&request.proposal_type)?;
+    validate_pause_extension(&request.proposal_type)?;
+    validate_treasury_proposal(&request.proposal_type).await?;
+    let eligible = active_weight(&proposal.mechanism).await?;
 
-    let proposal_id = PROPOSAL_COUNTER.with(|counter| {
-        let mut counter = counter.borrow_mut();
-        *counter += 1;
-        *counter
-    });
-
+    let mut proposal = load_proposal(proposal_id)?;
     let current_time = get_current_time();
-    let voting_deadline = current_time + days_to_nanoseconds(request.voting_duration_days);
-    
-    let proposal = Proposal {
-        id: proposal_id,
-        proposer_id: caller_id,
-        title: request.title,
-        description: request.description,
-        proposal_type: request.proposal_type,
-        status: ProposalStatus::Active,
-        created_at: current_time,
-        voting_deadline,
-        votes_for: 0,
-        votes_against: 0,
-        votes_abstain: 0,
-        minimum_threshold: config.minimum_voting_threshold,
-        executed_at: None,
-    };
+    if open_voting_if_due(&mut proposal, current_time) {
+        save_proposal(&proposal);
+    }
+    if !matches!(proposal.status, ProposalStatus::Draft | ProposalStatus::Discussion) {
+        return Err(ApiError::InvalidInput("Proposals can only be amended before voting opens".to_string()));
+    }
 
-    PROPOSALS.with(|proposals| proposals.borrow_mut().insert(proposal_id, proposal.clone()));
+    PROPOSAL_REVISIONS.with(|revisions| {
+        revisions.borrow_mut().push(ProposalRevision {
+            proposal_id,
+            version: proposal.version,
+            title: proposal.title.clone(),
+            description: proposal.description.clone(),
+            proposal_type: proposal.proposal_type.clone(),
+            amended_at: current_time,
+        })
+    });
+    match proposal.status {
+        ProposalStatus::Draft => proposal.co_sponsors.clear(),
+        _ => {
+            let config = GOVERNANCE_CONFIG.with(|config| config.borrow().clone());
+            schedule_discussion(&mut proposal, &config, current_time, eligible);
+        }
+    }
+    proposal.title = request.title;
+    proposal.description = request.description;
+    proposal.proposal_type = request.proposal_type;
+    proposal.version += 1;
+    save_proposal(&proposal);
     Ok(proposal)
 }
 
+// Earlier versions of a proposal, oldest first
 #[query]
 #[candid_method(query)]
-fn get_proposal(proposal_id: u64) -> Result<Proposal> {
-    PROPOSALS.with(|proposals| {
-        proposals.borrow().get(&proposal_id).cloned()
-            .ok_or_else(|| ApiError::NotFound("Proposal not found".to_string()))
+fn get_proposal_revisions(proposal_id: u64, cursor: Option<String>, limit: Option<u32>) -> Result<Page<ProposalRevision>> {
+    PROPOSAL_REVISIONS.with(|revisions| {
+        paginate(
+            revisions.borrow().iter().filter(|revision| revision.proposal_id == proposal_id),
+            |revision| format!("{:010}", revision.version),
+            cursor,
+            limit,
+        )
     })
 }
 
+// Withdraws a proposal before anyone has voted on it and refunds its fee. The
+// cancellation stands even if the refund fails; close_proposal retries it.
+#[update]
+#[candid_method(update)]
+async fn cancel_proposal(proposal_id: u64) -> Result<Proposal> {
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let mut proposal = load_proposal(proposal_id)?;
+    ensure_proposer(&proposal, caller_id)?;
+    let open = matches!(
+        proposal.status,
+        ProposalStatus::Draft | ProposalStatus::Discussion | ProposalStatus::Active
+    );
+    if !open || (proposal.status == ProposalStatus::Active && get_current_time() > proposal.voting_deadline) {
+        return Err(ApiError::InvalidInput("Proposal can no longer be cancelled".to_string()));
+    }
+    if has_votes(proposal_id) {
+        return Err(ApiError::InvalidInput("Proposals cannot be cancelled once votes are cast".to_string()));
+    }
+
+    proposal.status = ProposalStatus::Cancelled;
+    save_proposal(&proposal);
+    match settle_proposal_fee(proposal_id).await {
+        Ok(proposal) => Ok(proposal),
+        Err(_) => load_proposal(proposal_id),
+    }
+}
+
 #[update]
 #[candid_method(update)]
 async fn vote_on_proposal(
@@ -203,72 +2111,220 @@ async fn vote_on_proposal(
     vote_type: VoteType,
 ) -> Result<Vote> {
     let caller_id = caller();
+    ensure_account_active(caller_id).await?;
     
     // Get proposal and check if it exists and is active
-    let mut proposal = get_proposal(proposal_id)?;
+    let current_time = get_current_time();
+    let mut proposal = load_proposal(proposal_id)?;
+    open_voting_if_due(&mut proposal, current_time);
     if !matches!(proposal.status, ProposalStatus::Active) {
         return Err(ApiError::InvalidInput("Proposal is not active".to_string()));
     }
     
     // Check if voting period has ended
-    let current_time = get_current_time();
     if current_time > proposal.voting_deadline {
         // Update proposal status if deadline passed
-        proposal.status = if proposal.votes_for > proposal.votes_against && 
-                             proposal.votes_for >= proposal.minimum_threshold {
-            ProposalStatus::Passed
-        } else {
-            ProposalStatus::Rejected
-        };
-        
-        PROPOSALS.with(|proposals| proposals.borrow_mut().insert(proposal_id, proposal));
+        let restricted = restricted_delegators().await?;
+
+        let mut proposal = load_proposal(proposal_id)?;
+        open_voting_if_due(&mut proposal, current_time);
+        if proposal.status == ProposalStatus::Active {
+            let voting_deadline = proposal.voting_deadline;
+            close_voting(&mut proposal, voting_deadline, &restricted);
+            save_proposal(&proposal);
+        }
         return Err(ApiError::InvalidInput("Voting period has ended".to_string()));
     }
 
     let vote_key = vote_key(proposal_id, &caller_id);
     
-    // Check if user already voted
-    if VOTES.with(|votes| votes.borrow().contains_key(&vote_key)) {
-        return Err(ApiError::AlreadyExists("User has already voted on this proposal".to_string()));
+    // A voter can change their vote while the proposal is active; the previous
+    // vote's power is taken back out of the tally before the new one is added
+    let previous = get_vote(proposal_id, &caller_id);
+    if let Some(ref previous) = previous {
+        if previous.vote_type == vote_type {
+            return Err(ApiError::AlreadyExists("User has already cast this vote".to_string()));
+        }
+
+        match previous.vote_type {
+            VoteType::For => proposal.votes_for -= previous.weight,
+            VoteType::Against => proposal.votes_against -= previous.weight,
+            VoteType::Abstain => proposal.votes_abstain -= previous.weight,
+        }
+
+        // Spam flags can only be raised by Against voters
+        if SPAM_FLAGS.with(|flags| flags.borrow_mut().remove(&vote_key)) {
+            proposal.spam_flags = proposal.spam_flags.saturating_sub(previous.weight);
+        }
     }
 
     let voting_power = calculate_voting_power(&caller_id);
+    let weight = vote_weight(&proposal.mechanism, voting_power);
     
     let vote = Vote {
         proposal_id,
         voter_id: caller_id,
         vote_type: vote_type.clone(),
         voting_power,
+        weight,
         timestamp: current_time,
+        revision: previous.map(|previous| previous.revision + 1).unwrap_or(1),
     };
 
-    // Update proposal vote counts
+    // Update proposal vote counts. Conviction is only applied when voting
+    // closes, so until then these hold each vote's full weight.
     match vote_type {
-        VoteType::For => proposal.votes_for += voting_power,
-        VoteType::Against => proposal.votes_against += voting_power,
-        VoteType::Abstain => proposal.votes_abstain += voting_power,
+        VoteType::For => proposal.votes_for += weight,
+        VoteType::Against => proposal.votes_against += weight,
+        VoteType::Abstain => proposal.votes_abstain += weight,
     }
 
     // Store vote and updated proposal
-    VOTES.with(|votes| votes.borrow_mut().insert(vote_key, vote.clone()));
+    VOTES.with(|votes| votes.borrow_mut().entry(proposal_id).or_default().insert(caller_id, vote.clone()));
+    VOTE_HISTORY.with(|history| history.borrow_mut().push(vote.clone()));
     PROPOSALS.with(|proposals| proposals.borrow_mut().insert(proposal_id, proposal));
     
     Ok(vote)
 }
 
+// Every vote cast on a proposal in order, including votes that were later
+// changed, so the final tally can be audited
+#[query]
+#[candid_method(query)]
+fn get_vote_history(proposal_id: u64, cursor: Option<String>, limit: Option<u32>) -> Result<Page<Vote>> {
+    VOTE_HISTORY.with(|history| {
+        paginate(
+            history.borrow().iter().filter(|vote| vote.proposal_id == proposal_id),
+            |vote| format!("{:020}:{}:{:010}", vote.timestamp, vote.voter_id, vote.revision),
+            cursor,
+            limit,
+        )
+    })
+}
+
+// Lets an Against voter additionally flag the proposal as spam, which
+// forfeits the proposer's fee if most of the voting power cast agrees
+#[update]
+#[candid_method(update)]
+async fn flag_proposal_as_spam(proposal_id: u64) -> Result<Proposal> {
+    let caller_id = caller();
+    ensure_account_active(caller_id).await?;
+
+    let mut proposal = load_proposal(proposal_id)?;
+    if proposal.status != ProposalStatus::Active || get_current_time() > proposal.voting_deadline {
+        return Err(ApiError::InvalidInput("Proposal is not active".to_string()));
+    }
+
+    let vote_key = vote_key(proposal_id, &caller_id);
+    let vote = get_vote(proposal_id, &caller_id)
+        .filter(|vote| vote.vote_type == VoteType::Against)
+        .ok_or_else(|| ApiError::InvalidInput("Only Against voters can flag a proposal as spam".to_string()))?;
+
+    if !SPAM_FLAGS.with(|flags| flags.borrow_mut().insert(vote_key)) {
+        return Err(ApiError::AlreadyExists("You have already flagged this proposal".to_string()));
+    }
+
+    proposal.spam_flags += vote.weight;
+    save_proposal(&proposal);
+    Ok(proposal)
+}
+
+// Closes voting on a proposal whose deadline has passed and settles its fee.
+// Conviction proposals can also close early, as soon as enough conviction
+// has built up for them to pass. Calling it again retries a fee settlement
+// that failed.
+#[update]
+#[candid_method(update)]
+async fn close_proposal(proposal_id: u64) -> Result<Proposal> {
+    let restricted = restricted_delegators().await?;
+    let mut proposal = load_proposal(proposal_id)?;
+    open_voting_if_due(&mut proposal, get_current_time());
+
+    if proposal.status == ProposalStatus::Active {
+        let current_time = get_current_time();
+        if current_time > proposal.voting_deadline {
+            let voting_deadline = proposal.voting_deadline;
+            close_voting(&mut proposal, voting_deadline, &restricted);
+        } else if proposal.mechanism == VotingMechanism::Conviction {
+            let mut closed = proposal.clone();
+            close_voting(&mut closed, current_time, &restricted);
+            if closed.status != ProposalStatus::Passed {
+                return Err(ApiError::InvalidInput("Not enough conviction to pass yet".to_string()));
+            }
+            closed.voting_deadline = current_time;
+            proposal = closed;
+        } else {
+            return Err(ApiError::InvalidInput("Voting period has not ended".to_string()));
+        }
+        save_proposal(&proposal);
+    }
+
+    settle_proposal_fee(proposal_id).await
+}
+
+#[update]
+#[candid_method(update)]
+async fn set_ledger(ledger_canister_id: Option<Principal>) -> Result<bool> {
+    if !ic_cdk::api::is_controller(&caller()) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    LEDGER_CANISTER.with(|ledger| *ledger.borrow_mut() = ledger_canister_id);
+    Ok(true)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_treasury_account() -> Account {
+    treasury_account()
+}
+
+// Asks the ledger for the treasury balance, which also includes deposits
+// made directly to the treasury account
+#[update]
+#[candid_method(update)]
+async fn get_treasury_balance() -> Result<Nat> {
+    let ledger = ledger_canister()
+        .ok_or_else(|| ApiError::InternalError("Ledger is not configured".to_string()))?;
+
+    let (balance,): (Nat,) = ic_cdk::call(ledger, "icrc1_balance_of", (treasury_account(),))
+        .await
+        .map_err(|(_, message)| ApiError::InternalError(message))?;
+    Ok(balance)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_treasury_movements(cursor: Option<String>, limit: Option<u32>) -> Result<Page<TreasuryMovement>> {
+    // Newest movements first
+    TREASURY_MOVEMENTS.with(|movements| {
+        paginate(
+            movements.borrow().iter(),
+            |movement| format!("{:020}", u64::MAX - movement.id),
+            cursor,
+            limit,
+        )
+    })
+}
+
 #[query]
 #[candid_method(query)]
 fn get_user_vote(proposal_id: u64, user_id: Principal) -> Option<Vote> {
-    let vote_key = vote_key(proposal_id, &user_id);
-    VOTES.with(|votes| votes.borrow().get(&vote_key).cloned())
+    get_vote(proposal_id, &user_id)
+}
+
+// Passed proposals wait out the execution delay, except emergency ones
+fn executable_at(proposal: &Proposal, config: &GovernanceConfig) -> u64 {
+    if is_emergency(&proposal.proposal_type) {
+        return proposal.voting_deadline;
+    }
+    proposal.voting_deadline + days_to_nanoseconds(config.execution_delay_days)
 }
 
 #[update]
 #[candid_method(update)]
 async fn execute_proposal(proposal_id: u64) -> Result<bool> {
-    let caller_id = caller();
-    
-    let mut proposal = get_proposal(proposal_id)?;
+    let mut proposal = load_proposal(proposal_id)?;
     
     // Check if proposal has passed
     if !matches!(proposal.status, ProposalStatus::Passed) {
@@ -278,11 +2334,22 @@ async fn execute_proposal(proposal_id: u64) -> Result<bool> {
     // Check if execution delay has passed
     let current_time = get_current_time();
     let config = GOVERNANCE_CONFIG.with(|config| config.borrow().clone());
-    let execution_delay = days_to_nanoseconds(config.execution_delay_days);
-    if current_time < (proposal.voting_deadline + execution_delay) {
+    if current_time < executable_at(&proposal, &config) {
         return Err(ApiError::InvalidInput("Execution delay period has not passed".to_string()));
     }
 
+    // Guardians can hold back execution, except of the proposals that extend a pause
+    if !matches!(proposal.proposal_type, ProposalType::ExtendPause { .. }) {
+        if let Some(until) = paused_until(&PauseScope::ProposalExecution) {
+            return Err(ApiError::InvalidInput(format!("Proposal execution is paused until {}", until)));
+        }
+    }
+
+    // Only one execution may be in flight so that transfers cannot run twice
+    if !EXECUTING_PROPOSALS.with(|executing| executing.borrow_mut().insert(proposal_id)) {
+        return Err(ApiError::InvalidInput("Proposal is already being executed".to_string()));
+    }
+
     // Execute the proposal based on its type
     let execution_successful = match &proposal.proposal_type {
         ProposalType::CourseApproval { course_id } => {
@@ -293,159 +2360,214 @@ async fn execute_proposal(proposal_id: u64) -> Result<bool> {
             // Inter-canister call to verify instructor
             execute_instructor_verification(*instructor_id).await
         },
-        ProposalType::PlatformUpgrade { upgrade_details: _ } => {
-            // This would trigger a platform upgrade
-            // For now, we'll just mark it as executed
-            true
+        ProposalType::PlatformUpgrade { canister_id, wasm_hash, arg, .. } => {
+            // Management canister calls to upgrade the target canister
+            execute_platform_upgrade(proposal_id, *canister_id, wasm_hash.clone(), arg.clone()).await
         },
         ProposalType::TokenomicsChange { change_details: _ } => {
             // This would update tokenomics parameters
             // For now, we'll just mark it as executed
             true
         },
-        ProposalType::GovernanceParameter { parameter, new_value } => {
-            execute_governance_parameter_change(parameter.clone(), new_value.clone()).await
+        ProposalType::GovernanceParameter { parameter } => {
+            apply_governance_parameter(parameter);
+            true
+        },
+        ProposalType::ModerationAppeal { action_id } => {
+            // Inter-canister call to overturn the appealed moderation action
+            execute_moderation_appeal(*action_id, proposal_id).await
+        },
+        ProposalType::TreasurySpend { recipient, amount, memo } => {
+            execute_treasury_spend(proposal_id, recipient.clone(), *amount, memo.clone()).await
+        },
+        ProposalType::ScholarshipGrant { recipient, course_id, amount } => {
+            execute_scholarship_grant(proposal_id, *recipient, course_id.clone(), *amount).await
+        },
+        ProposalType::ExtendPause { scope, hours } => {
+            execute_pause_extension(proposal_id, scope.clone(), *hours).await
         },
     };
 
+    EXECUTING_PROPOSALS.with(|executing| executing.borrow_mut().remove(&proposal_id));
+
+    proposal = load_proposal(proposal_id)?;
     if execution_successful {
         proposal.status = ProposalStatus::Executed;
         proposal.executed_at = Some(current_time);
+        proposal.execution_error = None;
     }
 
     PROPOSALS.with(|proposals| proposals.borrow_mut().insert(proposal_id, proposal));
     Ok(execution_successful)
 }
 
-async fn execute_course_approval(course_id: String) -> bool {
+async fn execute_course_approval(_course_id: String) -> bool {
     // Inter-canister call to course_management to approve/publish course
     true
 }
 
-async fn execute_instructor_verification(instructor_id: Principal) -> bool {
+async fn execute_instructor_verification(_instructor_id: Principal) -> bool {
     // Inter-canister call to user_management to update user role to instructor
     true
 }
 
-async fn execute_governance_parameter_change(parameter: String, new_value: String) -> bool {
-    match parameter.as_str() {
-        "minimum_proposal_threshold" => {
-            if let Ok(value) = new_value.parse::<u64>() {
-                GOVERNANCE_CONFIG.with(|config| {
-                    config.borrow_mut().minimum_proposal_threshold = value;
-                });
-                true
-            } else {
-                false
-            }
+async fn execute_moderation_appeal(action_id: u64, proposal_id: u64) -> bool {
+    let canister_id = match course_management_canister() {
+        Ok(canister_id) => canister_id,
+        Err(_) => return false,
+    };
+
+    let result: std::result::Result<(Result<candid::Reserved>,), _> =
+        ic_cdk::call(canister_id, "overturn_moderation_action", (action_id, proposal_id)).await;
+    matches!(result, Ok((Ok(_),)))
+}
+
+// Governance Parameter Functions
+
+const MINIMUM_PROPOSAL_THRESHOLD: RangeInclusive<u64> = 1..=1_000_000;
+const MINIMUM_VOTING_THRESHOLD: RangeInclusive<u64> = 1..=100_000_000;
+const VOTING_PERIOD_DAYS: RangeInclusive<u64> = 1..=30;
+const EXECUTION_DELAY_DAYS: RangeInclusive<u64> = 1..=14;
+const PROPOSAL_FEE: RangeInclusive<u64> = 0..=1_000_000;
+const QUORUM_PERCENT: RangeInclusive<u64> = 0..=100;
+const REQUIRED_CO_SPONSORS: RangeInclusive<u64> = 0..=10;
+const DISCUSSION_PERIOD_DAYS: RangeInclusive<u64> = 1..=14;
+
+fn check_bounds(name: &str, value: u64, bounds: RangeInclusive<u64>) -> Result<()> {
+    if !bounds.contains(&value) {
+        return Err(ApiError::InvalidInput(format!(
+            "{} must be between {} and {}",
+            name, bounds.start(), bounds.end()
+        )));
+    }
+    Ok(())
+}
+
+fn validate_governance_parameter(parameter: &GovernanceParameter) -> Result<()> {
+    match parameter {
+        GovernanceParameter::MinimumProposalThreshold { value } => {
+            check_bounds("minimum_proposal_threshold", *value, MINIMUM_PROPOSAL_THRESHOLD)
         },
-        "minimum_voting_threshold" => {
-            if let Ok(value) = new_value.parse::<u64>() {
-                GOVERNANCE_CONFIG.with(|config| {
-                    config.borrow_mut().minimum_voting_threshold = value;
-                });
-                true
-            } else {
-                false
-            }
+        GovernanceParameter::MinimumVotingThreshold { value } => {
+            check_bounds("minimum_voting_threshold", *value, MINIMUM_VOTING_THRESHOLD)
         },
-        "voting_period_days" => {
-            if let Ok(value) = new_value.parse::<u64>() {
-                GOVERNANCE_CONFIG.with(|config| {
-                    config.borrow_mut().voting_period_days = value;
-                });
-                true
-            } else {
-                false
-            }
+        GovernanceParameter::VotingPeriodDays { value } => {
+            check_bounds("voting_period_days", *value, VOTING_PERIOD_DAYS)
+        },
+        GovernanceParameter::ExecutionDelayDays { value } => {
+            check_bounds("execution_delay_days", *value, EXECUTION_DELAY_DAYS)
         },
-        _ => false,
+        GovernanceParameter::ProposalFee { value } => check_bounds("proposal_fee", *value, PROPOSAL_FEE),
+        GovernanceParameter::VotingMechanism { .. } => Ok(()),
+        GovernanceParameter::VotingRule { quorum_percent, .. } => {
+            check_bounds("quorum_percent", *quorum_percent as u64, QUORUM_PERCENT)
+        },
+        GovernanceParameter::RequiredCoSponsors { value } => {
+            check_bounds("required_co_sponsors", *value as u64, REQUIRED_CO_SPONSORS)
+        },
+        GovernanceParameter::DiscussionPeriodDays { value } => {
+            check_bounds("discussion_period_days", *value, DISCUSSION_PERIOD_DAYS)
+        },
+    }
+}
+
+// Applies the same bounds to a whole config set directly by an admin
+fn validate_governance_config(config: &GovernanceConfig) -> Result<()> {
+    for parameter in [
+        GovernanceParameter::MinimumProposalThreshold { value: config.minimum_proposal_threshold },
+        GovernanceParameter::MinimumVotingThreshold { value: config.minimum_voting_threshold },
+        GovernanceParameter::VotingPeriodDays { value: config.voting_period_days },
+        GovernanceParameter::ExecutionDelayDays { value: config.execution_delay_days },
+        GovernanceParameter::ProposalFee { value: config.proposal_fee },
+        GovernanceParameter::RequiredCoSponsors { value: config.required_co_sponsors },
+        GovernanceParameter::DiscussionPeriodDays { value: config.discussion_period_days },
+    ] {
+        validate_governance_parameter(&parameter)?;
     }
+    validate_voting_rules(&config.voting_rules)?;
+    if config.voting_mechanisms.iter().enumerate().any(|(index, setting)| {
+        config.voting_mechanisms[..index].iter().any(|other| other.topic == setting.topic)
+    }) {
+        return Err(ApiError::InvalidInput("Duplicate voting mechanism for a topic".to_string()));
+    }
+    Ok(())
+}
+
+// Parameters were validated when the proposal was created, so this cannot fail
+fn apply_governance_parameter(parameter: &GovernanceParameter) {
+    GOVERNANCE_CONFIG.with(|config| {
+        let mut config = config.borrow_mut();
+        match parameter.clone() {
+            GovernanceParameter::MinimumProposalThreshold { value } => config.minimum_proposal_threshold = value,
+            GovernanceParameter::MinimumVotingThreshold { value } => config.minimum_voting_threshold = value,
+            GovernanceParameter::VotingPeriodDays { value } => config.voting_period_days = value,
+            GovernanceParameter::ExecutionDelayDays { value } => config.execution_delay_days = value,
+            GovernanceParameter::ProposalFee { value } => config.proposal_fee = value,
+            GovernanceParameter::VotingMechanism { topic, mechanism } => {
+                config.voting_mechanisms.retain(|setting| setting.topic != topic);
+                config.voting_mechanisms.push(MechanismSetting { topic, mechanism });
+            },
+            GovernanceParameter::VotingRule { topic, quorum_percent, approval } => {
+                config.voting_rules.retain(|rule| rule.topic != topic);
+                config.voting_rules.push(VotingRule { topic, quorum_percent, approval });
+            },
+            GovernanceParameter::RequiredCoSponsors { value } => config.required_co_sponsors = value,
+            GovernanceParameter::DiscussionPeriodDays { value } => config.discussion_period_days = value,
+        }
+    });
 }
 
 #[query]
 #[candid_method(query)]
-fn get_active_proposals(limit: Option<u32>) -> Vec<Proposal> {
-    let limit = limit.unwrap_or(10).min(100) as usize;
-    
-    PROPOSALS.with(|proposals| {
-        let mut active_proposals: Vec<Proposal> = proposals.borrow()
-            .iter()
-            .filter_map(|(_, proposal)| {
-                if matches!(proposal.status, ProposalStatus::Active) {
-                    Some(proposal.clone())
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        // Sort by creation time (newest first)
-        active_proposals.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-        active_proposals.truncate(limit);
-        active_proposals
-    })
+fn get_active_proposals(cursor: Option<String>, limit: Option<u32>) -> Result<Page<Proposal>> {
+    let proposals = current_proposals();
+    paginate(
+        proposals.iter().filter(|proposal| matches!(proposal.status, ProposalStatus::Active)),
+        proposal_page_key,
+        cursor,
+        limit,
+    )
 }
 
 #[query]
 #[candid_method(query)]
 fn get_proposal_history(
     status: Option<ProposalStatus>,
+    cursor: Option<String>,
     limit: Option<u32>,
-) -> Vec<Proposal> {
-    let limit = limit.unwrap_or(10).min(100) as usize;
-    
-    PROPOSALS.with(|proposals| {
-        let mut filtered_proposals: Vec<Proposal> = proposals.borrow()
-            .iter()
-            .filter_map(|(_, proposal)| {
-                if let Some(ref filter_status) = status {
-                    if proposal.status != *filter_status {
-                        return None;
-                    }
-                }
-                Some(proposal.clone())
-            })
-            .collect();
-
-        // Sort by creation time (newest first)
-        filtered_proposals.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-        filtered_proposals.truncate(limit);
-        filtered_proposals
-    })
+) -> Result<Page<Proposal>> {
+    let proposals = current_proposals();
+    paginate(
+        proposals.iter().filter(|proposal| status.as_ref().map(|status| proposal.status == *status).unwrap_or(true)),
+        proposal_page_key,
+        cursor,
+        limit,
+    )
 }
 
 #[query]
 #[candid_method(query)]
-fn get_user_proposals(user_id: Principal) -> Vec<Proposal> {
-    PROPOSALS.with(|proposals| {
-        proposals.borrow()
-            .iter()
-            .filter_map(|(_, proposal)| {
-                if proposal.proposer_id == user_id {
-                    Some(proposal.clone())
-                } else {
-                    None
-                }
-            })
-            .collect()
-    })
+fn get_user_proposals(user_id: Principal, cursor: Option<String>, limit: Option<u32>) -> Result<Page<Proposal>> {
+    let proposals = current_proposals();
+    paginate(
+        proposals.iter().filter(|proposal| proposal.proposer_id == user_id),
+        proposal_page_key,
+        cursor,
+        limit,
+    )
 }
 
 #[query]
 #[candid_method(query)]
-fn get_proposal_votes(proposal_id: u64) -> Vec<Vote> {
+fn get_proposal_votes(proposal_id: u64, cursor: Option<String>, limit: Option<u32>) -> Result<Page<Vote>> {
+    // Votes are listed in the order they were cast
     VOTES.with(|votes| {
-        votes.borrow()
-            .iter()
-            .filter_map(|(_, vote)| {
-                if vote.proposal_id == proposal_id {
-                    Some(vote.clone())
-                } else {
-                    None
-                }
-            })
-            .collect()
+        paginate(
+            votes.borrow().get(&proposal_id).into_iter().flat_map(HashMap::values),
+            |vote| format!("{:020}:{}", vote.timestamp, vote.voter_id),
+            cursor,
+            limit,
+        )
     })
 }
 
@@ -475,25 +2597,20 @@ fn get_user_voting_power(user_id: Principal) -> u64 {
 #[query]
 #[candid_method(query)]
 fn get_governance_stats() -> GovernanceStats {
-    let (total_proposals, active_proposals, executed_proposals) = 
-        PROPOSALS.with(|proposals| {
-            let mut total = 0u64;
-            let mut active = 0u64;
-            let mut executed = 0u64;
-
-            for (_, proposal) in proposals.borrow().iter() {
-                total += 1;
-                match proposal.status {
-                    ProposalStatus::Active => active += 1,
-                    ProposalStatus::Executed => executed += 1,
-                    _ => {}
-                }
-            }
+    let mut total_proposals = 0u64;
+    let mut active_proposals = 0u64;
+    let mut executed_proposals = 0u64;
 
-            (total, active, executed)
-        });
+    for proposal in current_proposals() {
+        total_proposals += 1;
+        match proposal.status {
+            ProposalStatus::Active => active_proposals += 1,
+            ProposalStatus::Executed => executed_proposals += 1,
+            _ => {}
+        }
+    }
 
-    let total_votes = VOTES.with(|votes| votes.borrow().len());
+    let total_votes: usize = VOTES.with(|votes| votes.borrow().values().map(HashMap::len).sum());
     
     let config = GOVERNANCE_CONFIG.with(|config| config.borrow().clone());
 
@@ -515,6 +2632,7 @@ async fn update_governance_config(new_config: GovernanceConfig) -> Result<bool>
     if !is_user_admin(caller_id).await {
         return Err(ApiError::InsufficientPermissions);
     }
+    validate_governance_config(&new_config)?;
 
     GOVERNANCE_CONFIG.with(|config| {
         *config.borrow_mut() = new_config;
@@ -535,3 +2653,267 @@ pub struct GovernanceStats {
 
 
 export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::Future;
+
+    thread_local! {
+        static NOW: RefCell<u64> = const { RefCell::new(0) };
+    }
+
+    pub(super) fn now() -> u64 {
+        NOW.with(|now| *now.borrow())
+    }
+
+    fn set_now(time: u64) {
+        NOW.with(|now| *now.borrow_mut() = time);
+    }
+
+    // Runs a future that makes no inter-canister calls to completion
+    fn now_or_never<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+        match future.as_mut().poll(&mut context) {
+            std::task::Poll::Ready(output) => output,
+            std::task::Poll::Pending => panic!("future made an inter-canister call"),
+        }
+    }
+
+    fn principal(n: u8) -> Principal {
+        Principal::from_slice(&[n; 29])
+    }
+
+    fn delegate(delegator: Principal, delegate: Principal, topic: Option<ProposalTopic>) {
+        let delegation = Delegation { delegator, delegate, topic, created_at: 0 };
+        DELEGATIONS.with(|delegations| {
+            delegations.borrow_mut().insert(delegation_key(&delegator, topic), delegation)
+        });
+    }
+
+    fn cast(proposal_id: u64, voter_id: Principal, vote_type: VoteType, weight: u64) {
+        let vote = Vote {
+            proposal_id,
+            voter_id,
+            vote_type,
+            voting_power: weight,
+            weight,
+            timestamp: 0,
+            revision: 1,
+        };
+        VOTES.with(|votes| votes.borrow_mut().entry(proposal_id).or_default().insert(voter_id, vote));
+    }
+
+    fn proposal(id: u64) -> Proposal {
+        Proposal {
+            id,
+            proposer_id: principal(0),
+            title: "Proposal".to_string(),
+            description: String::new(),
+            proposal_type: ProposalType::TokenomicsChange { change_details: String::new() },
+            status: ProposalStatus::Active,
+            created_at: 0,
+            co_sponsors: vec![],
+            version: 1,
+            voting_duration_days: 7,
+            voting_opens_at: None,
+            voting_deadline: days_to_nanoseconds(7),
+            votes_for: 0,
+            votes_against: 0,
+            votes_abstain: 0,
+            delegated_for: 0,
+            delegated_against: 0,
+            delegated_abstain: 0,
+            minimum_threshold: 0,
+            quorum: 0,
+            approval: ApprovalThreshold::SimpleMajority,
+            executed_at: None,
+            execution_error: None,
+            fee: ProposalFee { amount: 0, collected_in: None, status: ProposalFeeStatus::Waived },
+            spam_flags: 0,
+            mechanism: VotingMechanism::Linear,
+        }
+    }
+
+    #[test]
+    fn days_to_nanoseconds_saturates() {
+        assert_eq!(days_to_nanoseconds(1), 86_400_000_000_000);
+        assert_eq!(days_to_nanoseconds(u32::MAX as u64), u64::MAX);
+        assert_eq!(hours_to_nanoseconds(u64::MAX / 2), u64::MAX);
+    }
+
+    #[test]
+    fn voting_durations_respect_the_voting_period() {
+        let config = GovernanceConfig::default();
+        assert!(validate_voting_duration(&config, 0).is_err());
+        assert!(validate_voting_duration(&config, config.voting_period_days - 1).is_err());
+        assert!(validate_voting_duration(&config, config.voting_period_days).is_ok());
+        assert!(validate_voting_duration(&config, *VOTING_PERIOD_DAYS.end()).is_ok());
+        assert!(validate_voting_duration(&config, *VOTING_PERIOD_DAYS.end() + 1).is_err());
+    }
+
+    #[test]
+    fn approval_thresholds_ignore_abstentions() {
+        assert!(meets_approval(&ApprovalThreshold::SimpleMajority, 51, 49));
+        assert!(!meets_approval(&ApprovalThreshold::SimpleMajority, 50, 50));
+        assert!(meets_approval(&ApprovalThreshold::TwoThirds, 2, 1));
+        assert!(!meets_approval(&ApprovalThreshold::TwoThirds, 65, 35));
+        assert!(meets_approval(&ApprovalThreshold::ThreeQuarters, 3, 1));
+        assert!(!meets_approval(&ApprovalThreshold::ThreeQuarters, 74, 26));
+        assert!(!meets_approval(&ApprovalThreshold::TwoThirds, 0, 0));
+        assert!(meets_approval(&ApprovalThreshold::SimpleMajority, u64::MAX, u64::MAX - 1));
+    }
+
+    #[test]
+    fn quorum_is_measured_against_the_voter_base() {
+        let voters = [principal(1), principal(2), principal(3), principal(4)];
+        USER_VOTING_POWER.with(|power| power.borrow_mut().insert(principal(1).to_text(), 400));
+        assert_eq!(eligible_weight(&VotingMechanism::Linear, &voters), 700);
+        assert_eq!(eligible_weight(&VotingMechanism::Quadratic, &voters), 50);
+
+        let config = GovernanceConfig { minimum_voting_threshold: 0, ..GovernanceConfig::default() };
+        let rule = voting_rule(&config, ProposalTopic::Tokenomics);
+        assert_eq!(quorum_for(&config, &VotingMechanism::Linear, &rule, 700), 105);
+    }
+
+    #[test]
+    fn quorum_follows_the_voter_base_when_discussion_restarts() {
+        let config = GovernanceConfig { minimum_voting_threshold: 0, ..GovernanceConfig::default() };
+        let mut proposal = proposal(5);
+        schedule_discussion(&mut proposal, &config, 0, 700);
+        assert_eq!(proposal.quorum, 105);
+
+        schedule_discussion(&mut proposal, &config, days_to_nanoseconds(1), 2_000);
+        assert_eq!(proposal.quorum, 300);
+    }
+
+    #[test]
+    fn conviction_builds_over_half_lives() {
+        let half_life = days_to_nanoseconds(CONVICTION_HALF_LIFE_DAYS as u64);
+        assert_eq!(weight_at(&VotingMechanism::Conviction, 1_000, 0, 0), 0);
+        assert_eq!(weight_at(&VotingMechanism::Conviction, 1_000, 0, half_life), 500);
+        assert_eq!(weight_at(&VotingMechanism::Conviction, 1_000, 0, 2 * half_life), 750);
+        assert_eq!(weight_at(&VotingMechanism::Conviction, 1_000, half_life, half_life), 0);
+        assert_eq!(weight_at(&VotingMechanism::Linear, 1_000, 0, 0), 1_000);
+        assert_eq!(weight_at(&VotingMechanism::Quadratic, 31, 0, 0), 31);
+    }
+
+    #[test]
+    fn late_conviction_votes_cannot_outweigh_held_ones() {
+        let (alice, bob) = (principal(1), principal(2));
+        let mut proposal = Proposal { mechanism: VotingMechanism::Conviction, ..proposal(4) };
+        cast(4, alice, VoteType::For, 100);
+        cast(4, bob, VoteType::Against, 1_000);
+        VOTES.with(|votes| {
+            let mut votes = votes.borrow_mut();
+            let bob_vote = votes.get_mut(&4).unwrap().get_mut(&bob).unwrap();
+            bob_vote.timestamp = proposal.voting_deadline - hours_to_nanoseconds(1);
+        });
+
+        let deadline = proposal.voting_deadline;
+        close_voting(&mut proposal, deadline, &HashSet::new());
+        assert_eq!((proposal.votes_for, proposal.votes_against), (91, 14));
+        assert_eq!(proposal.status, ProposalStatus::Passed);
+    }
+
+    #[test]
+    fn spam_flags_decay_with_their_votes() {
+        let (alice, bob) = (principal(1), principal(2));
+        cast(2, alice, VoteType::For, 100);
+        cast(2, bob, VoteType::Against, 100);
+        SPAM_FLAGS.with(|flags| flags.borrow_mut().insert(vote_key(2, &bob)));
+        let mut proposal = Proposal { mechanism: VotingMechanism::Conviction, spam_flags: 100, ..proposal(2) };
+
+        close_voting(&mut proposal, days_to_nanoseconds(CONVICTION_HALF_LIFE_DAYS as u64), &HashSet::new());
+        assert_eq!((proposal.votes_against, proposal.spam_flags), (50, 50));
+        assert!(!is_spam(&proposal));
+    }
+
+    #[test]
+    fn guardian_pauses_can_be_extended_before_they_run_out() {
+        let config = GovernanceConfig::default();
+        let scope = PauseScope::ProposalExecution;
+        let guardian_pause_ends = hours_to_nanoseconds(MAX_GUARDIAN_PAUSE_HOURS);
+        now_or_never(apply_pause(&scope, guardian_pause_ends)).unwrap();
+
+        // Submitted a day into the pause, once it has its co-sponsors
+        set_now(hours_to_nanoseconds(24));
+        let mut proposal = Proposal {
+            proposal_type: ProposalType::ExtendPause { scope: scope.clone(), hours: 240 },
+            status: ProposalStatus::Discussion,
+            ..proposal(6)
+        };
+        schedule_discussion(&mut proposal, &config, now(), 0);
+        assert_eq!(current_view(proposal.clone(), now()).status, ProposalStatus::Active);
+
+        cast(6, principal(1), VoteType::For, config.minimum_voting_threshold);
+        let deadline = proposal.voting_deadline;
+        close_voting(&mut proposal, deadline, &HashSet::new());
+        assert_eq!(proposal.status, ProposalStatus::Passed);
+
+        set_now(executable_at(&proposal, &config));
+        assert_eq!(now(), hours_to_nanoseconds(48));
+        assert_eq!(paused_until(&scope), Some(guardian_pause_ends));
+        now_or_never(apply_pause(&scope, pause_extension_end(&scope, 240))).unwrap();
+        assert_eq!(paused_until(&scope), Some(guardian_pause_ends + hours_to_nanoseconds(240)));
+    }
+
+    #[test]
+    fn discussion_reports_as_active_once_its_period_is_over() {
+        let config = GovernanceConfig::default();
+        let mut proposal = Proposal { status: ProposalStatus::Discussion, ..proposal(3) };
+        schedule_discussion(&mut proposal, &config, 0, 0);
+        let opens_at = days_to_nanoseconds(config.discussion_period_days);
+        assert_eq!(proposal.voting_opens_at, Some(opens_at));
+        assert_eq!(proposal.voting_deadline, opens_at + days_to_nanoseconds(proposal.voting_duration_days));
+
+        assert_eq!(current_view(proposal.clone(), opens_at - 1).status, ProposalStatus::Discussion);
+        assert_eq!(current_view(proposal, opens_at).status, ProposalStatus::Active);
+    }
+
+    #[test]
+    fn delegated_votes_follow_the_chain() {
+        let (alice, bob, carol) = (principal(1), principal(2), principal(3));
+        delegate(alice, bob, None);
+        delegate(bob, carol, Some(ProposalTopic::Tokenomics));
+        cast(1, carol, VoteType::For, 100);
+        let direct_votes = VOTES.with(|votes| votes.borrow()[&1].clone());
+
+        let vote = resolve_delegated_vote(&alice, ProposalTopic::Tokenomics, &direct_votes);
+        assert_eq!(vote.map(|vote| vote.voter_id), Some(carol));
+        // Bob's delegation only covers tokenomics, so on other topics the chain ends with him
+        assert!(resolve_delegated_vote(&alice, ProposalTopic::Treasury, &direct_votes).is_none());
+    }
+
+    #[test]
+    fn delegation_cycles_are_not_counted() {
+        let (alice, bob) = (principal(1), principal(2));
+        delegate(alice, bob, None);
+        delegate(bob, alice, None);
+        assert!(resolve_delegated_vote(&alice, ProposalTopic::Tokenomics, &HashMap::new()).is_none());
+    }
+
+    #[test]
+    fn restricted_delegators_are_left_out_of_the_tally() {
+        let (alice, bob, carol) = (principal(1), principal(2), principal(3));
+        delegate(alice, carol, None);
+        delegate(bob, carol, None);
+        cast(1, carol, VoteType::Against, 100);
+
+        let tally = tally_proposal(&proposal(1), 0, &HashSet::new());
+        assert_eq!((tally.direct_against, tally.delegated_against), (100, 200));
+        let tally = tally_proposal(&proposal(1), 0, &HashSet::from([bob]));
+        assert_eq!((tally.direct_against, tally.delegated_against), (100, 100));
+    }
+
+    #[test]
+    fn fees_below_the_ledger_fee_are_waived() {
+        let tokenomics = ProposalType::TokenomicsChange { change_details: String::new() };
+        assert!(is_fee_waived(&tokenomics, 0, 0));
+        assert!(is_fee_waived(&tokenomics, 10, 10_000));
+        assert!(is_fee_waived(&tokenomics, 10_000, 10_000));
+        assert!(!is_fee_waived(&tokenomics, 10_001, 10_000));
+        assert!(is_fee_waived(&ProposalType::ModerationAppeal { action_id: 1 }, 1_000_000, 10_000));
+    }
+}
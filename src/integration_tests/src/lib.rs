@@ -0,0 +1,254 @@
+// PocketIC harness for the canister integration tests in tests/.
+//
+// The tests need a PocketIC server and prebuilt wasm modules, all passed in
+// through environment variables, so they are ignored by default and
+// `cargo test --workspace` keeps working on machines without the IC
+// toolchain. Run them with `cargo test -p integration_tests -- --ignored`.
+//
+//   POCKET_IC_BIN             path to the pocket-ic server binary
+//   ICRC1_LEDGER_WASM         path to the DFINITY ICRC-1 ledger wasm (with ICRC-2)
+//   USER_MANAGEMENT_WASM      target/wasm32-unknown-unknown/release/user_management.wasm
+//   COURSE_MANAGEMENT_WASM    as above, for course_management
+//   GOVERNANCE_WASM           as above, for governance
+//
+// The canisters resolve each other through CANISTER_ID_* variables at build
+// time, so build them against the fixed ids below, for example:
+//
+//   CANISTER_ID_USER_MANAGEMENT=rrkah-fqaaa-aaaaa-aaaaq-cai \
+//   CANISTER_ID_COURSE_MANAGEMENT=ryjl3-tyaaa-aaaaa-aaaba-cai \
+//   cargo build --target wasm32-unknown-unknown --release
+
+use candid::utils::{ArgumentDecoder, ArgumentEncoder};
+use candid::{decode_args, encode_args, CandidType, Nat, Principal};
+use pocket_ic::{PocketIc, PocketIcBuilder, WasmResult};
+use std::time::{Duration, UNIX_EPOCH};
+
+pub const USER_MANAGEMENT_ID: &str = "rrkah-fqaaa-aaaaa-aaaaq-cai";
+pub const COURSE_MANAGEMENT_ID: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai";
+
+pub const LEDGER_FEE: u64 = 10_000;
+pub const INITIAL_BALANCE: u64 = 100_000_000;
+
+const CANISTER_CYCLES: u128 = 10_000_000_000_000;
+
+// Reads the wasm modules named by the variables, failing with what is missing
+pub fn wasm_modules(vars: &[&str]) -> Vec<Vec<u8>> {
+    let mut missing: Vec<&str> = Vec::new();
+    if std::env::var_os("POCKET_IC_BIN").is_none() {
+        missing.push("POCKET_IC_BIN");
+    }
+    let modules: Vec<Option<Vec<u8>>> = vars.iter()
+        .map(|var| {
+            let module = std::env::var(var).ok().and_then(|path| std::fs::read(path).ok());
+            if module.is_none() {
+                missing.push(var);
+            }
+            module
+        })
+        .collect();
+
+    if !missing.is_empty() {
+        panic!("PocketIC tests need {} to be set", missing.join(", "));
+    }
+    modules.into_iter().flatten().collect()
+}
+
+// NNS subnet for the canisters installed at fixed ids, application subnet for the rest
+pub fn new_pocket_ic() -> PocketIc {
+    PocketIcBuilder::new().with_nns_subnet().with_application_subnet().build()
+}
+
+pub fn now_nanos(pic: &PocketIc) -> u64 {
+    pic.get_time().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64
+}
+
+pub fn days(days: u64) -> Duration {
+    Duration::from_secs(days * 24 * 60 * 60)
+}
+
+// Installs a module on a new canister controlled by the anonymous principal
+pub fn install(pic: &PocketIc, canister_id: Option<&str>, wasm: Vec<u8>, arg: Vec<u8>) -> Principal {
+    let canister_id = match canister_id {
+        Some(id) => pic
+            .create_canister_with_id(None, None, Principal::from_text(id).unwrap())
+            .expect("fixed canister id is not available"),
+        None => pic.create_canister(),
+    };
+    pic.add_cycles(canister_id, CANISTER_CYCLES);
+    pic.install_canister(canister_id, wasm, arg, None);
+    canister_id
+}
+
+// Upgrades a canister installed by `install`, keeping its stable memory
+pub fn upgrade(pic: &PocketIc, canister_id: Principal, wasm: Vec<u8>) {
+    pic.upgrade_canister(canister_id, wasm, encode_args(()).unwrap(), None)
+        .unwrap_or_else(|error| panic!("upgrade of {} failed: {:?}", canister_id, error));
+}
+
+pub fn update<Args, Reply>(pic: &PocketIc, canister_id: Principal, sender: Principal, method: &str, args: Args) -> Reply
+where
+    Args: ArgumentEncoder,
+    Reply: for<'a> ArgumentDecoder<'a>,
+{
+    let result = pic.update_call(canister_id, sender, method, encode_args(args).unwrap());
+    decode_reply(method, result)
+}
+
+pub fn query<Args, Reply>(pic: &PocketIc, canister_id: Principal, sender: Principal, method: &str, args: Args) -> Reply
+where
+    Args: ArgumentEncoder,
+    Reply: for<'a> ArgumentDecoder<'a>,
+{
+    let result = pic.query_call(canister_id, sender, method, encode_args(args).unwrap());
+    decode_reply(method, result)
+}
+
+fn decode_reply<Reply>(method: &str, result: Result<WasmResult, pocket_ic::UserError>) -> Reply
+where
+    Reply: for<'a> ArgumentDecoder<'a>,
+{
+    match result {
+        Ok(WasmResult::Reply(bytes)) => decode_args(&bytes).unwrap_or_else(|error| {
+            panic!("could not decode the reply to {}: {}", method, error)
+        }),
+        Ok(WasmResult::Reject(message)) => panic!("{} was rejected: {}", method, message),
+        Err(error) => panic!("{} failed: {}", method, error),
+    }
+}
+
+// Courses
+
+#[derive(CandidType)]
+pub enum DifficultyLevel {
+    Beginner,
+}
+
+#[derive(CandidType)]
+pub struct CreateCourseRequest {
+    pub title: String,
+    pub description: String,
+    pub category: String,
+    pub tags: Vec<String>,
+    pub difficulty_level: DifficultyLevel,
+    pub estimated_duration_hours: u32,
+    pub price: u64,
+    pub prerequisites: Vec<String>,
+    pub language: String,
+}
+
+#[derive(Debug, CandidType, candid::Deserialize)]
+pub struct Course {
+    pub id: String,
+}
+
+// Creates and publishes a course, returning its id
+pub fn create_published_course(pic: &PocketIc, courses: Principal, instructor: Principal, title: &str, price: u64) -> String {
+    let request = CreateCourseRequest {
+        title: title.to_string(),
+        description: "Paying for things on chain".to_string(),
+        category: "finance".to_string(),
+        tags: vec![],
+        difficulty_level: DifficultyLevel::Beginner,
+        estimated_duration_hours: 3,
+        price,
+        prerequisites: vec![],
+        language: "en".to_string(),
+    };
+    let (course,): (Result<Course, candid::Reserved>,) = update(pic, courses, instructor, "create_course", (request,));
+    let course = course.expect("create_course failed");
+
+    let (published,): (Result<Course, candid::Reserved>,) =
+        update(pic, courses, instructor, "publish_course", (course.id.clone(),));
+    published.expect("publish_course failed");
+    course.id
+}
+
+// ICRC-1 ledger
+
+#[derive(Clone, Debug, CandidType, candid::Deserialize, PartialEq)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+pub fn account(owner: Principal) -> Account {
+    Account { owner, subaccount: None }
+}
+
+#[derive(CandidType)]
+enum LedgerArg {
+    Init(LedgerInitArgs),
+}
+
+#[derive(CandidType)]
+enum MetadataValue {
+    Text(String),
+}
+
+#[derive(CandidType)]
+struct FeatureFlags {
+    icrc2: bool,
+}
+
+#[derive(CandidType)]
+struct ArchiveOptions {
+    num_blocks_to_archive: u64,
+    trigger_threshold: u64,
+    controller_id: Principal,
+}
+
+#[derive(CandidType)]
+struct LedgerInitArgs {
+    minting_account: Account,
+    transfer_fee: Nat,
+    token_symbol: String,
+    token_name: String,
+    metadata: Vec<(String, MetadataValue)>,
+    initial_balances: Vec<(Account, Nat)>,
+    feature_flags: Option<FeatureFlags>,
+    archive_options: ArchiveOptions,
+}
+
+#[derive(CandidType)]
+struct ApproveArgs {
+    spender: Account,
+    amount: Nat,
+}
+
+// Installs an ICRC-2 ledger where every holder starts with INITIAL_BALANCE
+pub fn install_ledger(pic: &PocketIc, wasm: Vec<u8>, holders: &[Principal]) -> Principal {
+    let minter = Principal::from_slice(&[0xff; 29]);
+    let init = LedgerArg::Init(LedgerInitArgs {
+        minting_account: account(minter),
+        transfer_fee: Nat::from(LEDGER_FEE),
+        token_symbol: "TEST".to_string(),
+        token_name: "Test token".to_string(),
+        metadata: vec![("icrc1:name".to_string(), MetadataValue::Text("Test token".to_string()))],
+        initial_balances: holders.iter().map(|holder| (account(*holder), Nat::from(INITIAL_BALANCE))).collect(),
+        feature_flags: Some(FeatureFlags { icrc2: true }),
+        archive_options: ArchiveOptions {
+            num_blocks_to_archive: 1_000,
+            trigger_threshold: 2_000,
+            controller_id: Principal::anonymous(),
+        },
+    });
+    install(pic, None, wasm, encode_args((init,)).unwrap())
+}
+
+pub fn balance_of(pic: &PocketIc, ledger: Principal, owner: Principal) -> u64 {
+    let (balance,): (Nat,) = query(pic, ledger, owner, "icrc1_balance_of", (account(owner),));
+    u64::try_from(balance.0).unwrap()
+}
+
+pub fn approve(pic: &PocketIc, ledger: Principal, owner: Principal, spender: Principal, amount: u64) {
+    let args = ApproveArgs { spender: account(spender), amount: Nat::from(amount) };
+    let (result,): (Result<Nat, candid::Reserved>,) = update(pic, ledger, owner, "icrc2_approve", (args,));
+    result.expect("approve failed");
+}
+
+// Distinct self-authenticating principals for test users
+pub fn user(n: u8) -> Principal {
+    let mut bytes = [n; 29];
+    bytes[28] = 0x02;
+    Principal::from_slice(&bytes)
+}
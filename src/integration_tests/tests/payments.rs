@@ -0,0 +1,269 @@
+// Course payments against a real ICRC-2 ledger: charges, refunds,
+// waitlist promotion and settlement of instructor earnings.
+
+use candid::{CandidType, Deserialize, Nat, Principal, Reserved};
+use integration_tests::*;
+use pocket_ic::PocketIc;
+
+const PRICE: u64 = 1_000_000;
+const PLATFORM_FEE_BPS: u16 = 1_000;
+
+#[derive(CandidType)]
+struct PaymentConfig {
+    ledger_canister_id: Option<Principal>,
+    platform_fee_bps: u16,
+    platform_account: Option<Account>,
+    refund_window_days: u32,
+    refund_max_completion: f32,
+}
+
+#[derive(CandidType)]
+struct CreateCourseRunRequest {
+    course_id: String,
+    title: String,
+    start_date: u64,
+    end_date: u64,
+    enrollment_opens_at: u64,
+    enrollment_closes_at: u64,
+    capacity: u32,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+struct CourseRun {
+    id: String,
+    enrollment_count: u32,
+    waitlist: Vec<Principal>,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+enum RunEnrollmentStatus {
+    Enrolled(Reserved),
+    Waitlisted { position: u32 },
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+enum PaymentStatus {
+    Held,
+    Refunding,
+    Refunded(Reserved),
+    Settled,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+struct Payment {
+    run_id: Option<String>,
+    amount: u64,
+    status: PaymentStatus,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+struct Page<T> {
+    items: Vec<T>,
+}
+
+struct Setup {
+    pic: PocketIc,
+    ledger: Principal,
+    courses: Principal,
+}
+
+const INSTRUCTOR: u8 = 1;
+
+fn setup(learners: &[Principal]) -> Setup {
+    let mut modules = wasm_modules(&["ICRC1_LEDGER_WASM", "USER_MANAGEMENT_WASM", "COURSE_MANAGEMENT_WASM"]);
+    let course_wasm = modules.pop().unwrap();
+    let user_wasm = modules.pop().unwrap();
+    let ledger_wasm = modules.pop().unwrap();
+
+    let pic = new_pocket_ic();
+    install(&pic, Some(USER_MANAGEMENT_ID), user_wasm, candid::encode_args(()).unwrap());
+    let courses = install(&pic, Some(COURSE_MANAGEMENT_ID), course_wasm, candid::encode_args(()).unwrap());
+    let mut holders = learners.to_vec();
+    holders.push(user(INSTRUCTOR));
+    // The canister starts with a float to pay the ledger fees of refunds from
+    holders.push(courses);
+    let ledger = install_ledger(&pic, ledger_wasm, &holders);
+
+    let config = PaymentConfig {
+        ledger_canister_id: Some(ledger),
+        platform_fee_bps: PLATFORM_FEE_BPS,
+        platform_account: None,
+        refund_window_days: 7,
+        refund_max_completion: 20.0,
+    };
+    let (result,): (Result<Reserved, Reserved>,) =
+        update(&pic, courses, Principal::anonymous(), "set_payment_config", (config,));
+    result.expect("set_payment_config failed");
+
+    Setup { pic, ledger, courses }
+}
+
+fn create_paid_course(setup: &Setup) -> String {
+    create_published_course(&setup.pic, setup.courses, user(INSTRUCTOR), "Ledger basics", PRICE)
+}
+
+fn create_run(setup: &Setup, course_id: &str, capacity: u32) -> String {
+    let now = now_nanos(&setup.pic);
+    let day = days(1).as_nanos() as u64;
+    let request = CreateCourseRunRequest {
+        course_id: course_id.to_string(),
+        title: "Spring cohort".to_string(),
+        start_date: now + 2 * day,
+        end_date: now + 30 * day,
+        enrollment_opens_at: now - 1,
+        enrollment_closes_at: now + day,
+        capacity,
+    };
+    let (run,): (Result<CourseRun, Reserved>,) =
+        update(&setup.pic, setup.courses, user(INSTRUCTOR), "create_course_run", (request,));
+    run.expect("create_course_run failed").id
+}
+
+fn payments_of(setup: &Setup, learner: Principal) -> Vec<Payment> {
+    let (page,): (Result<Page<Payment>, Reserved>,) = query(
+        &setup.pic,
+        setup.courses,
+        learner,
+        "get_user_payments",
+        (learner, None::<String>, None::<u32>),
+    );
+    page.expect("get_user_payments failed").items
+}
+
+fn approve_course_price(setup: &Setup, learner: Principal) {
+    approve(&setup.pic, setup.ledger, learner, setup.courses, PRICE + LEDGER_FEE);
+}
+
+#[test]
+#[ignore = "needs PocketIC and the canister wasm modules"]
+fn self_paced_payment_is_refunded_inside_the_window() {
+    let learner = user(10);
+    let setup = setup(&[learner]);
+    let course_id = create_paid_course(&setup);
+
+    approve_course_price(&setup, learner);
+    let (enrolled,): (Result<Reserved, Reserved>,) =
+        update(&setup.pic, setup.courses, learner, "enroll_in_course", (course_id.clone(),));
+    enrolled.expect("enroll_in_course failed");
+    assert_eq!(balance_of(&setup.pic, setup.ledger, learner), INITIAL_BALANCE - LEDGER_FEE - PRICE - LEDGER_FEE);
+    assert_eq!(balance_of(&setup.pic, setup.ledger, setup.courses), INITIAL_BALANCE + PRICE);
+
+    let (archived,): (Result<Reserved, Reserved>,) =
+        update(&setup.pic, setup.courses, learner, "unenroll", (course_id,));
+    archived.expect("unenroll failed");
+
+    // The price is refunded in full and the canister pays the fee of the transfer back
+    assert_eq!(balance_of(&setup.pic, setup.ledger, learner), INITIAL_BALANCE - 2 * LEDGER_FEE);
+    assert_eq!(balance_of(&setup.pic, setup.ledger, setup.courses), INITIAL_BALANCE - LEDGER_FEE);
+    let payments = payments_of(&setup, learner);
+    assert_eq!(payments.len(), 1);
+    assert!(matches!(payments[0].status, PaymentStatus::Refunded(_)));
+}
+
+#[test]
+#[ignore = "needs PocketIC and the canister wasm modules"]
+fn waitlisted_learners_pay_when_promoted() {
+    let (first, second) = (user(10), user(11));
+    let setup = setup(&[first, second]);
+    let course_id = create_paid_course(&setup);
+    let run_id = create_run(&setup, &course_id, 1);
+
+    approve_course_price(&setup, first);
+    approve_course_price(&setup, second);
+
+    let (status,): (Result<RunEnrollmentStatus, Reserved>,) =
+        update(&setup.pic, setup.courses, first, "enroll_in_run", (run_id.clone(),));
+    assert!(matches!(status, Ok(RunEnrollmentStatus::Enrolled(_))));
+
+    let (status,): (Result<RunEnrollmentStatus, Reserved>,) =
+        update(&setup.pic, setup.courses, second, "enroll_in_run", (run_id.clone(),));
+    assert!(matches!(status, Ok(RunEnrollmentStatus::Waitlisted { position: 1 })));
+    assert_eq!(balance_of(&setup.pic, setup.ledger, second), INITIAL_BALANCE - LEDGER_FEE);
+    assert!(payments_of(&setup, second).is_empty());
+
+    // The first learner's seat is refunded and the second learner is charged for it
+    let (run,): (Result<CourseRun, Reserved>,) =
+        update(&setup.pic, setup.courses, first, "withdraw_from_run", (run_id.clone(),));
+    let run = run.expect("withdraw_from_run failed");
+    assert_eq!(run.enrollment_count, 1);
+    assert!(run.waitlist.is_empty());
+
+    assert_eq!(balance_of(&setup.pic, setup.ledger, first), INITIAL_BALANCE - 2 * LEDGER_FEE);
+    assert_eq!(balance_of(&setup.pic, setup.ledger, second), INITIAL_BALANCE - LEDGER_FEE - PRICE - LEDGER_FEE);
+    let payments = payments_of(&setup, second);
+    assert_eq!(payments.len(), 1);
+    assert_eq!(payments[0].run_id.as_deref(), Some(run_id.as_str()));
+    assert_eq!(payments[0].amount, PRICE);
+    assert!(matches!(payments[0].status, PaymentStatus::Held));
+}
+
+#[test]
+#[ignore = "needs PocketIC and the canister wasm modules"]
+fn leaving_the_waitlist_costs_nothing() {
+    let (first, second) = (user(10), user(11));
+    let setup = setup(&[first, second]);
+    let course_id = create_paid_course(&setup);
+    let run_id = create_run(&setup, &course_id, 1);
+
+    approve_course_price(&setup, first);
+    let (status,): (Result<RunEnrollmentStatus, Reserved>,) =
+        update(&setup.pic, setup.courses, first, "enroll_in_run", (run_id.clone(),));
+    assert!(matches!(status, Ok(RunEnrollmentStatus::Enrolled(_))));
+
+    let (status,): (Result<RunEnrollmentStatus, Reserved>,) =
+        update(&setup.pic, setup.courses, second, "enroll_in_run", (run_id.clone(),));
+    assert!(matches!(status, Ok(RunEnrollmentStatus::Waitlisted { .. })));
+
+    // Long after any refund window, leaving the waitlist still needs no refund
+    setup.pic.advance_time(days(10));
+    let (run,): (Result<CourseRun, Reserved>,) =
+        update(&setup.pic, setup.courses, second, "withdraw_from_run", (run_id,));
+    assert!(run.expect("withdraw_from_run failed").waitlist.is_empty());
+    assert_eq!(balance_of(&setup.pic, setup.ledger, second), INITIAL_BALANCE);
+}
+
+#[test]
+#[ignore = "needs PocketIC and the canister wasm modules"]
+fn earnings_settle_after_the_refund_window() {
+    let learner = user(10);
+    let setup = setup(&[learner]);
+    let course_id = create_paid_course(&setup);
+
+    approve_course_price(&setup, learner);
+    let (enrolled,): (Result<Reserved, Reserved>,) =
+        update(&setup.pic, setup.courses, learner, "enroll_in_course", (course_id,));
+    enrolled.expect("enroll_in_course failed");
+
+    // Nothing can be withdrawn while the payment is still refundable
+    let (withdrawn,): (Result<Nat, Reserved>,) =
+        update(&setup.pic, setup.courses, user(INSTRUCTOR), "withdraw_earnings", (None::<Account>,));
+    assert!(withdrawn.is_err());
+
+    setup.pic.advance_time(days(8));
+    let (withdrawn,): (Result<Nat, Reserved>,) =
+        update(&setup.pic, setup.courses, user(INSTRUCTOR), "withdraw_earnings", (None::<Account>,));
+    withdrawn.expect("withdraw_earnings failed");
+
+    let instructor_share = PRICE - PRICE * PLATFORM_FEE_BPS as u64 / 10_000;
+    assert_eq!(
+        balance_of(&setup.pic, setup.ledger, user(INSTRUCTOR)),
+        INITIAL_BALANCE + instructor_share - LEDGER_FEE
+    );
+    let payments = payments_of(&setup, learner);
+    assert!(matches!(payments[0].status, PaymentStatus::Settled));
+}
+
+#[test]
+#[ignore = "needs PocketIC and the canister wasm modules"]
+fn prices_below_the_ledger_fee_are_not_charged() {
+    let learner = user(10);
+    let setup = setup(&[learner]);
+    let course_id = create_published_course(&setup.pic, setup.courses, user(INSTRUCTOR), "Dust", LEDGER_FEE);
+
+    approve_course_price(&setup, learner);
+    let (enrolled,): (Result<Reserved, Reserved>,) =
+        update(&setup.pic, setup.courses, learner, "enroll_in_course", (course_id,));
+    assert!(enrolled.is_err());
+    assert_eq!(balance_of(&setup.pic, setup.ledger, learner), INITIAL_BALANCE - LEDGER_FEE);
+    assert!(payments_of(&setup, learner).is_empty());
+}
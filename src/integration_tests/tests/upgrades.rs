@@ -0,0 +1,238 @@
+// Canister state has to survive an upgrade: every canister saves its storage
+// to stable memory in pre_upgrade and restores it in post_upgrade.
+
+use candid::{CandidType, Deserialize, Principal, Reserved};
+use integration_tests::*;
+use pocket_ic::PocketIc;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+#[derive(CandidType)]
+struct CreateUserRequest {
+    username: String,
+    email: String,
+    full_name: String,
+    bio: Option<String>,
+    skills: Vec<String>,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+struct User {
+    id: Principal,
+    username: String,
+}
+
+#[derive(CandidType)]
+struct SearchRequest {
+    query: Option<String>,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+struct SearchResults {
+    total: u32,
+}
+
+#[derive(Debug, CandidType, Deserialize, PartialEq)]
+struct GuardianSet {
+    guardians: Vec<Principal>,
+    threshold: u32,
+}
+
+#[derive(CandidType)]
+enum UserRole {
+    Admin,
+}
+
+#[derive(CandidType)]
+enum ProposalType {
+    PlatformUpgrade { canister_id: Principal, wasm_hash: Vec<u8>, arg: Vec<u8>, upgrade_details: String },
+}
+
+#[derive(CandidType)]
+struct CreateProposalRequest {
+    title: String,
+    description: String,
+    proposal_type: ProposalType,
+    voting_duration_days: u64,
+}
+
+#[derive(CandidType)]
+enum VoteType {
+    For,
+}
+
+#[derive(Debug, CandidType, Deserialize, PartialEq)]
+enum ProposalStatus {
+    Draft,
+    Discussion,
+    Active,
+    Passed,
+    Rejected,
+    Executed,
+    Cancelled,
+}
+
+#[derive(Debug, CandidType, Deserialize)]
+struct Proposal {
+    id: u64,
+    status: ProposalStatus,
+    execution_error: Option<String>,
+}
+
+const CHUNK_BYTES: usize = 1024 * 1024;
+
+fn create_user(pic: &PocketIc, users: Principal, n: u8) {
+    let request = CreateUserRequest {
+        username: format!("user{}", n),
+        email: format!("user{}@example.com", n),
+        full_name: format!("User {}", n),
+        bio: None,
+        skills: vec![],
+    };
+    let (created,): (Result<User, Reserved>,) = update(pic, users, user(n), "create_user", (request,));
+    created.expect("create_user failed");
+}
+
+#[test]
+#[ignore = "needs PocketIC and the canister wasm modules"]
+fn users_survive_an_upgrade() {
+    let mut modules = wasm_modules(&["USER_MANAGEMENT_WASM"]);
+    let user_wasm = modules.pop().unwrap();
+    let pic = new_pocket_ic();
+    let users = install(&pic, Some(USER_MANAGEMENT_ID), user_wasm.clone(), candid::encode_args(()).unwrap());
+
+    let request = CreateUserRequest {
+        username: "ada".to_string(),
+        email: "ada@example.com".to_string(),
+        full_name: "Ada Lovelace".to_string(),
+        bio: None,
+        skills: vec![],
+    };
+    let (created,): (Result<User, Reserved>,) = update(&pic, users, user(1), "create_user", (request,));
+    created.expect("create_user failed");
+
+    upgrade(&pic, users, user_wasm);
+
+    let (found,): (Result<User, Reserved>,) = query(&pic, users, user(1), "get_user", (user(1),));
+    let found = found.expect("user was lost in the upgrade");
+    assert_eq!((found.id, found.username.as_str()), (user(1), "ada"));
+}
+
+#[test]
+#[ignore = "needs PocketIC and the canister wasm modules"]
+fn published_courses_stay_searchable_after_an_upgrade() {
+    let mut modules = wasm_modules(&["USER_MANAGEMENT_WASM", "COURSE_MANAGEMENT_WASM"]);
+    let course_wasm = modules.pop().unwrap();
+    let user_wasm = modules.pop().unwrap();
+    let pic = new_pocket_ic();
+    install(&pic, Some(USER_MANAGEMENT_ID), user_wasm, candid::encode_args(()).unwrap());
+    let courses = install(&pic, Some(COURSE_MANAGEMENT_ID), course_wasm.clone(), candid::encode_args(()).unwrap());
+    create_published_course(&pic, courses, user(1), "Ledger basics", 0);
+
+    let search = |pic: &pocket_ic::PocketIc| {
+        let request = SearchRequest { query: Some("ledger".to_string()) };
+        let (results,): (Result<SearchResults, Reserved>,) =
+            query(pic, courses, user(2), "search_courses", (request,));
+        results.expect("search_courses failed").total
+    };
+    assert_eq!(search(&pic), 1);
+
+    // The search index is not saved; it is rebuilt from the restored courses
+    upgrade(&pic, courses, course_wasm);
+    assert_eq!(search(&pic), 1);
+}
+
+#[test]
+#[ignore = "needs PocketIC and the canister wasm modules"]
+fn governance_keeps_its_state_across_an_upgrade() {
+    let mut modules = wasm_modules(&["GOVERNANCE_WASM"]);
+    let governance_wasm = modules.pop().unwrap();
+    let pic = new_pocket_ic();
+    let governance = install(&pic, None, governance_wasm.clone(), candid::encode_args(()).unwrap());
+
+    let (set,): (Result<GuardianSet, Reserved>,) =
+        update(&pic, governance, Principal::anonymous(), "set_guardians", (vec![user(1), user(2)], 2u32));
+    let set = set.expect("set_guardians failed");
+
+    upgrade(&pic, governance, governance_wasm);
+
+    let (guardians,): (GuardianSet,) = query(&pic, governance, Principal::anonymous(), "get_guardians", ());
+    assert_eq!(guardians, set);
+}
+
+#[test]
+#[ignore = "needs PocketIC and the canister wasm modules"]
+fn upgrade_proposals_install_the_uploaded_wasm() {
+    let mut modules = wasm_modules(&["USER_MANAGEMENT_WASM", "GOVERNANCE_WASM"]);
+    let governance_wasm = modules.pop().unwrap();
+    let user_wasm = modules.pop().unwrap();
+    let pic = new_pocket_ic();
+    let users = install(&pic, Some(USER_MANAGEMENT_ID), user_wasm.clone(), candid::encode_args(()).unwrap());
+    let governance = install(&pic, None, governance_wasm, candid::encode_args(()).unwrap());
+    pic.set_controllers(users, None, vec![Principal::anonymous(), governance])
+        .expect("set_controllers failed");
+
+    // An admin gives the proposer and the co-sponsors enough voting power
+    let (admin, proposer, sponsors) = (user(1), user(2), [user(3), user(4)]);
+    for n in 1..=4 {
+        create_user(&pic, users, n);
+    }
+    let (role,): (Result<Reserved, Reserved>,) =
+        update(&pic, users, Principal::anonymous(), "update_user_role", (admin, UserRole::Admin));
+    role.expect("update_user_role failed");
+    for (voter, power) in [(proposer, 100_000u64), (sponsors[0], 1_000), (sponsors[1], 1_000)] {
+        let (set,): (Result<bool, Reserved>,) =
+            update(&pic, governance, admin, "update_user_voting_power", (voter, power));
+        set.expect("update_user_voting_power failed");
+    }
+
+    let request = CreateProposalRequest {
+        title: "Upgrade user_management".to_string(),
+        description: "Reinstalls the current build".to_string(),
+        proposal_type: ProposalType::PlatformUpgrade {
+            canister_id: users,
+            wasm_hash: Sha256::digest(&user_wasm).to_vec(),
+            arg: candid::encode_args(()).unwrap(),
+            upgrade_details: "No changes".to_string(),
+        },
+        voting_duration_days: 7,
+    };
+    let (proposal,): (Result<Proposal, Reserved>,) = update(&pic, governance, proposer, "create_proposal", (request,));
+    let proposal_id = proposal.expect("create_proposal failed").id;
+    for sponsor in sponsors {
+        let (sponsored,): (Result<Proposal, Reserved>,) =
+            update(&pic, governance, sponsor, "sponsor_proposal", (proposal_id,));
+        sponsored.expect("sponsor_proposal failed");
+    }
+    let (submitted,): (Result<Proposal, Reserved>,) =
+        update(&pic, governance, proposer, "submit_proposal", (proposal_id,));
+    assert_eq!(submitted.expect("submit_proposal failed").status, ProposalStatus::Discussion);
+
+    for chunk in user_wasm.chunks(CHUNK_BYTES) {
+        let (uploaded,): (Result<Reserved, Reserved>,) =
+            update(&pic, governance, proposer, "upload_wasm_chunk", (proposal_id, chunk.to_vec()));
+        uploaded.expect("upload_wasm_chunk failed");
+    }
+
+    pic.advance_time(days(2));
+    let (vote,): (Result<Reserved, Reserved>,) =
+        update(&pic, governance, proposer, "vote_on_proposal", (proposal_id, VoteType::For));
+    vote.expect("vote_on_proposal failed");
+
+    pic.advance_time(days(7) + Duration::from_secs(1));
+    let (closed,): (Result<Proposal, Reserved>,) = update(&pic, governance, proposer, "close_proposal", (proposal_id,));
+    assert_eq!(closed.expect("close_proposal failed").status, ProposalStatus::Passed);
+
+    pic.advance_time(days(2));
+    let (executed,): (Result<bool, Reserved>,) =
+        update(&pic, governance, Principal::anonymous(), "execute_proposal", (proposal_id,));
+    let (proposal,): (Result<Proposal, Reserved>,) =
+        query(&pic, governance, Principal::anonymous(), "get_proposal", (proposal_id,));
+    let proposal = proposal.expect("get_proposal failed");
+    assert!(executed.expect("execute_proposal failed"), "upgrade failed: {:?}", proposal.execution_error);
+    assert_eq!(proposal.status, ProposalStatus::Executed);
+
+    // The upgraded canister is running and kept its users
+    let (found,): (Result<User, Reserved>,) = query(&pic, users, proposer, "get_user", (proposer,));
+    assert_eq!(found.expect("user was lost in the upgrade").username, "user2");
+}
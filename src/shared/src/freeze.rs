@@ -0,0 +1,60 @@
+use std::cell::RefCell;
+
+// Governance guardians can freeze a canister's mutating endpoints until a
+// given time. Each canister links its own copy of this state, calls
+// ensure_not_frozen first in every mutating endpoint, and exposes
+// set_frozen_until and get_frozen_until endpoints. Governance, which is a
+// controller, calls set_frozen_until to freeze the canister or to lift a
+// freeze early.
+thread_local! {
+    static FROZEN_UNTIL: RefCell<Option<u64>> = const { RefCell::new(None) };
+}
+
+// Returned to callers of set_frozen_until that are not controllers. Each
+// canister converts it into its own ApiError.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NotController;
+
+// The end of the freeze in effect at `now`, if there is one
+pub fn frozen_until(now: u64) -> Option<u64> {
+    FROZEN_UNTIL.with(|frozen| *frozen.borrow()).filter(|until| now < *until)
+}
+
+pub fn set_frozen_until(frozen_until: Option<u64>) -> Result<(), NotController> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err(NotController);
+    }
+    restore(frozen_until);
+    Ok(())
+}
+
+// Puts back a freeze saved across an upgrade
+pub fn restore(frozen_until: Option<u64>) {
+    FROZEN_UNTIL.with(|frozen| *frozen.borrow_mut() = frozen_until);
+}
+
+// Fails with the end of the freeze while one is in effect
+pub fn ensure_not_frozen(now: u64) -> Result<(), u64> {
+    match frozen_until(now) {
+        Some(until) => Err(until),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn freezes_lapse_at_their_end() {
+        assert!(ensure_not_frozen(0).is_ok());
+
+        restore(Some(100));
+        assert_eq!(frozen_until(99), Some(100));
+        assert_eq!(ensure_not_frozen(99), Err(100));
+        assert!(ensure_not_frozen(100).is_ok());
+
+        restore(None);
+        assert_eq!(frozen_until(0), None);
+    }
+}
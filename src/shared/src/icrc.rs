@@ -0,0 +1,106 @@
+use candid::{CandidType, Deserialize, Nat, Principal};
+use serde::Serialize;
+
+// ICRC-1 ledger account
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct TransferFromArgs {
+    pub spender_subaccount: Option<Vec<u8>>,
+    pub from: Account,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct TransferArg {
+    pub from_subaccount: Option<Vec<u8>>,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+// ICRC-1 ledgers reject longer memos
+pub const MAX_MEMO_BYTES: usize = 32;
+
+pub fn describe_transfer_from_error(error: TransferFromError) -> String {
+    match error {
+        TransferFromError::InsufficientFunds { balance } => format!("Insufficient funds: balance is {}", balance),
+        TransferFromError::InsufficientAllowance { allowance } => {
+            format!("Insufficient allowance: approve this canister first (current allowance {})", allowance)
+        }
+        TransferFromError::BadFee { expected_fee } => format!("Bad fee: expected {}", expected_fee),
+        TransferFromError::TemporarilyUnavailable => "Ledger is temporarily unavailable".to_string(),
+        TransferFromError::GenericError { message, .. } => message,
+        error => format!("{:?}", error),
+    }
+}
+
+pub fn describe_transfer_error(error: TransferError) -> String {
+    match error {
+        TransferError::InsufficientFunds { balance } => format!("Insufficient funds: balance is {}", balance),
+        TransferError::BadFee { expected_fee } => format!("Bad fee: expected {}", expected_fee),
+        TransferError::TemporarilyUnavailable => "Ledger is temporarily unavailable".to_string(),
+        TransferError::GenericError { message, .. } => message,
+        error => format!("{:?}", error),
+    }
+}
+
+// Memo for a transfer made on behalf of a record, such as a payment or a
+// proposal, truncated to what the ledger accepts
+pub fn memo_for(id: &str) -> Vec<u8> {
+    id.bytes().take(MAX_MEMO_BYTES).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memos_fit_the_ledger_limit() {
+        assert_eq!(memo_for("payment_42"), b"payment_42".to_vec());
+        assert_eq!(memo_for(&"x".repeat(40)).len(), MAX_MEMO_BYTES);
+    }
+
+    #[test]
+    fn transfer_errors_are_readable() {
+        let error = TransferError::InsufficientFunds { balance: Nat::from(5u64) };
+        assert_eq!(describe_transfer_error(error), "Insufficient funds: balance is 5");
+        let error = TransferFromError::GenericError { error_code: Nat::from(1u64), message: "nope".to_string() };
+        assert_eq!(describe_transfer_from_error(error), "nope");
+    }
+}
@@ -0,0 +1,5 @@
+// Helpers shared by every canister in the workspace
+pub mod freeze;
+pub mod icrc;
+pub mod pagination;
+pub mod stable;
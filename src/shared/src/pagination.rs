@@ -0,0 +1,110 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+pub const DEFAULT_PAGE_SIZE: u32 = 20;
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+// One page of a list query; pass next_cursor back to fetch the following page
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+// Returned for cursors that were not produced by encode_cursor. Each canister
+// converts it into its own ApiError.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvalidCursor;
+
+// Cursors are the hex-encoded sort key of the last item on the previous page
+pub fn encode_cursor(key: &str) -> String {
+    key.bytes().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn decode_cursor(cursor: &str) -> Result<String, InvalidCursor> {
+    if !cursor.len().is_multiple_of(2) {
+        return Err(InvalidCursor);
+    }
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| cursor.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or(InvalidCursor)?;
+    String::from_utf8(bytes).map_err(|_| InvalidCursor)
+}
+
+// Returns the items whose key sorts after the cursor, in ascending key order.
+// Keys must be unique; numeric parts are zero-padded so they sort as text.
+pub fn paginate<'a, T: Clone + 'a, E: From<InvalidCursor>>(
+    items: impl Iterator<Item = &'a T>,
+    key: impl Fn(&T) -> String,
+    cursor: Option<String>,
+    limit: Option<u32>,
+) -> Result<Page<T>, E> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE) as usize;
+    let after = cursor.as_deref().map(decode_cursor).transpose()?;
+
+    let mut keyed: Vec<(String, &T)> = items
+        .map(|item| (key(item), item))
+        .filter(|(item_key, _)| after.as_ref().map(|after| item_key > after).unwrap_or(true))
+        .collect();
+    keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let next_cursor = if keyed.len() > limit {
+        Some(encode_cursor(&keyed[limit - 1].0))
+    } else {
+        None
+    };
+    Ok(Page {
+        items: keyed.into_iter().take(limit).map(|(_, item)| item.clone()).collect(),
+        next_cursor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(items: &[u32], cursor: Option<String>, limit: Option<u32>) -> Page<u32> {
+        paginate::<_, InvalidCursor>(items.iter(), |item| format!("{:010}", item), cursor, limit).unwrap()
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let key = "00000000000000000042:course_7";
+        assert_eq!(decode_cursor(&encode_cursor(key)), Ok(key.to_string()));
+        assert_eq!(decode_cursor("abc"), Err(InvalidCursor));
+        assert_eq!(decode_cursor("zz"), Err(InvalidCursor));
+    }
+
+    #[test]
+    fn pages_follow_key_order() {
+        let items = [5, 1, 4, 2, 3];
+        let first = page(&items, None, Some(2));
+        assert_eq!(first.items, vec![1, 2]);
+
+        let second = page(&items, first.next_cursor, Some(2));
+        assert_eq!(second.items, vec![3, 4]);
+
+        let last = page(&items, second.next_cursor, Some(2));
+        assert_eq!(last.items, vec![5]);
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn limit_is_clamped() {
+        let items: Vec<u32> = (0..150).collect();
+        assert_eq!(page(&items, None, Some(0)).items.len(), 1);
+        assert_eq!(page(&items, None, None).items.len(), DEFAULT_PAGE_SIZE as usize);
+        assert_eq!(page(&items, None, Some(1000)).items.len(), MAX_PAGE_SIZE as usize);
+    }
+}
@@ -0,0 +1,20 @@
+use candid::CandidType;
+use serde::de::DeserializeOwned;
+
+// The heap is wiped on upgrade, so each canister writes its storage to stable
+// memory in pre_upgrade and reads it back in post_upgrade
+
+pub fn save<T: CandidType>(state: T) {
+    ic_cdk::storage::stable_save((state,)).expect("failed to save state to stable memory");
+}
+
+// Canisters installed before their state was kept in stable memory have
+// nothing to read back, and start again from the default state
+pub fn restore<T: CandidType + DeserializeOwned>() -> Option<T> {
+    if ic_cdk::api::stable::stable_size() == 0 {
+        return None;
+    }
+
+    let (state,): (T,) = ic_cdk::storage::stable_restore().expect("failed to restore state from stable memory");
+    Some(state)
+}
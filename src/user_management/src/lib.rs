@@ -1,9 +1,11 @@
 use candid::{candid_method, CandidType, Deserialize, Principal};
-use ic_cdk::api::time;
-use ic_cdk::{caller, export_candid, query, update};
+use ic_cdk::{caller, export_candid, post_upgrade, pre_upgrade, query, update};
 use serde::Serialize;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use shared::freeze;
+use shared::pagination::{paginate, InvalidCursor, Page};
+use shared::stable;
 
 // Simple types for the demo
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
@@ -36,6 +38,28 @@ pub struct User {
     pub reputation_score: u32,
     pub skills: Vec<String>,
     pub achievements: Vec<Achievement>,
+    pub account_status: AccountStatus,
+    pub warnings: u32,
+    pub profile_hidden: bool,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum AccountStatus {
+    Active,
+    Suspended { until: u64, reason: String },
+    // A ban without an expiry is permanent
+    Banned { until: Option<u64>, reason: String },
+}
+
+// Account-level moderation actions requested by a trusted platform canister
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub enum UserModeration {
+    HideProfile,
+    RestoreProfile,
+    Warn,
+    RemoveWarning,
+    Suspend { until: u64, reason: String },
+    Reinstate,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
@@ -54,20 +78,75 @@ pub enum ApiError {
     InvalidInput(String),
     AlreadyExists(String),
     InsufficientPermissions,
+    AccountRestricted(AccountStatus),
+    Frozen { until: u64 }, // governance guardians froze the canister's mutating endpoints
 }
 
 pub type Result<T> = std::result::Result<T, ApiError>;
 
+impl From<InvalidCursor> for ApiError {
+    fn from(_: InvalidCursor) -> Self {
+        ApiError::InvalidInput("Invalid cursor".to_string())
+    }
+}
+
 // Simple in-memory storage for demo purposes
 thread_local! {
     static USERS: RefCell<HashMap<Principal, User>> = RefCell::new(HashMap::new());
     static USERNAME_TO_ID: RefCell<HashMap<String, Principal>> = RefCell::new(HashMap::new());
     static EMAIL_TO_ID: RefCell<HashMap<String, Principal>> = RefCell::new(HashMap::new());
+    static TRUSTED_CANISTERS: RefCell<Vec<Principal>> = const { RefCell::new(Vec::new()) };
+}
+
+// Stable Memory Functions
+
+// Everything kept across upgrades
+#[derive(CandidType, Deserialize)]
+struct StableState {
+    users: HashMap<Principal, User>,
+    username_to_id: HashMap<String, Principal>,
+    email_to_id: HashMap<String, Principal>,
+    trusted_canisters: Vec<Principal>,
+    frozen_until: Option<u64>,
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let state = StableState {
+        users: USERS.with(RefCell::take),
+        username_to_id: USERNAME_TO_ID.with(RefCell::take),
+        email_to_id: EMAIL_TO_ID.with(RefCell::take),
+        trusted_canisters: TRUSTED_CANISTERS.with(RefCell::take),
+        frozen_until: freeze::frozen_until(get_current_time()),
+    };
+    stable::save(state);
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    let Some(state) = stable::restore::<StableState>() else { return };
+    USERS.with(|users| *users.borrow_mut() = state.users);
+    USERNAME_TO_ID.with(|usernames| *usernames.borrow_mut() = state.username_to_id);
+    EMAIL_TO_ID.with(|emails| *emails.borrow_mut() = state.email_to_id);
+    TRUSTED_CANISTERS.with(|canisters| *canisters.borrow_mut() = state.trusted_canisters);
+    freeze::restore(state.frozen_until);
 }
 
 // Helper functions
+#[cfg(not(test))]
 fn get_current_time() -> u64 {
-    time()
+    ic_cdk::api::time()
+}
+
+// Unit tests run outside a canister, where there is no system clock
+#[cfg(test)]
+fn get_current_time() -> u64 {
+    tests::now()
+}
+
+// Users are listed in the order they joined
+fn user_page_key(user: &User) -> String {
+    format!("{:020}:{}", user.created_at, user.id)
 }
 
 fn is_valid_email(email: &str) -> bool {
@@ -78,11 +157,51 @@ fn is_valid_username(username: &str) -> bool {
     username.len() >= 3 && username.len() <= 50 && username.chars().all(|c| c.is_alphanumeric() || c == '_')
 }
 
+// Hidden profiles only show their bio and avatar to the owner
+fn profile_view(mut user: User, viewer_id: &Principal) -> User {
+    if user.profile_hidden && user.id != *viewer_id {
+        user.bio = None;
+        user.avatar_url = None;
+    }
+    user.account_status = effective_status(&user.account_status);
+    user
+}
+
+// Suspensions and bans lapse on their own once their expiry has passed
+fn effective_status(status: &AccountStatus) -> AccountStatus {
+    let current_time = get_current_time();
+    match status {
+        AccountStatus::Suspended { until, .. } if *until <= current_time => AccountStatus::Active,
+        AccountStatus::Banned { until: Some(until), .. } if *until <= current_time => AccountStatus::Active,
+        status => status.clone(),
+    }
+}
+
+fn account_status_of(user_id: &Principal) -> AccountStatus {
+    USERS.with(|users| {
+        users.borrow().get(user_id)
+            .map(|user| effective_status(&user.account_status))
+            .unwrap_or(AccountStatus::Active)
+    })
+}
+
+fn ensure_account_active(user_id: &Principal) -> Result<()> {
+    match account_status_of(user_id) {
+        AccountStatus::Active => Ok(()),
+        status => Err(ApiError::AccountRestricted(status)),
+    }
+}
+
+fn is_trusted_canister(principal: &Principal) -> bool {
+    TRUSTED_CANISTERS.with(|trusted| trusted.borrow().contains(principal))
+}
+
 // User Management Functions
 
 #[update]
 #[candid_method(update)]
 async fn create_user(request: CreateUserRequest) -> Result<User> {
+    ensure_not_frozen()?;
     let caller_id = caller();
     
     // Validate input
@@ -128,6 +247,9 @@ async fn create_user(request: CreateUserRequest) -> Result<User> {
         reputation_score: 0,
         skills: request.skills,
         achievements: vec![],
+        account_status: AccountStatus::Active,
+        warnings: 0,
+        profile_hidden: false,
     };
 
     // Store user and mappings
@@ -143,6 +265,7 @@ async fn create_user(request: CreateUserRequest) -> Result<User> {
 fn get_user(user_id: Principal) -> Result<User> {
     USERS.with(|users| {
         users.borrow().get(&user_id).cloned()
+            .map(|user| profile_view(user, &caller()))
             .ok_or_else(|| ApiError::NotFound("User not found".to_string()))
     })
 }
@@ -172,8 +295,10 @@ async fn update_user_profile(
     avatar_url: Option<String>,
     skills: Vec<String>
 ) -> Result<User> {
+    ensure_not_frozen()?;
     let caller_id = caller();
-    
+    ensure_account_active(&caller_id)?;
+
     USERS.with(|users| {
         let mut users = users.borrow_mut();
         match users.get(&caller_id).cloned() {
@@ -194,13 +319,17 @@ async fn update_user_profile(
 #[update]
 #[candid_method(update)]
 async fn update_user_role(user_id: Principal, new_role: UserRole) -> Result<User> {
+    ensure_not_frozen()?;
     let caller_id = caller();
     
-    // Check if caller has admin privileges
-    let caller_user = get_user(caller_id)?;
-    match caller_user.role {
-        UserRole::Admin => {},
-        _ => return Err(ApiError::InsufficientPermissions),
+    // Check if caller has admin privileges. Controllers can assign roles too,
+    // which is how the first admin is appointed.
+    if !ic_cdk::api::is_controller(&caller_id) {
+        let caller_user = get_user(caller_id)?;
+        match caller_user.role {
+            UserRole::Admin => {},
+            _ => return Err(ApiError::InsufficientPermissions),
+        }
     }
 
     USERS.with(|users| {
@@ -221,6 +350,7 @@ async fn update_user_role(user_id: Principal, new_role: UserRole) -> Result<User
 #[update]
 #[candid_method(update)]
 async fn add_achievement(user_id: Principal, achievement: Achievement) -> Result<User> {
+    ensure_not_frozen()?;
     let caller_id = caller();
     
     // Check if caller has permission to add achievements (admin or instructor)
@@ -248,6 +378,7 @@ async fn add_achievement(user_id: Principal, achievement: Achievement) -> Result
 #[update]
 #[candid_method(update)]
 async fn update_reputation_score(user_id: Principal, score_delta: i32) -> Result<User> {
+    ensure_not_frozen()?;
     let caller_id = caller();
     
     // Check if caller has permission (admin or system)
@@ -278,19 +409,17 @@ async fn update_reputation_score(user_id: Principal, score_delta: i32) -> Result
 
 #[query]
 #[candid_method(query)]
-fn get_users_by_role(role: UserRole) -> Vec<User> {
-    USERS.with(|users| {
-        users.borrow()
-            .iter()
-            .filter_map(|(_, user)| {
-                if user.role == role {
-                    Some(user.clone())
-                } else {
-                    None
-                }
-            })
-            .collect()
-    })
+fn get_users_by_role(role: UserRole, cursor: Option<String>, limit: Option<u32>) -> Result<Page<User>> {
+    let caller_id = caller();
+    let page = USERS.with(|users| {
+        paginate::<_, ApiError>(
+            users.borrow().values().filter(|user| user.role == role),
+            user_page_key,
+            cursor,
+            limit,
+        )
+    })?;
+    Ok(page.map(|user| profile_view(user, &caller_id)))
 }
 
 #[query]
@@ -301,42 +430,40 @@ fn get_user_count() -> u64 {
 
 #[query]
 #[candid_method(query)]
-fn search_users(query: String, limit: Option<u32>) -> Vec<User> {
-    let limit = limit.unwrap_or(10).min(100) as usize;
+fn search_users(query: String, cursor: Option<String>, limit: Option<u32>) -> Result<Page<User>> {
     let query_lower = query.to_lowercase();
-    
-    USERS.with(|users| {
-        users.borrow()
-            .iter()
-            .filter_map(|(_, user)| {
-                if user.username.to_lowercase().contains(&query_lower) ||
-                   user.full_name.to_lowercase().contains(&query_lower) ||
-                   user.skills.iter().any(|skill| skill.to_lowercase().contains(&query_lower)) {
-                    Some(user.clone())
-                } else {
-                    None
-                }
-            })
-            .take(limit)
-            .collect()
-    })
+    let caller_id = caller();
+
+    let page = USERS.with(|users| {
+        paginate::<_, ApiError>(
+            users.borrow().values().filter(|user| {
+                user.username.to_lowercase().contains(&query_lower) ||
+                user.full_name.to_lowercase().contains(&query_lower) ||
+                user.skills.iter().any(|skill| skill.to_lowercase().contains(&query_lower))
+            }),
+            user_page_key,
+            cursor,
+            limit,
+        )
+    })?;
+    Ok(page.map(|user| profile_view(user, &caller_id)))
 }
 
 #[query]
 #[candid_method(query)]
-fn get_leaderboard(limit: Option<u32>) -> Vec<User> {
-    let limit = limit.unwrap_or(10).min(100) as usize;
-    
-    USERS.with(|users| {
-        let mut user_list: Vec<User> = users.borrow()
-            .iter()
-            .map(|(_, user)| user.clone())
-            .collect();
-        
-        user_list.sort_by(|a, b| b.reputation_score.cmp(&a.reputation_score));
-        user_list.truncate(limit);
-        user_list
-    })
+fn get_leaderboard(cursor: Option<String>, limit: Option<u32>) -> Result<Page<User>> {
+    let caller_id = caller();
+
+    // Highest reputation first
+    let page = USERS.with(|users| {
+        paginate::<_, ApiError>(
+            users.borrow().values(),
+            |user| format!("{:010}:{}", u32::MAX - user.reputation_score, user.id),
+            cursor,
+            limit,
+        )
+    })?;
+    Ok(page.map(|user| profile_view(user, &caller_id)))
 }
 
 // System functions
@@ -360,5 +487,248 @@ fn is_instructor(user_id: Principal) -> bool {
     })
 }
 
+#[query]
+#[candid_method(query)]
+fn is_moderator(user_id: Principal) -> bool {
+    USERS.with(|users| {
+        users.borrow().get(&user_id)
+            .map(|user| matches!(user.role, UserRole::Moderator | UserRole::Admin))
+            .unwrap_or(false)
+    })
+}
+
+// Account status functions
+
+// Consulted by the other platform canisters before accepting enrollments,
+// discussion posts, votes and proposals
+#[query]
+#[candid_method(query)]
+fn get_account_status(user_id: Principal) -> AccountStatus {
+    account_status_of(&user_id)
+}
+
+// Registered users who are neither suspended nor banned; governance sizes
+// quorums from their voting power a page at a time
+#[query]
+#[candid_method(query)]
+fn get_active_users(cursor: Option<String>, limit: Option<u32>) -> Result<Page<Principal>> {
+    accounts_page(|status| status == AccountStatus::Active, cursor, limit)
+}
+
+// Suspended and banned users; governance leaves those among its delegators
+// out of a tally
+#[query]
+#[candid_method(query)]
+fn get_restricted_accounts(cursor: Option<String>, limit: Option<u32>) -> Result<Page<Principal>> {
+    accounts_page(|status| status != AccountStatus::Active, cursor, limit)
+}
+
+fn accounts_page(
+    include: impl Fn(AccountStatus) -> bool,
+    cursor: Option<String>,
+    limit: Option<u32>,
+) -> Result<Page<Principal>> {
+    let page = USERS.with(|users| {
+        paginate::<_, ApiError>(
+            users.borrow().values().filter(|user| include(effective_status(&user.account_status))),
+            user_page_key,
+            cursor,
+            limit,
+        )
+    })?;
+    Ok(page.map(|user| user.id))
+}
+
+#[update]
+#[candid_method(update)]
+async fn set_account_status(user_id: Principal, status: AccountStatus) -> Result<User> {
+    ensure_not_frozen()?;
+    let caller_id = caller();
+
+    // Check if caller has admin privileges
+    let caller_user = get_user(caller_id)?;
+    match caller_user.role {
+        UserRole::Admin => {},
+        _ => return Err(ApiError::InsufficientPermissions),
+    }
+    ensure_account_active(&caller_id)?;
+
+    if user_id == caller_id {
+        return Err(ApiError::InvalidInput("Admins cannot change their own account status".to_string()));
+    }
+
+    let current_time = get_current_time();
+    match &status {
+        AccountStatus::Active => {},
+        AccountStatus::Suspended { until, reason } | AccountStatus::Banned { until: Some(until), reason } => {
+            if *until <= current_time {
+                return Err(ApiError::InvalidInput("Expiry must be in the future".to_string()));
+            }
+            if reason.trim().is_empty() {
+                return Err(ApiError::InvalidInput("A reason is required".to_string()));
+            }
+        }
+        AccountStatus::Banned { until: None, reason } => {
+            if reason.trim().is_empty() {
+                return Err(ApiError::InvalidInput("A reason is required".to_string()));
+            }
+        }
+    }
+
+    USERS.with(|users| {
+        let mut users = users.borrow_mut();
+        match users.get(&user_id).cloned() {
+            Some(mut user) => {
+                user.account_status = status;
+                user.updated_at = current_time;
+
+                users.insert(user_id, user.clone());
+                Ok(user)
+            }
+            None => Err(ApiError::NotFound("User not found".to_string()))
+        }
+    })
+}
+
+// Moderation functions
+
+// Lets a controller register the platform canisters (such as course_management)
+// that may apply moderation actions
+#[update]
+#[candid_method(update)]
+async fn set_trusted_canisters(canister_ids: Vec<Principal>) -> Result<bool> {
+    if !ic_cdk::api::is_controller(&caller()) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    TRUSTED_CANISTERS.with(|trusted| *trusted.borrow_mut() = canister_ids);
+    Ok(true)
+}
+
+#[update]
+#[candid_method(update)]
+async fn apply_moderation(user_id: Principal, action: UserModeration) -> Result<User> {
+    ensure_not_frozen()?;
+    if !is_trusted_canister(&caller()) {
+        return Err(ApiError::InsufficientPermissions);
+    }
+
+    USERS.with(|users| {
+        let mut users = users.borrow_mut();
+        match users.get(&user_id).cloned() {
+            Some(mut user) => {
+                match action {
+                    UserModeration::HideProfile => user.profile_hidden = true,
+                    UserModeration::RestoreProfile => user.profile_hidden = false,
+                    UserModeration::Warn => user.warnings += 1,
+                    UserModeration::RemoveWarning => user.warnings = user.warnings.saturating_sub(1),
+                    // Moderator suspensions never shorten or lift an admin ban
+                    UserModeration::Suspend { until, reason } => {
+                        if !matches!(effective_status(&user.account_status), AccountStatus::Banned { .. }) {
+                            user.account_status = AccountStatus::Suspended { until, reason };
+                        }
+                    }
+                    UserModeration::Reinstate => {
+                        if matches!(user.account_status, AccountStatus::Suspended { .. }) {
+                            user.account_status = AccountStatus::Active;
+                        }
+                    }
+                }
+                user.updated_at = get_current_time();
+
+                users.insert(user_id, user.clone());
+                Ok(user)
+            }
+            None => Err(ApiError::NotFound("User not found".to_string()))
+        }
+    })
+}
+
+// Freeze Functions
+
+fn ensure_not_frozen() -> Result<()> {
+    freeze::ensure_not_frozen(get_current_time()).map_err(|until| ApiError::Frozen { until })
+}
+
+#[update]
+#[candid_method(update)]
+fn set_frozen_until(frozen_until: Option<u64>) -> Result<bool> {
+    freeze::set_frozen_until(frozen_until).map_err(|_| ApiError::InsufficientPermissions)?;
+    Ok(true)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_frozen_until() -> Option<u64> {
+    freeze::frozen_until(get_current_time())
+}
+
 // Export candid interface
 export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    thread_local! {
+        static NOW: RefCell<u64> = const { RefCell::new(0) };
+    }
+
+    pub(super) fn now() -> u64 {
+        NOW.with(|now| *now.borrow())
+    }
+
+    fn set_now(time: u64) {
+        NOW.with(|now| *now.borrow_mut() = time);
+    }
+
+    fn store_user(id: Principal, account_status: AccountStatus) {
+        let user = User {
+            id,
+            username: id.to_text(),
+            email: format!("{}@example.com", id.to_text()),
+            full_name: "Learner".to_string(),
+            bio: None,
+            avatar_url: None,
+            role: UserRole::Student,
+            created_at: 0,
+            updated_at: 0,
+            reputation_score: 0,
+            skills: vec![],
+            achievements: vec![],
+            account_status,
+            warnings: 0,
+            profile_hidden: false,
+        };
+        USERS.with(|users| users.borrow_mut().insert(id, user));
+    }
+
+    #[test]
+    fn suspensions_lapse_at_their_expiry() {
+        let suspended = AccountStatus::Suspended { until: 100, reason: "Spam".to_string() };
+        set_now(99);
+        assert_eq!(effective_status(&suspended), suspended);
+        set_now(100);
+        assert_eq!(effective_status(&suspended), AccountStatus::Active);
+    }
+
+    #[test]
+    fn bans_without_an_expiry_are_permanent() {
+        let temporary = AccountStatus::Banned { until: Some(100), reason: "Abuse".to_string() };
+        let permanent = AccountStatus::Banned { until: None, reason: "Abuse".to_string() };
+        set_now(u64::MAX);
+        assert_eq!(effective_status(&temporary), AccountStatus::Active);
+        assert_eq!(effective_status(&permanent), permanent);
+    }
+
+    #[test]
+    fn restricted_accounts_are_rejected_until_they_lapse() {
+        let (suspended, unknown) = (Principal::from_slice(&[1]), Principal::from_slice(&[2]));
+        store_user(suspended, AccountStatus::Suspended { until: 100, reason: "Spam".to_string() });
+
+        assert!(matches!(ensure_account_active(&suspended), Err(ApiError::AccountRestricted(_))));
+        assert!(ensure_account_active(&unknown).is_ok());
+        set_now(100);
+        assert!(ensure_account_active(&suspended).is_ok());
+    }
+}